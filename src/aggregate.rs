@@ -0,0 +1,148 @@
+use std::rc::Rc;
+
+use crate::{
+    completion::{rule::Rule, subst::{Subst, Var}},
+    context::Context,
+    eval::{eval_generators, QueryError},
+    instance::Instance,
+    literal::{Decimal, Literal},
+    predicate::Predicate,
+    saturate::Carrier,
+    symbol_table::Names,
+    term::{Term, TermInner},
+};
+
+/// `#return`の集約 `sum[e: Emp | wrk!e = d] sal!e`
+/// `[...]`の変数を`#for`の代入ごとに列挙し、条件が真になる組について項の値を集める。
+#[derive(Clone, Debug)]
+pub struct Aggregate {
+    pub func: AggregateFn,
+    /// 集約する変数
+    pub vars: Context,
+    /// `|`の後の条件。`#where`と同じく真になる組だけを使う
+    pub wh: Vec<Predicate>,
+    /// 集約する値。countではNone
+    pub term: Option<TermInner>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AggregateFn {
+    Count,
+    Sum,
+    Min,
+    Max,
+}
+
+impl AggregateFn {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AggregateFn::Count => "count",
+            AggregateFn::Sum => "sum",
+            AggregateFn::Min => "min",
+            AggregateFn::Max => "max",
+        }
+    }
+}
+
+impl Aggregate {
+    /// 出力の生成元1つ(`#for`の代入`row`)について値を求める
+    /// 値がnullや値のないattrになる組があれば、集約の結果も決まらないのでエラーにする。
+    /// min/maxで組が一つもなければNone(attrの値を与えない)。
+    pub fn eval(
+        &self,
+        instance: &Instance,
+        rules: &Vec<Rule>,
+        carrier: &Carrier,
+        names: &Names,
+        attr: &str,
+        row: &Subst,
+    ) -> Result<Option<Literal>, QueryError> {
+        // 集約の変数は`#for`の同名の変数を隠す
+        let outer = Subst::new(
+            row.0
+                .iter()
+                .filter(|(var, _)| !matches!(var, Var::Id(vid) if self.vars.0.contains_key(vid)))
+                .map(|(var, t)| (var.clone(), t.clone()))
+                .collect(),
+        );
+        let wh = self.wh.iter().map(|pred| pred.substitute(&outer)).collect::<Vec<_>>();
+        let matches = eval_generators(instance, rules, carrier, std::slice::from_ref(&self.vars), &wh);
+
+        let Some(term) = &self.term else {
+            return Ok(Some(Literal::Int(matches.len() as i64)));
+        };
+        let term = Rc::new(term.clone()).substitute(&outer);
+        let values = matches
+            .iter()
+            .map(|subst| {
+                let value = Term {
+                    context: Rc::new(Context::default()),
+                    names: Rc::new(names.clone()),
+                    inner: term.substitute(subst),
+                }
+                .normalize_with(rules, &instance.schema.theory.builtins);
+                match value.inner.as_ref() {
+                    TermInner::Lit(lit) => Ok(lit.clone()),
+                    _ => Err(QueryError::UnknownAggregated {
+                        attr: attr.to_string(),
+                        row: crate::eval::assigns_name(names, subst),
+                        value: value.to_string(),
+                    }),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let not_aggregable = |lit: &Literal| QueryError::NotAggregable {
+            attr: attr.to_string(),
+            func: self.func.name().to_string(),
+            value: lit.to_string(),
+        };
+        match self.func {
+            AggregateFn::Count => Ok(Some(Literal::Int(values.len() as i64))),
+            AggregateFn::Sum => {
+                // すべてIntならIntのまま、Decが混ざればDecで足す
+                if values.iter().all(|lit| matches!(lit, Literal::Int(_))) {
+                    let mut total = 0i64;
+                    for lit in &values {
+                        let Literal::Int(i) = lit else { unreachable!() };
+                        total = total.checked_add(*i).ok_or_else(|| not_aggregable(lit))?;
+                    }
+                    return Ok(Some(Literal::Int(total)));
+                }
+                let mut total = Decimal::new(0, 0);
+                for lit in &values {
+                    let d = lit.as_decimal().ok_or_else(|| not_aggregable(lit))?;
+                    total = total.checked_add(&d).ok_or_else(|| not_aggregable(lit))?;
+                }
+                Ok(Some(Literal::Dec(total)))
+            }
+            AggregateFn::Min | AggregateFn::Max => {
+                let mut best: Option<&Literal> = None;
+                for lit in &values {
+                    let Some(current) = best else {
+                        if !matches!(lit, Literal::Str(_)) && lit.as_decimal().is_none() {
+                            return Err(not_aggregable(lit));
+                        }
+                        best = Some(lit);
+                        continue;
+                    };
+                    let ord = match (current, lit) {
+                        (Literal::Str(x), Literal::Str(y)) => y.cmp(x),
+                        _ => match (current.as_decimal(), lit.as_decimal()) {
+                            (Some(x), Some(y)) => y.cmp(&x),
+                            _ => return Err(not_aggregable(lit)),
+                        },
+                    };
+                    let better = match self.func {
+                        AggregateFn::Min => ord.is_lt(),
+                        _ => ord.is_gt(),
+                    };
+                    if better {
+                        best = Some(lit);
+                    }
+                }
+                Ok(best.cloned())
+            }
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::rc::Rc;
+
+use crate::external::{Diagnostic, ExternalFn};
+use crate::id::OperId;
+use crate::literal::Literal;
+use crate::term::TermInner;
+
+/// `#func lt: Int * Int -> Bool #builtin lt`のように宣言する組み込み演算
+/// 引数がすべてリテラルのときだけ評価する。
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Builtin {
+    True,
+    False,
+    Not,
+    And,
+    Or,
+    Add,
+    Sub,
+    Mul,
+    Lt,
+    Le,
+    Eq,
+    Concat,
+    Length,
+}
+
+const BUILTIN_NAMES: [(Builtin, &str); 13] = [
+    (Builtin::True, "true"),
+    (Builtin::False, "false"),
+    (Builtin::Not, "not"),
+    (Builtin::And, "and"),
+    (Builtin::Or, "or"),
+    (Builtin::Add, "add"),
+    (Builtin::Sub, "sub"),
+    (Builtin::Mul, "mul"),
+    (Builtin::Lt, "lt"),
+    (Builtin::Le, "le"),
+    (Builtin::Eq, "eq"),
+    (Builtin::Concat, "concat"),
+    (Builtin::Length, "length"),
+];
+
+impl std::fmt::Display for Builtin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (_, name) = BUILTIN_NAMES.iter().find(|(b, _)| b == self).unwrap();
+        write!(f, "{}", name)
+    }
+}
+
+impl std::str::FromStr for Builtin {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        BUILTIN_NAMES
+            .iter()
+            .find(|(_, name)| *name == s)
+            .map(|(b, _)| *b)
+            .ok_or_else(|| format!("unknown builtin: {}", s))
+    }
+}
+
+/// theoryの演算子と組み込み演算・外部関数の対応
+#[derive(Clone, Default)]
+pub struct Builtins {
+    pub ops: BTreeMap<OperId, Builtin>,
+    externals: BTreeMap<OperId, (String, Rc<dyn ExternalFn>)>,
+    // theoryを複製しても同じ場所に集める
+    diagnostics: Rc<RefCell<BTreeSet<Diagnostic>>>,
+}
+
+impl std::fmt::Debug for Builtins {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let externals = self
+            .externals
+            .iter()
+            .map(|(oid, (name, _))| (oid, name))
+            .collect::<BTreeMap<_, _>>();
+        f.debug_struct("Builtins")
+            .field("ops", &self.ops)
+            .field("externals", &externals)
+            .finish()
+    }
+}
+
+impl Builtins {
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty() && self.externals.is_empty()
+    }
+
+    pub fn register(&mut self, oid: OperId, name: &str, f: Rc<dyn ExternalFn>) {
+        self.externals.insert(oid, (name.to_string(), f));
+    }
+
+    /// これまでの評価で外部関数が返したエラーを取り出す
+    pub fn take_diagnostics(&self) -> Vec<Diagnostic> {
+        std::mem::take(&mut *self.diagnostics.borrow_mut())
+            .into_iter()
+            .collect()
+    }
+
+    /// 項の中の評価できる組み込み演算を内側から評価する
+    pub fn eval(&self, t: &Rc<TermInner>) -> Rc<TermInner> {
+        if self.is_empty() {
+            return t.clone();
+        }
+        let TermInner::Fun(oid, args) = t.as_ref() else {
+            return t.clone();
+        };
+        let args = args.iter().map(|arg| self.eval(arg)).collect::<Vec<_>>();
+        let evaluated = match (self.ops.get(oid), self.externals.get(oid)) {
+            (Some(b), _) => self.apply(*b, &args),
+            (None, Some((name, f))) => self.call(name, f.as_ref(), &args),
+            (None, None) => None,
+        };
+        evaluated
+            .map(Rc::new)
+            .unwrap_or_else(|| Rc::new(TermInner::Fun(oid.clone(), args)))
+    }
+
+    fn call(&self, name: &str, f: &dyn ExternalFn, args: &[Rc<TermInner>]) -> Option<TermInner> {
+        let args = args
+            .iter()
+            .map(|arg| self.as_literal(arg))
+            .collect::<Option<Vec<_>>>()?;
+        match f.call(&args) {
+            Ok(lit) => Some(TermInner::Lit(lit)),
+            Err(message) => {
+                self.diagnostics.borrow_mut().insert(Diagnostic {
+                    func: name.to_string(),
+                    args,
+                    message,
+                });
+                None
+            }
+        }
+    }
+
+    fn apply(&self, b: Builtin, args: &[Rc<TermInner>]) -> Option<TermInner> {
+        use Literal::{Bool, Dec, Int, Str};
+
+        // `#builtin true`/`#builtin false`の定数はBoolのリテラルとして扱う
+        let args = args
+            .iter()
+            .map(|arg| self.as_literal(arg))
+            .collect::<Option<Vec<_>>>()?;
+        let lit = match (b, args.as_slice()) {
+            (Builtin::True, []) => Bool(true),
+            (Builtin::False, []) => Bool(false),
+            (Builtin::Not, [Bool(x)]) => Bool(!x),
+            (Builtin::And, [Bool(x), Bool(y)]) => Bool(*x && *y),
+            (Builtin::Or, [Bool(x), Bool(y)]) => Bool(*x || *y),
+            (Builtin::Add, [Int(x), Int(y)]) => Int(x.checked_add(*y)?),
+            (Builtin::Sub, [Int(x), Int(y)]) => Int(x.checked_sub(*y)?),
+            (Builtin::Mul, [Int(x), Int(y)]) => Int(x.checked_mul(*y)?),
+            // IntとDecが混ざっていればDecで計算する
+            (Builtin::Add, [x, y]) => Dec(x.as_decimal()?.checked_add(&y.as_decimal()?)?),
+            (Builtin::Sub, [x, y]) => Dec(x.as_decimal()?.checked_sub(&y.as_decimal()?)?),
+            (Builtin::Mul, [x, y]) => Dec(x.as_decimal()?.checked_mul(&y.as_decimal()?)?),
+            (Builtin::Lt | Builtin::Le | Builtin::Eq, [Str(x), Str(y)]) => Bool(compare(b, x.cmp(y))),
+            (Builtin::Eq, [Bool(x), Bool(y)]) => Bool(x == y),
+            (Builtin::Lt | Builtin::Le | Builtin::Eq, [x, y]) => {
+                Bool(compare(b, x.as_decimal()?.cmp(&y.as_decimal()?)))
+            }
+            (Builtin::Concat, [Str(x), Str(y)]) => Str(format!("{}{}", x, y)),
+            (Builtin::Length, [Str(x)]) => Int(i64::try_from(x.chars().count()).ok()?),
+            _ => return None,
+        };
+        Some(TermInner::Lit(lit))
+    }
+
+    fn as_literal(&self, t: &TermInner) -> Option<Literal> {
+        match t {
+            TermInner::Lit(lit) => Some(lit.clone()),
+            TermInner::Fun(oid, args) if args.is_empty() => match self.ops.get(oid)? {
+                Builtin::True => Some(Literal::Bool(true)),
+                Builtin::False => Some(Literal::Bool(false)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+fn compare(b: Builtin, ord: std::cmp::Ordering) -> bool {
+    match b {
+        Builtin::Lt => ord.is_lt(),
+        Builtin::Le => ord.is_le(),
+        _ => ord.is_eq(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::rc::Rc;
+
+    use rstest::rstest;
+
+    use crate::builtin::{Builtin, Builtins};
+    use crate::id::OperId;
+    use crate::literal::{Decimal, Literal};
+    use crate::term::TermInner;
+
+    fn builtins() -> Builtins {
+        let map = [
+            Builtin::True,
+            Builtin::False,
+            Builtin::Not,
+            Builtin::And,
+            Builtin::Add,
+            Builtin::Sub,
+            Builtin::Lt,
+            Builtin::Le,
+            Builtin::Concat,
+            Builtin::Length,
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, b)| (OperId(i), b))
+        .collect::<BTreeMap<_, _>>();
+        Builtins {
+            ops: map,
+            ..Builtins::default()
+        }
+    }
+
+    fn fun(b: Builtin, args: Vec<Rc<TermInner>>) -> Rc<TermInner> {
+        let (oid, _) = builtins().ops.into_iter().find(|(_, x)| *x == b).unwrap();
+        Rc::new(TermInner::Fun(oid, args))
+    }
+
+    fn lit(l: Literal) -> Rc<TermInner> {
+        Rc::new(TermInner::Lit(l))
+    }
+
+    fn int(i: i64) -> Rc<TermInner> {
+        lit(Literal::Int(i))
+    }
+
+    fn dec(s: &str) -> Rc<TermInner> {
+        lit(Literal::Dec(s.parse::<Decimal>().unwrap()))
+    }
+
+    fn st(s: &str) -> Rc<TermInner> {
+        lit(Literal::Str(s.to_string()))
+    }
+
+    fn bool(b: bool) -> Rc<TermInner> {
+        lit(Literal::Bool(b))
+    }
+
+    #[rstest]
+    #[case(fun(Builtin::Add, vec![int(2), int(3)]), int(5))]
+    #[case(fun(Builtin::Sub, vec![int(1), int(2)]), int(-1))]
+    #[case(fun(Builtin::Add, vec![int(1), dec("0.25")]), dec("1.25"))]
+    #[case(fun(Builtin::Lt, vec![int(2), int(3)]), bool(true))]
+    #[case(fun(Builtin::Le, vec![dec("3.5"), int(2)]), bool(false))]
+    #[case(fun(Builtin::Lt, vec![st("abc"), st("abd")]), bool(true))]
+    #[case(fun(Builtin::Concat, vec![st("a"), st("b")]), st("ab"))]
+    #[case(fun(Builtin::Length, vec![st("abc")]), int(3))]
+    #[case(fun(Builtin::Not, vec![fun(Builtin::False, vec![])]), bool(true))]
+    #[case(fun(Builtin::And, vec![bool(true), fun(Builtin::True, vec![])]), bool(true))]
+    fn test_eval(#[case] t: Rc<TermInner>, #[case] expected: Rc<TermInner>) {
+        assert_eq!(builtins().eval(&t), expected);
+    }
+
+    #[test]
+    fn test_eval_nested() {
+        // and![lt![1 2] le![sub![3 1] 2]]
+        let sub = fun(Builtin::Sub, vec![int(3), int(1)]);
+        let t = fun(
+            Builtin::And,
+            vec![
+                fun(Builtin::Lt, vec![int(1), int(2)]),
+                fun(Builtin::Le, vec![sub, int(2)]),
+            ],
+        );
+        assert_eq!(builtins().eval(&t), bool(true));
+    }
+
+    #[test]
+    fn test_eval_leaves_non_literals() {
+        let t = fun(Builtin::Sub, vec![int(i64::MIN), int(1)]);
+        assert_eq!(builtins().eval(&t), t);
+        let t = fun(Builtin::Add, vec![Rc::new(TermInner::Var(crate::id::VarId(0))), int(2)]);
+        assert_eq!(builtins().eval(&t), t);
+        let t = fun(Builtin::Add, vec![st("a"), int(2)]);
+        assert_eq!(builtins().eval(&t), t);
+    }
+
+    #[test]
+    fn test_builtin_names() {
+        assert_eq!("concat".parse::<Builtin>(), Ok(Builtin::Concat));
+        assert_eq!(Builtin::Le.to_string(), "le");
+        assert!("pow".parse::<Builtin>().is_err());
+    }
+}
@@ -0,0 +1,313 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use crate::{
+    consistency::Consistency,
+    context::Context,
+    equation::Equation,
+    id::{GenId, Symbol},
+    instance::{replace_gens, Elem, Generator, Instance},
+    oper::Oper,
+    schema::Schema,
+    term::{Term, TermInner},
+    transform::{elements, Transform},
+};
+
+/// 余極限が作れない理由
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ColimitError {
+    /// schemaや、transformの元・行き先のinstanceが合わない
+    Mismatch { reason: String },
+    /// 同一視によって異なる定数が等しくなった
+    Collapsed { left: String, right: String },
+    /// 無矛盾性の検査が終わらず、定数がつぶれていないと言い切れない
+    Undecided { reason: String },
+}
+
+impl std::fmt::Display for ColimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ColimitError::Mismatch { reason } => write!(f, "{}", reason),
+            ColimitError::Collapsed { left, right } => {
+                write!(f, "the merge makes distinct constants equal: {} = {}", left, right)
+            }
+            ColimitError::Undecided { reason } => write!(f, "cannot decide whether the merge is consistent: {}", reason),
+        }
+    }
+}
+
+/// 2つのinstanceの余積と、それぞれからの埋め込み
+pub struct Coproduct {
+    /// 名前は`i+hr`
+    pub name: String,
+    pub instance: Instance,
+    pub left: Transform,
+    pub right: Transform,
+}
+
+/// 余積: 2つのinstanceの生成元とデータを並べる
+/// 右の生成元・nullの名前が左と重なれば、右のinstanceの名前を付けて`hr.e1`・`?hr.x`に改名する。
+pub fn coproduct(left: (&str, &Instance), right: (&str, &Instance)) -> Result<Coproduct, ColimitError> {
+    let ((left_name, l), (right_name, r)) = (left, right);
+    if !same_schema(&l.schema, &r.schema) {
+        return Err(ColimitError::Mismatch {
+            reason: format!("{} and {} are not instances of the same schema", left_name, right_name),
+        });
+    }
+    let name = format!("{}+{}", left_name, right_name);
+    let mut names = l.names.as_ref().clone();
+    names.extend(r.names.as_ref().clone());
+    let mut next_gen = names.next_gen_id();
+
+    let mut elems = l.elems.iter().filter(|e| !matches!(e, Elem::Subst(_))).cloned().collect::<Vec<_>>();
+    let mut rename = BTreeMap::new();
+    for elem in &r.elems {
+        let (Elem::Gen(g) | Elem::Null(g)) = elem else {
+            continue;
+        };
+        let renamed = if elements(l).any(|h| h.id == g.id || h.name == g.name) {
+            let gid = GenId(next_gen);
+            next_gen += 1;
+            let name = match g.name.strip_prefix('?') {
+                Some(null) => format!("?{}.{}", right_name, null),
+                None => format!("{}.{}", right_name, g.name),
+            };
+            names.insert(name.clone(), Symbol::Gen(gid.clone()));
+            Generator::new(gid, name, g.entity.clone())
+        } else {
+            g.clone()
+        };
+        rename.insert(g.id.clone(), Rc::new(TermInner::Gen(renamed.id.clone())));
+        elems.push(match elem {
+            Elem::Null(_) => Elem::Null(renamed),
+            _ => Elem::Gen(renamed),
+        });
+    }
+
+    // 他のinstanceの表から来た同じ名前のnullに負けないよう、並べた生成元の名前を付け直す
+    for elem in &elems {
+        if let Elem::Gen(g) | Elem::Null(g) = elem {
+            names.insert(g.name.clone(), Symbol::Gen(g.id.clone()));
+        }
+    }
+    let names = Rc::new(names);
+    let data = l
+        .data
+        .iter()
+        .map(|eq| (eq.left.clone(), eq.right.clone()))
+        .chain(r.data.iter().map(|eq| (replace_gens(&eq.left, &rename), replace_gens(&eq.right, &rename))))
+        .map(|(left, right)| Equation { context: Rc::new(Context::default()), names: names.clone(), left, right })
+        .collect();
+    let instance = Instance { names: names.clone(), schema: l.schema.clone(), elems, data, lines: BTreeMap::new() };
+
+    let injection = |inj: &str, source_name: &str, source: &Instance, gens: BTreeMap<GenId, Rc<TermInner>>| Transform {
+        name: inj.to_string(),
+        source_name: source_name.to_string(),
+        target_name: name.clone(),
+        source: source.clone(),
+        target: instance.clone(),
+        gens,
+        names: names.clone(),
+    };
+    let identity = elements(l).map(|g| (g.id.clone(), Rc::new(TermInner::Gen(g.id.clone())))).collect();
+    let left = injection("inl", left_name, l, identity);
+    let right = injection("inr", right_name, r, rename);
+    Ok(Coproduct { name, instance, left, right })
+}
+
+/// 余等化: 同じinstanceへの2つのtransform f, g: X -> Yについて、Xの生成元ごとにf(x) = g(x)を加えたYの商
+/// 等しくなった生成元はsaturateで同一視し、値の分かったnullは値に置き換える。
+pub fn coequalizer(f: &Transform, g: &Transform) -> Result<Instance, ColimitError> {
+    if !same_instance(&f.source, &g.source) || !same_instance(&f.target, &g.target) {
+        return Err(ColimitError::Mismatch {
+            reason: format!(
+                "{}: {} -> {} and {}: {} -> {} are not parallel",
+                f.name, f.source_name, f.target_name, g.name, g.source_name, g.target_name
+            ),
+        });
+    }
+    let mut names = f.target.names.as_ref().clone();
+    names.extend(f.names.as_ref().clone());
+    names.extend(g.names.as_ref().clone());
+    for x in elements(&f.target) {
+        names.insert(x.name.clone(), Symbol::Gen(x.id.clone()));
+    }
+    let names = Rc::new(names);
+
+    let mut instance = f.target.clone();
+    instance.names = names.clone();
+    for x in elements(&f.source) {
+        let x = TermInner::Gen(x.id.clone());
+        let (left, right) = (f.apply(&x), g.apply(&x));
+        if left == right {
+            continue;
+        }
+        // 生成元と項の等式は、項を左辺に置いて`#data`の形にする
+        let (left, right) = match left.as_ref() {
+            TermInner::Gen(_) => (right, left),
+            _ => (left, right),
+        };
+        instance.data.push(Equation { context: Rc::new(Context::default()), names: names.clone(), left, right });
+    }
+    quotient(instance)
+}
+
+/// 押し出し: f: X -> A, g: X -> Bについて、A + Bをf(x) = g(x)で割ったinstance
+pub fn pushout(f: &Transform, g: &Transform) -> Result<Instance, ColimitError> {
+    if !same_instance(&f.source, &g.source) {
+        return Err(ColimitError::Mismatch {
+            reason: format!("{} and {} start from different instances", f.name, g.name),
+        });
+    }
+    let coproduct = coproduct((&f.target_name, &f.target), (&g.target_name, &g.target))?;
+    let mismatch = |reason| ColimitError::Mismatch { reason };
+    let f = f.compose(&coproduct.left).map_err(mismatch)?;
+    let g = g.compose(&coproduct.right).map_err(mismatch)?;
+    coequalizer(&f, &g)
+}
+
+/// 加えた等式で生成元を同一視し、定数がつぶれていないか調べてデータを整える
+fn quotient(instance: Instance) -> Result<Instance, ColimitError> {
+    let mut instance = instance.saturate();
+    match instance.is_consistent() {
+        Consistency::Consistent => {}
+        Consistency::Inconsistent(inconsistency) => {
+            return Err(ColimitError::Collapsed { left: inconsistency.left, right: inconsistency.right });
+        }
+        Consistency::Undecided(incomplete) => return Err(ColimitError::Undecided { reason: incomplete.to_string() }),
+    }
+    // データは後で除きながら変えるが、schemaの規則は変わらないので完備化は1度だけにする
+    let schema_rules = instance.schema.rules().0;
+    let rules = instance.deduction_rules_from(&schema_rules);
+
+    // 値の分かったnullは値に置き換える
+    let values = instance
+        .nulls()
+        .filter_map(|null| {
+            let term = Term {
+                context: Rc::new(Context::default()),
+                names: instance.names.clone(),
+                inner: Rc::new(TermInner::Gen(null.id.clone())),
+            };
+            let normal = term.normalize_with(&rules, &instance.schema.theory.builtins).inner.clone();
+            (normal != term.inner).then(|| (null.id.clone(), normal))
+        })
+        .collect::<BTreeMap<_, _>>();
+    instance.elems.retain(|e| !matches!(e, Elem::Null(null) if values.contains_key(&null.id)));
+    let mut data: Vec<Equation> = vec![];
+    for eq in &instance.data {
+        let eq = Equation { left: replace_gens(&eq.left, &values), right: replace_gens(&eq.right, &values), ..eq.clone() };
+        if eq.left != eq.right && !data.contains(&eq) {
+            data.push(eq);
+        }
+    }
+
+    // `fkey!elem`/`attr!elem`の形でない等式は、残りのデータから導けるなら除く
+    let mut i = 0;
+    while i < data.len() {
+        let is_data = matches!(data[i].left.as_ref(), TermInner::Fun(_, args) if matches!(args.as_slice(), [arg] if matches!(arg.as_ref(), TermInner::Gen(_))));
+        let rest = Instance { data: [&data[..i], &data[i + 1..]].concat(), ..instance.clone() };
+        if !is_data && rest.deducible_with(&data[i], &rest.deduction_rules_from(&schema_rules)) {
+            data.remove(i);
+        } else {
+            i += 1;
+        }
+    }
+    instance.data = data;
+    Ok(instance)
+}
+
+/// 同じschemaの、生成元・nullとデータが同じinstance
+fn same_instance(i: &Instance, j: &Instance) -> bool {
+    let sides = |instance: &Instance| instance.data.iter().map(|eq| (eq.left.clone(), eq.right.clone())).collect::<Vec<_>>();
+    same_schema(&i.schema, &j.schema) && elements(i).eq(elements(j)) && sides(i) == sides(j)
+}
+
+fn same_schema(s: &Schema, t: &Schema) -> bool {
+    let ids = |opers: &Vec<Oper>| opers.iter().map(|op| op.id.clone()).collect::<Vec<_>>();
+    s.entities == t.entities && ids(&s.fkeys) == ids(&t.fkeys) && ids(&s.attrs) == ids(&t.attrs)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::{
+        colimit::{coequalizer, coproduct, pushout, ColimitError},
+        term::TermInner,
+        util::instance::{gen_names, value},
+        workspace::Workspace,
+    };
+
+    #[test]
+    fn test_coproduct() {
+        let ws = Workspace::new("example");
+        let (i, hr) = (ws.instance("i").unwrap(), ws.instance("hr").unwrap());
+        let sum = coproduct(("i", &i), ("hr", &hr)).unwrap();
+        assert_eq!(sum.name, "i+hr");
+        assert_eq!(gen_names(&sum.instance, "Emp").len(), 9);
+        assert_eq!(sum.instance.data.len(), i.data.len() + hr.data.len());
+        assert!(sum.left.check().is_empty());
+        assert!(sum.right.check().is_empty());
+
+        // 同じ名前の生成元とnullは、右のinstanceの名前を付けて区別する
+        let nulls = ws.instance("nulls").unwrap();
+        let twice = coproduct(("nulls", &nulls), ("j", &nulls)).unwrap();
+        assert_eq!(gen_names(&twice.instance, "Emp").len(), 14);
+        assert_eq!(value(&twice.instance, "last!j.e2").as_deref(), Some("\"Noether\""));
+        assert_eq!(value(&twice.instance, "sal!j.e7").as_deref(), Some("?j.x"));
+        assert!(twice.right.check().is_empty());
+    }
+
+    #[test]
+    fn test_pushout() {
+        let ws = Workspace::new("example");
+        let (toi, tohr) = (ws.transform("toi").unwrap(), ws.transform("tohr").unwrap());
+        let merged = pushout(&toi, &tohr).unwrap();
+        // e3とx17、その部署d1とh1が同一視される
+        assert_eq!(gen_names(&merged, "Emp"), ["e1", "e2", "e3", "e4", "e5", "e6", "e7", "x18"]);
+        assert_eq!(gen_names(&merged, "Dept"), ["d1", "d2", "d3"]);
+        assert_eq!(value(&merged, "mgr!x18").as_deref(), Some("e3"));
+        assert_eq!(value(&merged, "wrk!x18").as_deref(), Some("d1"));
+        assert!(merged.data.iter().all(|eq| eq.left != eq.right));
+        assert!(merged.enforce_constraints(crate::constraint::Repair::Reject).is_ok());
+    }
+
+    #[test]
+    fn test_pushout_rejects_collapse() {
+        let ws = Workspace::new("example");
+        let toi = ws.transform("toi").unwrap();
+        let mut tohr = ws.transform("tohr").unwrap();
+        // e3をx18と同一視すると、給与が300 = 280になる(名前も"Einstein" = "Curie"になる)
+        let x18 = tohr.target.names.gen_id("x18").unwrap();
+        let p = tohr.source.names.gen_id("p").unwrap();
+        tohr.gens.insert(p, Rc::new(TermInner::Gen(x18)));
+        let err = pushout(&toi, &tohr).unwrap_err();
+        assert_eq!(err, ColimitError::Collapsed { left: "280".to_string(), right: "300".to_string() });
+    }
+
+    #[test]
+    fn test_coequalizer_fills_nulls() {
+        let ws = Workspace::new("example");
+        let nulls = ws.instance("nulls").unwrap();
+        let update = ws.transform("update").unwrap();
+        // nullsの恒等写像と、?xを180に写すnullsへの写像を余等化すると、?xが180に決まる
+        let mut fill = update.clone();
+        fill.target_name = "nulls".to_string();
+        fill.target = nulls.clone();
+        let identity = crate::transform::Transform::identity("id", "nulls", &nulls);
+        let filled = coequalizer(&identity, &fill).unwrap();
+        assert!(filled.nulls().next().is_none());
+        assert_eq!(value(&filled, "sal!e7").as_deref(), Some("180"));
+
+        assert!(matches!(coequalizer(&identity, &update), Err(ColimitError::Mismatch { .. })));
+        // 名前が同じでも、行き先のinstanceが違えば平行でない
+        let mut renamed = update.clone();
+        renamed.target_name = "nulls".to_string();
+        assert!(matches!(coequalizer(&identity, &renamed), Err(ColimitError::Mismatch { .. })));
+        // 名前が違っても、同じinstanceなら平行
+        let mut aliased = fill.clone();
+        aliased.source_name = "alias".to_string();
+        assert!(coequalizer(&identity, &aliased).is_ok());
+    }
+}
@@ -31,12 +31,20 @@ pub fn analyse(
     if lpo_gr(left.clone(), right.clone()) {
         // left > right
         Some(Rule::new(context, names, left.clone(), right.clone()))
-    } else {
+    } else if lpo_gr(right.clone(), left.clone()) || (is_ground(&left) && is_ground(&right)) {
         // left < right
+        // 変数を含まない等式は、比べられなくても(異なるリテラルなど)右から左へ向ける
         Some(Rule::new(context, names, right.clone(), left.clone()))
+    } else {
+        // `x + y = y + x`のように比べられない等式は向き付けない
+        None
     }
 }
 
+fn is_ground(t: &TermInner) -> bool {
+    t.vars().is_empty()
+}
+
 // fn analyse_rpo(_t1: Rc<TermInner>, _t2: Rc<TermInner>) -> PartialOrdering {
 //     PartialOrdering::Incomparable
     // match (t1.as_ref(), t2.as_ref()) {
@@ -80,7 +88,7 @@ fn analyse_inner(t1: Rc<TermInner>, t2: Rc<TermInner>) -> Option<bool> {
                         Ordering::Equal => analyse_inner(args_f[0].clone(), args_g[0].clone()),
                     }
                 }
-                (TermInner::Int(_), TermInner::Fun(_, _)) => Some(false),
+                (TermInner::Lit(_), TermInner::Fun(_, _)) => Some(false),
                 // (TermInner::Var(_), TermInner::RuledVar(_, _, _)) => false,
                 // (TermInner::RuledVar(_,_,_), TermInner::Var(_)) => true,
                 _ => {
@@ -97,7 +105,7 @@ impl TermInner {
         // 含まれる関数の数
         match &self {
             &TermInner::Var(_) | TermInner::RuledVar(_, _, _) => 0,
-            &TermInner::Int(_) | TermInner::Str(_) => 1,
+            &TermInner::Lit(_) | TermInner::Gen(_) => 1,
             &TermInner::Fun(_, args) => 1 + args.iter().map(|inner| inner.size()).sum::<usize>(),
             &TermInner::Subst(_) => unimplemented!(),
         }
@@ -106,7 +114,7 @@ impl TermInner {
         // 含まれる変数の数
         match &self {
             &TermInner::Var(_) | TermInner::RuledVar(_, _, _) => 1,
-            &TermInner::Int(_) | TermInner::Str(_) => 0,
+            &TermInner::Lit(_) | TermInner::Gen(_) => 0,
             &TermInner::Fun(_, args) => {
                 1 + args.iter().map(|inner| inner.var_size()).sum::<usize>()
             }
@@ -122,7 +130,7 @@ fn lpo_gr(t1: Rc<TermInner>, t2: Rc<TermInner>) -> bool {
 fn lpo_gr_eq(t1: Rc<TermInner>, t2: Rc<TermInner>) -> bool {
     // println!("lpo_gr_eq: t1: {:?}, t2: {:?}", t1, t2);
     match (t1.as_ref(), t2.as_ref()) {
-        (t, TermInner::Var(xi)) => !occur(&Var::Id(xi.clone()), t),
+        (t, TermInner::Var(xi)) => occur(&Var::Id(xi.clone()), t),
         (t, TermInner::RuledVar(xi, rid, kind)) => occur(&Var::Ruled(xi.clone(), *rid, kind.clone()), t),
         (TermInner::Var(_), _) | (TermInner::RuledVar(_, _, _), _) => false,
         (TermInner::Fun(f1, args1), TermInner::Fun(f2, args2)) => {
@@ -137,14 +145,16 @@ fn lpo_gr_eq(t1: Rc<TermInner>, t2: Rc<TermInner>) -> bool {
             ) ||
             args1.iter().any(|arg1| lpo_gr_eq(arg1.clone(), t2.clone()))
         }
-        (TermInner::Fun(_, _), TermInner::Str(_)) => true,
-        (TermInner::Str(_), TermInner::Fun(_, _), ) => false,
-        (TermInner::Fun(_, _), TermInner::Int(_)) => true,
-        (TermInner::Int(_), TermInner::Fun(_, _), ) => false,
-        (TermInner::Str(_), TermInner::Str(_)) => false,  // 比較できないが。。
-        (TermInner::Int(_), TermInner::Int(_)) => false,  // 比較できないが。。
-        (TermInner::Str(_), TermInner::Int(_)) => false,  // 比較できないが。。
-        (TermInner::Int(_), TermInner::Str(_)) => false,  // 比較できないが。。
+        // 生成元は演算子より小さく、リテラルより大きい
+        (TermInner::Fun(_, _), TermInner::Gen(_)) => true,
+        (TermInner::Gen(_), TermInner::Fun(_, _)) => false,
+        (TermInner::Gen(g1), TermInner::Gen(g2)) => g1 >= g2,
+        (TermInner::Gen(_), TermInner::Lit(_)) => true,
+        (TermInner::Lit(_), TermInner::Gen(_)) => false,
+        (TermInner::Fun(_, _), TermInner::Lit(_)) => true,
+        (TermInner::Lit(_), TermInner::Fun(_, _), ) => false,
+        // 異なるリテラルは異なる値なので、sortによらず向き付けない
+        (TermInner::Lit(l1), TermInner::Lit(l2)) => l1 == l2,
         _ => {
             dbg!(&t1, &t2);
             unimplemented!();
@@ -157,21 +167,15 @@ fn occur(v: &Var, t: &TermInner) -> bool {
     t.vars().contains(&v.clone())
 }
 
+/// 引数を左から比べ、最初に異なる引数で大小を決める
 fn lex_gr_eq(gr_eq: fn(Rc<TermInner>, Rc<TermInner>) -> bool, ts1: Vec<Rc<TermInner>>, ts2: Vec<Rc<TermInner>>) -> bool {
-    match ts1.cmp(&ts2) {
-        Ordering::Greater => true,
-        Ordering::Less => false,
-        Ordering::Equal => {
-            for (x, y) in ts1.iter().zip(&ts2) {
-                if gr_eq(x.clone(), y.clone()) && !gr_eq(y.clone(), x.clone()) {
-                    return true;
-                } else if !(gr_eq(x.clone(), y.clone()) && gr_eq(y.clone(), x.clone())) {
-                    return false;
-                }
-            }
-            true
+    for (x, y) in ts1.iter().zip(&ts2) {
+        if x == y {
+            continue;
         }
+        return gr_eq(x.clone(), y.clone()) && !gr_eq(y.clone(), x.clone());
     }
+    ts1.len() >= ts2.len()
 }
 
 #[cfg(test)]
@@ -198,4 +202,22 @@ mod tests {
         let rule = analyse(equation.context, equation.names, equation.left, equation.right);
         println!("{}", rule.unwrap());
     }
+
+    #[rstest]
+    // 変数は、その変数を含む項より小さい
+    #[case("x: Int | m!m!x = x", Some("Rule< m!m!x -> x >"))]
+    #[case("x: Int | x = p![x o;]", Some("Rule< p![x o;] -> x >"))]
+    #[case("x y: Int | p![x m!y] = p![x y]", Some("Rule< p![x m!y] -> p![x y] >"))]
+    // 引数を入れ替えただけの等式は向き付けない
+    #[case("x y: Int | p![y x] = p![x y]", None)]
+    #[case("x y z: Int | p![x p![y z]] = p![y p![x z]]", None)]
+    fn test_analyse_orientation(#[case] input: &str, #[case] expected: Option<&str>) {
+        let types = types(vec!["Int"]);
+        let opers = opers(vec!["o", "p", "m"]);
+        let ctxts = CtxtTable::new();
+
+        let equation = eq(input, &types, &opers, &ctxts);
+        let rule = analyse(equation.context, equation.names, equation.left, equation.right);
+        assert_eq!(rule.map(|rule| rule.to_string()).as_deref(), expected);
+    }
 }
@@ -0,0 +1,192 @@
+use std::{collections::BTreeMap, rc::Rc};
+
+use crate::{
+    completion::rule::Rule,
+    context::Context,
+    equation::Equation,
+    id::OperId,
+    symbol_table::Names,
+    term::TermInner,
+};
+
+/// 変数を含まない等式(instanceの`#data`)を合同閉包で閉じ、各同値類の代表元へ書き換える規則にする
+/// 代表元は同値類の中で最も小さい項(大きさ、リテラル優先、項の順序の順)。
+/// 規則の左辺は定数か、引数がすべて代表元の`f(..)`なので、完備化と違って必ず止まり、合流する。
+pub fn ground_rules(eqs: &[Equation]) -> Vec<Rule> {
+    let Some(names) = eqs.first().map(|eq| eq.names.clone()) else {
+        return vec![];
+    };
+    let mut graph = EGraph::default();
+    let pairs = eqs
+        .iter()
+        .map(|eq| (graph.add(&eq.left), graph.add(&eq.right)))
+        .collect::<Vec<_>>();
+    for (l, r) in pairs {
+        graph.union(l, r);
+    }
+    graph.close();
+    graph.rules(names)
+}
+
+/// 項の節点。関数の引数は節点の番号で持つ
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Node {
+    Leaf(Rc<TermInner>),
+    Fun(OperId, Vec<usize>),
+}
+
+#[derive(Default)]
+struct EGraph {
+    nodes: Vec<Node>,
+    ids: BTreeMap<Node, usize>,
+    parents: Vec<usize>,
+}
+
+impl EGraph {
+    fn add(&mut self, t: &Rc<TermInner>) -> usize {
+        let node = match t.as_ref() {
+            TermInner::Fun(oid, args) => Node::Fun(oid.clone(), args.iter().map(|arg| self.add(arg)).collect()),
+            _ => Node::Leaf(t.clone()),
+        };
+        if let Some(id) = self.ids.get(&node) {
+            return *id;
+        }
+        let id = self.nodes.len();
+        self.nodes.push(node.clone());
+        self.ids.insert(node, id);
+        self.parents.push(id);
+        id
+    }
+
+    fn find(&self, mut id: usize) -> usize {
+        while self.parents[id] != id {
+            id = self.parents[id];
+        }
+        id
+    }
+
+    fn union(&mut self, a: usize, b: usize) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return false;
+        }
+        self.parents[a.max(b)] = a.min(b);
+        true
+    }
+
+    /// 引数が同じ同値類になった同じ演算子の節点を、変わらなくなるまで同一視する
+    fn close(&mut self) {
+        loop {
+            let mut signatures: BTreeMap<(OperId, Vec<usize>), usize> = BTreeMap::new();
+            let mut changed = false;
+            for id in 0..self.nodes.len() {
+                let Node::Fun(oid, args) = &self.nodes[id] else {
+                    continue;
+                };
+                let signature = (oid.clone(), args.iter().map(|arg| self.find(*arg)).collect());
+                match signatures.get(&signature) {
+                    Some(other) => {
+                        let other = *other;
+                        changed |= self.union(id, other);
+                    }
+                    None => {
+                        signatures.insert(signature, id);
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    /// 引数を同値類の代表元にした節点の項。代表元がまだない引数があれば`None`
+    fn flatten(&self, id: usize, reps: &BTreeMap<usize, Rc<TermInner>>) -> Option<Rc<TermInner>> {
+        match &self.nodes[id] {
+            Node::Leaf(t) => Some(t.clone()),
+            Node::Fun(oid, args) => {
+                let args = args.iter().map(|arg| reps.get(&self.find(*arg)).cloned()).collect::<Option<Vec<_>>>()?;
+                Some(Rc::new(TermInner::Fun(oid.clone(), args)))
+            }
+        }
+    }
+
+    /// 各同値類の代表元を、小さい項が見つからなくなるまで選び直す
+    fn representatives(&self) -> BTreeMap<usize, Rc<TermInner>> {
+        let mut reps: BTreeMap<usize, Rc<TermInner>> = BTreeMap::new();
+        loop {
+            let mut changed = false;
+            for id in 0..self.nodes.len() {
+                let Some(t) = self.flatten(id, &reps) else {
+                    continue;
+                };
+                let class = self.find(id);
+                if reps.get(&class).is_none_or(|rep| key(&t) < key(rep)) {
+                    reps.insert(class, t);
+                    changed = true;
+                }
+            }
+            if !changed {
+                return reps;
+            }
+        }
+    }
+
+    fn rules(&self, names: Rc<Names>) -> Vec<Rule> {
+        let reps = self.representatives();
+        let mut rules = BTreeMap::new();
+        for id in 0..self.nodes.len() {
+            let (Some(before), Some(after)) = (self.flatten(id, &reps), reps.get(&self.find(id))) else {
+                continue;
+            };
+            if &before != after {
+                rules.insert(before, after.clone());
+            }
+        }
+        rules
+            .into_iter()
+            .map(|(before, after)| Rule::new(Rc::new(Context::default()), names.clone(), before, after))
+            .collect()
+    }
+}
+
+/// 代表元を選ぶときの順序。小さい項、同じ大きさならリテラルを優先する
+fn key(t: &Rc<TermInner>) -> (usize, bool, Rc<TermInner>) {
+    (size(t), !matches!(t.as_ref(), TermInner::Lit(_)), t.clone())
+}
+
+fn size(t: &TermInner) -> usize {
+    match t {
+        TermInner::Fun(_, args) => 1 + args.iter().map(|arg| size(arg)).sum::<usize>(),
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::{completion::congruence::ground_rules, term::TermInner, workspace::Workspace};
+
+    #[test]
+    fn test_ground_rules() {
+        let ws = Workspace::new("example");
+        let i = ws.instance("nulls").unwrap();
+        let rules = ground_rules(&i.data);
+        let left = |t: &str| i.data.iter().find(|eq| eq.left_term().to_string() == t).unwrap().left_term();
+        let normal = |t: &str| left(t).normalize(&rules).to_string();
+        assert_eq!(normal("sal!e1"), "250");
+        assert_eq!(normal("sal!e7"), "?x");
+        assert_eq!(normal("mgr!e5"), "e1");
+
+        // 合同: mgr!e5 = e1から、sal!mgr!e5 = sal!e1 = 250
+        let TermInner::Fun(sal, _) = left("sal!e5").inner.as_ref().clone() else {
+            unreachable!()
+        };
+        let mut t = left("mgr!e5");
+        t.inner = Rc::new(TermInner::Fun(sal, vec![t.inner.clone()]));
+        assert_eq!(t.normalize(&rules).to_string(), "250");
+
+        assert!(ground_rules(&[]).is_empty());
+    }
+}
@@ -1,5 +1,5 @@
 use std::{
-    collections::{BinaryHeap, HashMap},
+    collections::{BinaryHeap, BTreeMap},
     rc::Rc,
 };
 
@@ -81,7 +81,7 @@ pub fn prepare_rule(rule: &Rule, idx: usize) -> Rule {
 
 impl Rule {
     pub fn make_vars_ruled(&self, kind: RuleKind) -> Rule {
-        let subst = HashMap::new();
+        let subst = BTreeMap::new();
         let subst = self.context.0.keys().fold(subst, |mut subst, v| {
             let var = Var::Id(v.clone());
             let ruled_var = TermInner::RuledVar(v.clone(), self.id.unwrap(), kind.clone());
@@ -1,4 +1,5 @@
 pub mod analyse;
+pub mod congruence;
 pub mod critical_pairs;
 pub mod overlap;
 pub mod renumber;
@@ -15,16 +16,63 @@ use crate::{
 #[allow(unused)]
 use crate::util::dispv;
 
+/// 規則がこれより増えたら、完備化が止まらないものとして打ち切る
+pub const MAX_RULES: usize = 100;
+
+/// schemaやtypesideの等式を完備化するときに打ち切るステップ数
+pub const COMPLETION_LIMIT: usize = 200;
+
+/// 完備化が最後まで終わらなかった理由
+#[derive(Debug, Clone, PartialEq)]
+pub enum Incomplete {
+    /// `limit`の手順を使い切った
+    Limit,
+    /// 規則が`MAX_RULES`を超えた
+    TooManyRules,
+    /// 向き付けられない等式(`x + y = y + x`など)が残った
+    Unorientable(Equation),
+}
+
+impl std::fmt::Display for Incomplete {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Incomplete::Limit => write!(f, "completion reached the step limit"),
+            Incomplete::TooManyRules => write!(f, "completion produced more than {} rules", MAX_RULES),
+            Incomplete::Unorientable(eq) => write!(f, "cannot orient {} = {}", eq.left_term(), eq.right_term()),
+        }
+    }
+}
+
+/// 完備化する。終わらなかったときは、そこまでに得られた規則を返す
 pub fn complete(eqs: Vec<Equation>, limit: usize) -> Vec<Rule> {
+    complete_partially(eqs, limit).0
+}
+
+/// 完備化する。最後まで終わったときだけ規則を返す
+pub fn try_complete(eqs: Vec<Equation>, limit: usize) -> Result<Vec<Rule>, Incomplete> {
+    match complete_partially(eqs, limit) {
+        (rules, None) => Ok(rules),
+        (_, Some(reason)) => Err(reason),
+    }
+}
+
+/// 完備化し、得られた規則と、終わらなかったときはその理由を返す
+pub fn complete_partially(eqs: Vec<Equation>, limit: usize) -> (Vec<Rule>, Option<Incomplete>) {
     let mut step = 1;
     let mut eqs = BinaryHeap::from(eqs);
     let mut rules = vec![];
 
-    while !eqs.is_empty() && (limit == 0 || step <= limit) {
+    while !eqs.is_empty() {
+        if limit != 0 && step > limit {
+            return (rules, Some(Incomplete::Limit));
+        }
         // dbg!(step);
         let eq = eqs.pop().unwrap();
         // println!("POPED: {}", &eq);
-        let (new_eqs, new_rules) = complete_inner(step, &eq, &mut rules);
+        // 向き付けられない等式(`x + y = y + x`など)が出たら、そこで打ち切る
+        let Some((new_eqs, new_rules)) = complete_inner(step, &eq, &rules) else {
+            return (rules, Some(Incomplete::Unorientable(eq)));
+        };
 
         eqs.extend(new_eqs);
         // disp_eq(&eqs);
@@ -41,25 +89,27 @@ pub fn complete(eqs: Vec<Equation>, limit: usize) -> Vec<Rule> {
         //     dispv("rules:", &rules);
         // }
         rules = new_rules;
-        
-        if rules.len() == 100 { panic!(); }
+
+        if rules.len() > MAX_RULES {
+            return (rules, Some(Incomplete::TooManyRules));
+        }
         step += 1;
         // println!();
     }
 
-    rules
+    (rules, None)
 }
 
-fn complete_inner(_step: usize, eq: &Equation, rules: &Vec<Rule>) -> (Vec<Equation>, Vec<Rule>) {
-    let mut rules = rules.clone();
+fn complete_inner(_step: usize, eq: &Equation, rules: &[Rule]) -> Option<(Vec<Equation>, Vec<Rule>)> {
+    let mut rules = rules.to_vec();
 
     let left = eq.left_term().normalize(&rules);
     let right = eq.right_term().normalize(&rules);
     // println!("left: {}  | right: {}", &left, &right);
 
     let mut new_eqs = vec![];
-    (left != right).then(|| {
-        let new_rule = analyse(eq.context.clone(), eq.names.clone(), left.inner.clone(), right.inner.clone()).unwrap();
+    if left != right {
+        let new_rule = analyse(eq.context.clone(), eq.names.clone(), left.inner.clone(), right.inner.clone())?;
         // println!("new_rule: {}", new_rule);
 
         // α→βと既存rules内のrule毎の危険対の集合を作る
@@ -101,9 +151,9 @@ fn complete_inner(_step: usize, eq: &Equation, rules: &Vec<Rule>) -> (Vec<Equati
         // dispv("new_eqs before:", &new_eqs);
 
         // eqs.extend(new_eqs);
-    });
+    }
 
-    (new_eqs, rules)
+    Some((new_eqs, rules))
 }
 
 fn dedup_eqs(eqs: &BinaryHeap<Equation>) -> BinaryHeap<Equation> {
@@ -180,7 +230,7 @@ pub fn eqs() -> Vec<Equation> {
 #[cfg(test)]
 pub mod tests {
     use crate::{
-        completion::complete,
+        completion::{complete, try_complete, Incomplete},
         context_table::CtxtTable,
         equation::Equation,
         util::{dispv, eq, opers, types},
@@ -193,6 +243,40 @@ pub mod tests {
         dispv("FINAL RULES:", &rules);
     }
 
+    #[test]
+    fn test_complete_is_deterministic() {
+        let show = |rules: Vec<crate::completion::rule::Rule>| {
+            rules.iter().map(|r| r.to_string()).collect::<Vec<_>>()
+        };
+        let first = show(complete(complete_eqs(), 21));
+        let second = show(complete(complete_eqs(), 21));
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_complete_stops_at_unorientable_equation() {
+        let types = types(vec!["Int"]);
+        let opers = opers(vec!["o", "p", "m"]);
+        let ctxts = CtxtTable::new();
+
+        // 可換性は向き付けられないので、規則にせずに打ち切る
+        let eqs = vec![eq("x: Int | p![o; x] = x", &types, &opers, &ctxts), eq("x y: Int | p![x y] = p![y x]", &types, &opers, &ctxts)];
+        let rules = complete(eqs.clone(), 0);
+        assert!(rules.iter().all(|rule| rule.to_string() != "Rule< p![y x] -> p![x y] >"));
+        assert!(matches!(try_complete(eqs, 0), Err(Incomplete::Unorientable(_))));
+    }
+
+    #[test]
+    fn test_try_complete() {
+        assert_eq!(try_complete(complete_eqs(), 3), Err(Incomplete::Limit));
+
+        let types = types(vec!["Int"]);
+        let opers = opers(vec!["o", "p", "m"]);
+        let ctxts = CtxtTable::new();
+        let eqs = vec![eq("x: Int | p![o; x] = x", &types, &opers, &ctxts), eq("x: Int | m!m!x = x", &types, &opers, &ctxts)];
+        assert_eq!(try_complete(eqs, 0).map(|rules| rules.len()), Ok(2));
+    }
+
     pub fn complete_eqs() -> Vec<Equation> {
         let types = types(vec!["Int"]);
         let opers = opers(vec!["o", "p", "m"]);
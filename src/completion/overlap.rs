@@ -149,7 +149,7 @@ mod tests {
         let mut names = ctxts.current_var_table();
         let oper_names = opers.current_table();
         names.extend(oper_names);
-        let mut c = std::collections::HashMap::new();
+        let mut c = std::collections::BTreeMap::new();
         c.insert(VarId(0), crate::r#type::Type::Unary(TypeId(0)));
         c.insert(VarId(1), crate::r#type::Type::Unary(TypeId(0)));
         c.insert(VarId(2), crate::r#type::Type::Unary(TypeId(0)));
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, rc::Rc};
+use std::{collections::BTreeMap, rc::Rc};
 
 use crate::{
     completion::critical_pairs::CriticalPair, equation::Equation, id::VarId, completion::rule::{Rule, RuleKind}, completion::subst::{Subst, Var}, term::{Term, TermInner}
@@ -7,7 +7,7 @@ use crate::{
 impl Term {
     /// 変数を採番し直す
     pub fn refresh_vars(&self) -> Term {
-        let subst = HashMap::new();
+        let subst = BTreeMap::new();
         let vars = self.vars();
         let subst = vars.iter().fold(subst, |mut subst, var| {
             let len = subst.len();
@@ -28,9 +28,9 @@ impl Term {
 impl CriticalPair {
     /// 変数を採番し直す
     pub fn refresh_vars(&self) -> CriticalPair {
-        let subst = HashMap::new();
+        let subst = BTreeMap::new();
         let (p_term, q_term) = (self.p_term(), self.q_term());
-        let mut vars = std::collections::HashSet::new();
+        let mut vars = std::collections::BTreeSet::new();
         vars.extend(p_term.vars());
         vars.extend(q_term.vars());
         let subst = vars.iter().fold(subst, |mut subst, var| {
@@ -53,9 +53,9 @@ impl CriticalPair {
 impl Equation {
     /// 変数を採番し直す
     pub fn refresh_vars(&self) -> Equation {
-        let subst = HashMap::new();
+        let subst = BTreeMap::new();
         let (left_term, right_term) = (self.left_term(), self.right_term());
-        let mut vars = std::collections::HashSet::new();
+        let mut vars = std::collections::BTreeSet::new();
         vars.extend(left_term.vars());
         vars.extend(right_term.vars());
         let subst1 = vars.iter().fold(subst, |mut subst, var| {
@@ -68,7 +68,7 @@ impl Equation {
         let left_ruled = left_term.substitute(&Subst(subst1.clone()));
         let right_ruled = right_term.substitute(&Subst(subst1.clone()));
 
-        let subst = HashMap::new();
+        let subst = BTreeMap::new();
         let subst2 = vars.iter().fold(subst, |mut subst, _var| {
             let len = subst.len();
             subst
@@ -90,7 +90,7 @@ impl Rule {
     /// 変数を採番し直す
     pub fn refresh_vars(&self) -> Rule {
         // dbg!(self);
-        let subst = HashMap::new();
+        let subst = BTreeMap::new();
         let vars = self.before().vars();
         let subst = vars.iter().fold(subst, |mut subst, var| {
             let len = subst.len();
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, rc::Rc};
+use std::{collections::BTreeMap, rc::Rc};
 
 use crate::{
     id::VarId,
@@ -24,8 +24,8 @@ impl std::fmt::Debug for Var {
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
-pub struct Subst(pub HashMap<Var, Rc<TermInner>>);
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Subst(pub BTreeMap<Var, Rc<TermInner>>);
 
 impl std::fmt::Display for Subst {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -37,21 +37,21 @@ impl std::fmt::Display for Subst {
 }
 
 impl Subst {
-    pub fn new(map: HashMap<Var, Rc<TermInner>>) -> Self {
+    pub fn new(map: BTreeMap<Var, Rc<TermInner>>) -> Self {
         map.into()
     }
     pub fn insert(&mut self, k: Var, v: Rc<TermInner>) {
         self.0.insert(k, v);
     }
 }
-impl Into<Subst> for HashMap<Var, Rc<TermInner>> {
-    fn into(self) -> Subst {
-        Subst(self)
+impl From<BTreeMap<Var, Rc<TermInner>>> for Subst {
+    fn from(map: BTreeMap<Var, Rc<TermInner>>) -> Self {
+        Subst(map)
     }
 }
 impl std::default::Default for Subst {
     fn default() -> Self {
-        HashMap::new().into()
+        BTreeMap::new().into()
     }
 }
 
@@ -66,32 +66,25 @@ impl Term {
 }
 
 impl TermInner {
+    /// 代入は同時に適用する（代入の列挙順によって結果が変わらないように）
     pub fn substitute(&self, subst: &Subst) -> Rc<TermInner> {
-        let mut result = Rc::new(self.clone());
-        for (var, term) in &subst.0 {
-            result = substitute_inner(result.clone(), var, term.clone());
-        }
-        result
-    }
-}
-
-/// innerの中の変数varをtermに置き換える
-pub fn substitute_inner(inner: Rc<TermInner>, var: &Var, term: Rc<TermInner>) -> Rc<TermInner> {
-    // println!("{:?} {:?}", inner, var);
-    match (inner.as_ref(), var) {
-        (TermInner::Var(vid), Var::Id(v)) if vid == v => term,
-        (TermInner::RuledVar(vid, rid, kind), Var::Ruled(v, r, k))
-            if vid == v && rid == r && kind == k =>
-        {
-            term
+        match self {
+            TermInner::Var(vid) => subst
+                .0
+                .get(&Var::Id(vid.clone()))
+                .cloned()
+                .unwrap_or_else(|| Rc::new(self.clone())),
+            TermInner::RuledVar(vid, rid, kind) => subst
+                .0
+                .get(&Var::Ruled(vid.clone(), *rid, kind.clone()))
+                .cloned()
+                .unwrap_or_else(|| Rc::new(self.clone())),
+            TermInner::Fun(oper_id, args) => Rc::new(TermInner::Fun(
+                oper_id.clone(),
+                args.iter().map(|arg| arg.substitute(subst)).collect(),
+            )),
+            _ => Rc::new(self.clone()),
         }
-        (TermInner::Fun(oper_id, args), _) => Rc::new(TermInner::Fun(
-            oper_id.clone(),
-            args.iter()
-                .map(|arg| substitute_inner(arg.clone(), var, term.clone()))
-                .collect(),
-        )),
-        _ => inner,
     }
 }
 
@@ -166,11 +159,23 @@ mod tests {
         println!("result: {:?}", term.inner.substitute(&Subst::from(subst)));
     }
 
+    #[test]
+    fn test_substitute_simultaneously() {
+        let types = types(vec!["Int"]);
+        let opers = opers(vec!["plus", "minus"]);
+        let ctxts = CtxtTable::new();
+        // xとyを入れ替える。1つずつ適用するとplus![x minus!x]になってしまう
+        let term = tm("x y: Int | plus![x minus!y]", &types, &opers, &ctxts);
+        let subst = Subst::from(vec![(0, VarId(1)), (1, VarId(0))]);
+        let swapped = tm("x y: Int | plus![y minus!x]", &types, &opers, &ctxts);
+        assert_eq!(term.inner.substitute(&subst), swapped.inner);
+    }
+
     #[rstest]
     #[case("x1")]
     #[case("g!x2")]
     fn test_substitute_inner(#[case] t: &str) {
-        use std::collections::HashMap;
+        use std::collections::BTreeMap;
 
         use combine::EasyParser;
 
@@ -186,10 +191,10 @@ mod tests {
         ctxts.assign_to_current("x0".to_string());
         ctxts.assign_to_current("x1".to_string());
         ctxts.assign_to_current("x2".to_string());
-        let t = terminner_parser(&ctxts, &opers).easy_parse(t);
+        let t = terminner_parser(&ctxts, &opers, None).easy_parse(t);
 
-        let mut subst = HashMap::new();
-        let inner = terminner_parser(&ctxts, &opers).easy_parse("g!x1");
+        let mut subst = BTreeMap::new();
+        let inner = terminner_parser(&ctxts, &opers, None).easy_parse("g!x1");
         subst.insert(Var::Id(VarId(0)), inner.unwrap().0.into());
 
         let r = t.unwrap().0.substitute(&Subst(subst));
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, rc::Rc};
+use std::{collections::BTreeMap, rc::Rc};
 
 use crate::{
     id::VarId,
@@ -9,21 +9,21 @@ use crate::{
 
 /// TODO: s/tのcontextの扱いを確認する
 pub fn unify(s: Rc<Term>, t: Rc<Term>) -> Option<Subst> {
-    use TermInner::{Fun, Int, RuledVar, Str, Var};
+    use TermInner::{Fun, Gen, Lit, RuledVar, Var};
 
     // println!("unify s: {} t: {}", s, t);
 
     match (s.inner.as_ref(), t.inner.as_ref()) {
         (rv1 @ RuledVar(_, _, _), rv2 @ RuledVar(_, _, _)) if rv1 == rv2 => Some(Subst::default()),
         (RuledVar(vid, rid, kind), u) => (!is_subterm_of2(vid, rid, kind, t.as_ref())).then_some(
-            HashMap::from([(
+            BTreeMap::from([(
                 crate::completion::subst::Var::Ruled(vid.clone(), *rid, kind.clone()),
                 Rc::new(u.clone()),
             )])
             .into(),
         ),
         (u, RuledVar(vid, rid, kind)) => (!is_subterm_of2(vid, rid, kind, s.as_ref())).then_some(
-            HashMap::from([(
+            BTreeMap::from([(
                 crate::completion::subst::Var::Ruled(vid.clone(), *rid, kind.clone()),
                 Rc::new(u.clone()),
             )])
@@ -32,16 +32,16 @@ pub fn unify(s: Rc<Term>, t: Rc<Term>) -> Option<Subst> {
 
         // 全く同じ内容なら
         (Var(x), Var(y)) if x == y => Some(Subst::default()),
-        (Int(x), Int(y)) if x == y => Some(Subst::default()),
-        (Str(s1), Str(s2)) if s1 == s2 => Some(Subst::default()),
+        (Lit(l1), Lit(l2)) if l1 == l2 => Some(Subst::default()),
+        (Gen(g1), Gen(g2)) if g1 == g2 => Some(Subst::default()),
 
         // s,tのどちらかが変数
         // 変数をx, 他の項をuとする
         (Var(x), u) => (!is_subterm_of(x, t.as_ref())).then_some(
-            HashMap::from([(crate::completion::subst::Var::Id(x.clone()), Rc::new(u.clone()))]).into(),
+            BTreeMap::from([(crate::completion::subst::Var::Id(x.clone()), Rc::new(u.clone()))]).into(),
         ),
         (u, Var(x)) => (!is_subterm_of(x, s.as_ref())).then_some(
-            HashMap::from([(crate::completion::subst::Var::Id(x.clone()), Rc::new(u.clone()))]).into(),
+            BTreeMap::from([(crate::completion::subst::Var::Id(x.clone()), Rc::new(u.clone()))]).into(),
         ),
 
         // s,tが関数
@@ -96,7 +96,7 @@ impl Subst {
     // {yi:ti | yi ∈ D(τ) - D(σ), i=1~m}
     pub fn compose(&self, sigma: &Subst) -> Subst {
         // let mut tau = self.clone();
-        let sigma_new = HashMap::new();
+        let sigma_new = BTreeMap::new();
         let sig = sigma.0.iter().fold(sigma_new, |mut sig, (var, inner)| {
             // xi ∈ D(σ)
             let cond1 = match var {
@@ -127,7 +127,7 @@ impl Subst {
         });
         // dbg!(&sig);
 
-        let tau_new = HashMap::new();
+        let tau_new = BTreeMap::new();
         let mut ta = self.0.iter().fold(tau_new, |mut ta, (var, inner)| {
             // yi ∈ D(τ) - D(σ)
             // var is in sigma's varが入っていると除く
@@ -147,7 +147,7 @@ impl Subst {
 
 // impl From<Vec<(usize, TermInner)>> for Subst {
 //     fn from(value: Vec<(usize, TermInner)>) -> Self {
-//         let mut map = HashMap::new();
+//         let mut map = BTreeMap::new();
 //         value.iter().map(|(var1, terminner)| {
 //             (VarId(*var1), Rc::new(terminner))
 //         }).for_each(|(k, v) | {
@@ -159,7 +159,7 @@ impl Subst {
 // }
 impl From<Vec<(usize, VarId)>> for Subst {
     fn from(value: Vec<(usize, VarId)>) -> Self {
-        let mut map = HashMap::new();
+        let mut map = BTreeMap::new();
         value
             .iter()
             .map(|(var1, var2)| (Var::Id(VarId(*var1)), Rc::new(TermInner::Var(var2.clone()))))
@@ -172,7 +172,7 @@ impl From<Vec<(usize, VarId)>> for Subst {
 }
 impl From<Vec<((usize, usize, RuleKind), VarId)>> for Subst {
     fn from(value: Vec<((usize, usize, RuleKind), VarId)>) -> Self {
-        let mut map = HashMap::new();
+        let mut map = BTreeMap::new();
         value
             .iter()
             .map(|((vid, rid, kind), var2)| {
@@ -275,10 +275,10 @@ mod tests {
         ctxts.assign_to_current("x2".to_string());
 
         let mut subst1 = Subst::default();
-        let inner1 = terminner_parser(&ctxts, &opers).parse("g!x1");
+        let inner1 = terminner_parser(&ctxts, &opers, None).parse("g!x1");
         subst1.insert(Var::Id(VarId(0)), Rc::new(inner1.unwrap().0));
         let mut subst2 = Subst::default();
-        let inner2 = terminner_parser(&ctxts, &opers).parse("g!x2");
+        let inner2 = terminner_parser(&ctxts, &opers, None).parse("g!x2");
         subst2.insert(Var::Id(VarId(1)), Rc::new(inner2.unwrap().0));
         // let subst = subst1.compose(subst2);
         let subst = subst2.compose(&subst1);
@@ -0,0 +1,261 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use crate::{
+    context::Context,
+    equation::Equation,
+    eval::{Query, QueryEntity},
+    id::{Symbol, VarId},
+    predicate::Predicate,
+    preservation::{to_subst, Translated},
+    r#type::Type,
+    schema::Schema,
+    symbol_table::Names,
+    term::{Term, TermInner},
+};
+
+impl Query {
+    /// `self`の出力に`next`を続けて評価するのと同じ結果になる1つのqueryを作る
+    /// `next`の`#for`の変数を、そのentityの`self`のブロックの`#for`の変数の組に置き換え、
+    /// `#where`/`#return`/`#keys`の項を`self`の`#return`/`#keys`で元のschemaの項に書き換える。
+    /// fkeyをたどるときは、`self`の`#keys`の変換を`Subst::compose`でその時点の代入と合成する。
+    pub fn compose(&self, next: &Query) -> Result<Query, String> {
+        let Some(middle) = &self.target else {
+            return Err("The first query must declare #target".to_string());
+        };
+        if self.entities.iter().chain(&next.entities).any(|qe| qe.entity.is_none()) {
+            return Err("Only queries of #entity blocks can be composed".to_string());
+        }
+        if self.entities.iter().chain(&next.entities).any(|qe| !qe.aggs.is_empty()) {
+            return Err("Queries with aggregates cannot be composed".to_string());
+        }
+
+        let mut names = self.names.as_ref().clone();
+        names.extend(next.names.as_ref().clone());
+        let mut next_var = [next_var_id(self), next_var_id(next), names.next_var_id()].into_iter().max().unwrap_or(0);
+
+        // (nextのブロックのentity, nextの変数, selfの変数) -> 合成したqueryの変数
+        let mut renames = BTreeMap::new();
+        let mut blocks = vec![];
+        for qe in &next.entities {
+            let entity = qe.entity.clone().unwrap_or_default();
+            let mut fr = Context::default();
+            let mut wh = vec![];
+            let mut env = BTreeMap::new();
+            for (vid, tp) in qe.fr.iter().flat_map(|c| c.0.iter()) {
+                let Type::Unary(mid) = tp else {
+                    return Err("#for of the second query must range over entities".to_string());
+                };
+                let block = self.block(mid).ok_or_else(|| {
+                    format!("The first query has no #entity {} block", names.sort_name(mid).unwrap_or("?"))
+                })?;
+                let mut row = BTreeMap::new();
+                for (src_vid, src_tp) in block.fr.iter().flat_map(|c| c.0.iter()) {
+                    let new = VarId(next_var);
+                    next_var += 1;
+                    let name = format!(
+                        "{}.{}",
+                        next.names.var_name(vid).unwrap_or("_"),
+                        self.names.var_name(src_vid).unwrap_or("_")
+                    );
+                    names.insert(name, Symbol::Var(new.clone()));
+                    fr.0.insert(new.clone(), src_tp.clone());
+                    renames.insert((entity.clone(), vid.clone(), src_vid.clone()), new.clone());
+                    row.insert(src_vid.clone(), Rc::new(TermInner::Var(new)));
+                }
+                // 変数ごとに、selfのブロックの`#where`を付け替えて引き継ぐ
+                let subst = to_subst(&row);
+                wh.extend(block.wh.iter().map(|pred| pred.substitute(&subst)));
+                env.insert(vid.clone(), Translated::Row(mid.clone(), row));
+            }
+            blocks.push((qe, entity, fr, wh, env));
+        }
+
+        let names = Rc::new(names);
+        let composer = Composer { query: self, middle, names: names.clone() };
+        let mut entities = vec![];
+        for (qe, entity, fr, mut wh, env) in blocks {
+            for pred in &qe.wh {
+                wh.extend(composer.predicate(pred, &env)?);
+            }
+            let ret = qe
+                .ret
+                .iter()
+                .map(|(oid, term)| Ok((oid.clone(), composer.term(term, &env)?.as_ref().clone())))
+                .collect::<Result<Vec<_>, String>>()?;
+            let mut keys = vec![];
+            for (oid, target, transform) in &qe.keys {
+                let target = target.clone().unwrap_or_default();
+                let mut composed = vec![];
+                for (vid, term) in transform {
+                    for (src_vid, t) in composer.row(term, &env)? {
+                        let new = renames.get(&(target.clone(), vid.clone(), src_vid)).ok_or_else(|| {
+                            format!("#keys {}: cannot compose the transform", names.oper_name(oid).unwrap_or("?"))
+                        })?;
+                        composed.push((new.clone(), t.as_ref().clone()));
+                    }
+                }
+                keys.push((oid.clone(), Some(target), composed));
+            }
+            entities.push(QueryEntity {
+                instance: self.instance.clone(),
+                entity: Some(entity),
+                fr: vec![fr],
+                wh,
+                ret,
+                aggs: vec![],
+                keys,
+            });
+        }
+
+        Ok(Query {
+            instance: self.instance.clone(),
+            target: next.target.clone(),
+            entities,
+            names,
+        })
+    }
+}
+
+/// 2つ目のqueryの項を1つ目のqueryで元のschemaの項に書き換える
+struct Composer<'a> {
+    query: &'a Query,
+    middle: &'a Schema,
+    names: Rc<Names>,
+}
+
+impl Composer<'_> {
+    fn translate(&self, t: &TermInner, env: &BTreeMap<VarId, Translated>) -> Result<Translated, String> {
+        self.query
+            .translate(self.middle, t, env)
+            .ok_or_else(|| format!("{} cannot be rewritten by the first query", self.display(t)))
+    }
+
+    /// attrやtypesideの値になる項
+    fn term(&self, t: &TermInner, env: &BTreeMap<VarId, Translated>) -> Result<Rc<TermInner>, String> {
+        match self.translate(t, env)? {
+            Translated::Term(t) => Ok(t),
+            Translated::Row(..) => Err(format!("{} is not a value", self.display(t))),
+        }
+    }
+
+    /// entityの項。行き先のブロックの変数への代入になる
+    fn row(&self, t: &TermInner, env: &BTreeMap<VarId, Translated>) -> Result<BTreeMap<VarId, Rc<TermInner>>, String> {
+        match self.translate(t, env)? {
+            Translated::Row(_, row) => Ok(row),
+            Translated::Term(_) => Err(format!("{} is not a row of an entity", self.display(t))),
+        }
+    }
+
+    /// entityの項の等式は変数ごとの等式に分ける。否定や不等式は分けられないので値の項に限る
+    fn predicate(&self, pred: &Predicate, env: &BTreeMap<VarId, Translated>) -> Result<Vec<Predicate>, String> {
+        let eq = pred.equation();
+        let equation = |left: Rc<TermInner>, right: Rc<TermInner>| Equation {
+            context: Rc::new(Context::default()),
+            names: self.names.clone(),
+            left,
+            right,
+        };
+        match pred {
+            Predicate::Eq(_) => match (self.translate(&eq.left, env)?, self.translate(&eq.right, env)?) {
+                (Translated::Term(l), Translated::Term(r)) => Ok(vec![Predicate::Eq(equation(l, r))]),
+                (Translated::Row(_, l), Translated::Row(_, r)) => Ok(l
+                    .into_iter()
+                    .filter_map(|(vid, lt)| Some(Predicate::Eq(equation(lt, r.get(&vid)?.clone()))))
+                    .collect()),
+                _ => Err(format!("#where {}: sorts of both sides differ", pred)),
+            },
+            Predicate::Neq(_) => Ok(vec![Predicate::Neq(equation(self.term(&eq.left, env)?, self.term(&eq.right, env)?))]),
+            Predicate::Cmp(cmp, _) => {
+                Ok(vec![Predicate::Cmp(*cmp, equation(self.term(&eq.left, env)?, self.term(&eq.right, env)?))])
+            }
+            Predicate::Not(inner) => match self.predicate(inner, env)?.as_slice() {
+                [p] => Ok(vec![Predicate::Not(Box::new(p.clone()))]),
+                _ => Err(format!("#where {}: cannot negate an equation between rows", pred)),
+            },
+        }
+    }
+
+    fn display(&self, t: &TermInner) -> String {
+        Term {
+            context: Rc::new(Context::default()),
+            names: self.names.clone(),
+            inner: Rc::new(t.clone()),
+        }
+        .to_string()
+    }
+}
+
+/// queryの`#for`に現れるどの変数とも重ならない番号
+fn next_var_id(query: &Query) -> usize {
+    query
+        .entities
+        .iter()
+        .flat_map(|qe| qe.fr.iter().flat_map(|c| c.0.keys()))
+        .map(|vid| vid.0 + 1)
+        .max()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::{
+        context::Context,
+        eval::eval,
+        instance::Instance,
+        term::{Term, TermInner},
+        workspace::Workspace,
+    };
+
+    /// Staffの生成元ごとの(sname, uname!unit)
+    fn staff(instance: &Instance) -> Vec<(String, String)> {
+        let rules = instance.deduction_rules();
+        let value = |path: &[&str], gid| {
+            let inner = path.iter().rev().fold(Rc::new(TermInner::Gen(gid)), |t, oper| {
+                Rc::new(TermInner::Fun(instance.names.oper_id(oper).unwrap(), vec![t]))
+            });
+            Term { context: Rc::new(Context::default()), names: instance.names.clone(), inner }
+                .normalize_with(&rules, &instance.schema.theory.builtins)
+                .to_string()
+        };
+        let tid = instance.names.type_id("Staff").unwrap();
+        let mut rows = instance
+            .generators_of(&tid)
+            .map(|g| (value(&["sname"], g.id.clone()), value(&["uname", "unit"], g.id.clone())))
+            .collect::<Vec<_>>();
+        rows.sort();
+        rows
+    }
+
+    #[test]
+    fn test_compose() {
+        let ws = Workspace::new("example");
+        let (instance, blocks) = ws.query("blocks").unwrap();
+        let (_, staff_query) = ws.query("staff").unwrap();
+        let composed = blocks.compose(&staff_query).unwrap();
+
+        let expected = vec![
+            ("\"Euclid\"".to_string(), "\"Admin\"".to_string()),
+            ("\"Turing\"".to_string(), "\"Admin\"".to_string()),
+        ];
+        let twice = eval(eval(instance.clone(), blocks).unwrap(), staff_query).unwrap();
+        assert_eq!(staff(&twice), expected);
+        let once = eval(instance, composed).unwrap();
+        assert_eq!(staff(&once), expected);
+        assert_eq!(once.generators_of(&once.names.type_id("Unit").unwrap()).count(), 3);
+    }
+
+    #[test]
+    fn test_compose_errors() {
+        let ws = Workspace::new("example");
+        let (_, blocks) = ws.query("blocks").unwrap();
+        let (_, boss) = ws.query("boss").unwrap();
+        // bossの`#for`はEmpを動くが、blocksにEmpのブロックはない
+        assert!(blocks.compose(&boss).is_err());
+        let (_, staff_query) = ws.query("staff").unwrap();
+        // staffの出力にはさらに続けられるブロックがない
+        assert!(staff_query.compose(&blocks).is_err());
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::BTreeSet;
+use std::rc::Rc;
+
+use crate::{
+    completion::{
+        congruence::ground_rules, critical_pairs::prepare_rule, rule::{Rule, RuleKind}, Incomplete,
+    },
+    context::Context,
+    instance::Instance,
+    term::{Term, TermInner},
+};
+
+/// 書き換えの1ステップ
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Step {
+    /// 書き換えた後の項
+    pub term: String,
+    /// 使った規則。組み込み演算の評価なら`builtin`
+    pub rule: String,
+}
+
+/// 異なる定数が等しくなってしまう証拠
+/// 2つの定数をそれぞれ書き換えて、同じ項に合流する列
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Inconsistency {
+    pub left: String,
+    pub right: String,
+    pub left_steps: Vec<Step>,
+    pub right_steps: Vec<Step>,
+    /// 合流した項と等しいとする`#data`
+    pub data: Vec<String>,
+}
+
+impl std::fmt::Display for Inconsistency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "inconsistent: {} = {}", self.left, self.right)?;
+        for (start, steps) in [(&self.left, &self.left_steps), (&self.right, &self.right_steps)] {
+            write!(f, "\n  {}", start)?;
+            for step in steps {
+                write!(f, "\n    -> {}  by {}", step.term, step.rule)?;
+            }
+        }
+        for data in &self.data {
+            write!(f, "\n  from #data {}", data)?;
+        }
+        Ok(())
+    }
+}
+
+/// 無矛盾性の検査の結果
+#[derive(Clone, Debug, PartialEq)]
+pub enum Consistency {
+    Consistent,
+    Inconsistent(Inconsistency),
+    /// 完備化が終わらず、合流する定数がないとは言い切れない
+    Undecided(Incomplete),
+}
+
+impl Instance {
+    /// typesideの等式とschemaのconstraintを完備化し、データを合同閉包で閉じて、異なる定数が合流しないかを調べる
+    /// データはconstraintを生成元ごとに代入した等式と合わせて閉じるので、行数が増えても完備化の規則の上限には数えない。
+    /// 定数はリテラルと引数のない演算子(`true`など)で、組み込み演算で同じ値になるものは同じ定数とみなす。
+    /// 合流する定数があれば、両方から共通の項までの書き換えの列を証拠として返す。
+    /// 完備化が終わらなければ、途中の規則で合流が見つかったときだけ`Inconsistent`、それ以外は`Undecided`とする。
+    pub fn is_consistent(&self) -> Consistency {
+        let (schema_rules, incomplete) = self.schema.rules();
+        let mut rules = ground_rules(&self.ground_equations());
+        rules.extend(schema_rules);
+
+        let mut constants = BTreeSet::new();
+        for rule in &rules {
+            collect_constants(&rule.before, &mut constants);
+            collect_constants(&rule.after, &mut constants);
+        }
+        for eq in &self.data {
+            collect_constants(&eq.left, &mut constants);
+            collect_constants(&eq.right, &mut constants);
+        }
+
+        // (定数, 組み込み演算での値, 正規形, 書き換えの列)
+        let mut normals: Vec<(_, _, Rc<TermInner>, Vec<Step>)> = vec![];
+        for constant in constants {
+            let value = self.schema.theory.builtins.eval(&constant);
+            let (normal, steps) = self.derive(&constant, &rules);
+            let collapsed = normals.iter().find(|(_, v, n, _)| n == &normal && v != &value);
+            if let Some((other, _, _, other_steps)) = collapsed {
+                let data = self
+                    .data
+                    .iter()
+                    .filter(|eq| self.derive(&eq.left, &rules).0 == normal)
+                    .map(|eq| format!("{} = {}", eq.left_term(), eq.right_term()))
+                    .collect();
+                return Consistency::Inconsistent(Inconsistency {
+                    left: self.display(other),
+                    right: self.display(&constant),
+                    left_steps: other_steps.clone(),
+                    right_steps: steps,
+                    data,
+                });
+            }
+            normals.push((constant, value, normal, steps));
+        }
+        match incomplete {
+            Some(incomplete) => Consistency::Undecided(incomplete),
+            None => Consistency::Consistent,
+        }
+    }
+
+    /// `normalize_with`と同じ順で書き換え、使った規則を記録する
+    fn derive(&self, t: &Rc<TermInner>, rules: &[Rule]) -> (Rc<TermInner>, Vec<Step>) {
+        let mut term = Rc::new(Term { context: Rc::new(Context::default()), names: self.names.clone(), inner: t.clone() });
+        let mut steps = vec![];
+        loop {
+            let rewritten = rules.iter().enumerate().find_map(|(idx, rule)| {
+                let prepared = prepare_rule(rule, idx).make_vars_ruled(RuleKind::NotSet);
+                let redex = term.find_redexes_from(&prepared).into_iter().next()?;
+                Some((redex.apply(), rule))
+            });
+            if let Some((next, rule)) = rewritten {
+                steps.push(Step { term: next.to_string(), rule: format!("{} -> {}", rule.before(), rule.after()) });
+                term = next;
+                continue;
+            }
+            let evaluated = self.schema.theory.builtins.eval(&term.inner);
+            if evaluated == term.inner {
+                break;
+            }
+            term = Rc::new(Term { inner: evaluated, ..term.as_ref().clone() });
+            steps.push(Step { term: term.to_string(), rule: "builtin".to_string() });
+        }
+        (term.inner.clone(), steps)
+    }
+
+    fn display(&self, t: &Rc<TermInner>) -> String {
+        Term { context: Rc::new(Context::default()), names: self.names.clone(), inner: t.clone() }.to_string()
+    }
+}
+
+fn collect_constants(t: &Rc<TermInner>, constants: &mut BTreeSet<Rc<TermInner>>) {
+    match t.as_ref() {
+        TermInner::Lit(_) => {
+            constants.insert(t.clone());
+        }
+        TermInner::Fun(_, args) if args.is_empty() => {
+            constants.insert(t.clone());
+        }
+        TermInner::Fun(_, args) => args.iter().for_each(|arg| collect_constants(arg, constants)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use combine::{eof, stream::position::Stream, EasyParser, Parser};
+    use rstest::rstest;
+
+    use crate::{consistency::Consistency, instance::Instance, parser::instance::instance_parser, workspace::Workspace};
+
+    fn parse(ws: &Workspace, input: &str) -> Instance {
+        instance_parser(ws)
+            .skip(eof())
+            .easy_parse(Stream::new(input))
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn test_is_consistent_example() {
+        let ws = Workspace::new("example");
+        assert_eq!(ws.instance("i").unwrap().is_consistent(), Consistency::Consistent);
+        assert_eq!(ws.instance("hr").unwrap().is_consistent(), Consistency::Consistent);
+    }
+
+    #[test]
+    fn test_is_consistent_many_rows() {
+        let ws = Workspace::new("example");
+        // データの行数は完備化の規則の上限に数えないので、行が多くても決まる
+        let n = 30;
+        let mut input = format!("#schema s\n#elem d0: Dept\n#elem {}: Emp\n", (0..n).map(|k| format!("e{k}")).collect::<Vec<_>>().join(" "));
+        input += "#data name!d0 = \"HR\"\n#data sec!d0 = e0\n";
+        for k in 0..n {
+            input += &format!("#data last!e{k} = \"L{k}\"\n#data wrk!e{k} = d0\n#data mgr!e{k} = e0\n#data sal!e{k} = {}\n", 200 - k);
+        }
+        let instance = parse(&ws, &input);
+        assert_eq!(instance.data.len(), 4 * n + 2);
+        assert_eq!(instance.is_consistent(), Consistency::Consistent);
+
+        // 最後の人の名前を2つにすると、行が多くても矛盾が見つかる
+        input += &format!("#data last!e{} = \"Other\"\n", n - 1);
+        assert!(matches!(parse(&ws, &input).is_consistent(), Consistency::Inconsistent(_)));
+    }
+
+    #[rstest]
+    // 同じ人の名前が2つ
+    #[case("#data last!e1 = \"Gauss\"\n#data last!e1 = \"Noether\"\n", "\"Gauss\"", "\"Noether\"")]
+    // mgr!mgr!e = mgr!eから、e1とe2の名前が等しくなる
+    #[case("#data mgr!e1 = e2\n#data mgr!e2 = e1\n#data last!e1 = \"Gauss\"\n#data last!e2 = \"Noether\"\n", "\"Gauss\"", "\"Noether\"")]
+    fn test_is_inconsistent(#[case] data: &str, #[case] left: &str, #[case] right: &str) {
+        let ws = Workspace::new("example");
+        let instance = parse(&ws, &format!("#schema s\n#elem e1 e2: Emp\n{}", data));
+        let Consistency::Inconsistent(inconsistency) = instance.is_consistent() else {
+            panic!("not inconsistent");
+        };
+        let mut sides = [inconsistency.left.as_str(), inconsistency.right.as_str()];
+        sides.sort();
+        assert_eq!(sides, [left, right]);
+        assert!(!inconsistency.left_steps.is_empty() || !inconsistency.right_steps.is_empty());
+        assert!(inconsistency.data.contains(&format!("last!e1 = {}", left)));
+        assert!(inconsistency.data.iter().any(|data| data.ends_with(right)));
+    }
+}
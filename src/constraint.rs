@@ -0,0 +1,241 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use crate::{
+    completion::{complete_partially, congruence::ground_rules, rule::Rule, COMPLETION_LIMIT},
+    completion::subst::{Subst, Var},
+    context::Context,
+    equation::Equation,
+    id::{GenId, OperId},
+    instance::{Elem, Instance},
+    r#type::Type,
+    term::{Term, TermInner},
+};
+
+/// schemaのconstraintを満たさない生成元の組
+#[derive(Clone, Debug)]
+pub struct Violation {
+    /// 生成元を代入したconstraint
+    pub equation: Equation,
+    /// 左辺・右辺の正規形
+    pub left: Rc<Term>,
+    pub right: Rc<Term>,
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "violation: {} = {}", self.equation.left_term(), self.equation.right_term())?;
+        writeln!(f, "  left:  {}", self.left)?;
+        write!(f, "  right: {}", self.right)
+    }
+}
+
+/// 違反が見つかったときの扱い
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Repair {
+    /// 違反があればそのまま返す
+    Reject,
+    /// 正規形が同じentityの生成元同士なら、それらを同一視して直す
+    Merge,
+}
+
+impl Instance {
+    /// すべての生成元についてschemaのconstraintを調べる
+    /// 値が足りずに正規形がfkey/attrを含むものは判定できないので報告しない(`validate`が報告する)。
+    /// 正規形がnullを含むものも、値が分からないので両辺が一致しない限り判定しない。
+    pub fn check_constraints(&self) -> Vec<Violation> {
+        let rules = self.constraint_rules();
+        let builtins = &self.schema.theory.builtins;
+        self.schema
+            .constraints
+            .iter()
+            .flat_map(|cons| self.instantiate(cons))
+            .filter_map(|eq| {
+                let left = eq.left_term().normalize_with(&rules, builtins);
+                let right = eq.right_term().normalize_with(&rules, builtins);
+                let determined = !self.is_undetermined(&left.inner) && !self.is_undetermined(&right.inner);
+                (determined && left.inner != right.inner).then_some(Violation {
+                    equation: eq,
+                    left,
+                    right,
+                })
+            })
+            .collect()
+    }
+
+    /// constraintを満たすinstanceを返す。満たせなければ残った違反を返す
+    pub fn enforce_constraints(&self, repair: Repair) -> Result<Instance, Vec<Violation>> {
+        let mut instance = self.clone();
+        loop {
+            let violations = instance.check_constraints();
+            if violations.is_empty() {
+                return Ok(instance);
+            }
+            if repair == Repair::Reject {
+                return Err(violations);
+            }
+            let merge = violations.iter().find_map(|v| instance.mergeable(&v.left.inner, &v.right.inner));
+            let Some((keep, drop)) = merge else {
+                return Err(violations);
+            };
+            instance = instance.merge(&keep, &drop);
+        }
+    }
+
+    /// 同じentityの異なる生成元なら(残す方, 消す方)
+    fn mergeable(&self, left: &TermInner, right: &TermInner) -> Option<(GenId, GenId)> {
+        let (TermInner::Gen(g1), TermInner::Gen(g2)) = (left, right) else {
+            return None;
+        };
+        let entity_of = |gid: &GenId| {
+            self.elems.iter().find_map(|e| match e {
+                Elem::Gen(g) if &g.id == gid => Some(g.entity.clone()),
+                _ => None,
+            })
+        };
+        (g1 != g2 && entity_of(g1).is_some() && entity_of(g1) == entity_of(g2))
+            .then(|| (g1.clone().min(g2.clone()), g1.clone().max(g2.clone())))
+    }
+
+    /// 生成元`drop`を`keep`で置き換える
+    pub(crate) fn merge(&self, keep: &GenId, drop: &GenId) -> Instance {
+        let mut instance = self.clone();
+        instance.elems.retain(|e| !matches!(e, Elem::Gen(g) if &g.id == drop));
+        let mut data: Vec<Equation> = vec![];
+        for eq in &self.data {
+            let eq = Equation {
+                context: eq.context.clone(),
+                names: eq.names.clone(),
+                left: replace_gen(&eq.left, drop, keep),
+                right: replace_gen(&eq.right, drop, keep),
+            };
+            if !data.contains(&eq) {
+                data.push(eq);
+            }
+        }
+        instance.data = data;
+        instance
+    }
+
+    /// instanceのデータを合同閉包で閉じた規則と、theoryの等式を完備化した規則
+    /// theoryの等式は書いた向きのままだと可換性などで書き換えが止まらないので、LPOで向き付ける。
+    fn constraint_rules(&self) -> Vec<Rule> {
+        let mut rules = ground_rules(&self.data);
+        rules.extend(complete_partially(self.schema.theory.eqs.clone(), COMPLETION_LIMIT).0);
+        rules
+    }
+
+    /// constraintの変数に、型の合う生成元をすべての組み合わせで代入する
+    pub(crate) fn instantiate(&self, cons: &Equation) -> Vec<Equation> {
+        let init = vec![BTreeMap::new()];
+        let substs = cons.context.0.iter().fold(init, |substs, (vid, tp)| {
+            let gens = match tp {
+                Type::Unary(entity) => self.generators_of(entity).collect::<Vec<_>>(),
+                _ => vec![],
+            };
+            substs
+                .into_iter()
+                .flat_map(|subst| {
+                    gens.iter().map(move |g| {
+                        let mut subst = subst.clone();
+                        subst.insert(Var::Id(vid.clone()), Rc::new(TermInner::Gen(g.id.clone())));
+                        subst
+                    })
+                })
+                .collect()
+        });
+        substs
+            .into_iter()
+            .map(|subst| {
+                let subst = Subst::new(subst);
+                Equation {
+                    context: Rc::new(Context::default()),
+                    names: self.names.clone(),
+                    left: cons.left.substitute(&subst),
+                    right: cons.right.substitute(&subst),
+                }
+            })
+            .collect()
+    }
+
+    /// fkey/attrかnullを含む
+    pub(crate) fn is_undetermined(&self, t: &TermInner) -> bool {
+        let is_schema_oper = |id: &OperId| {
+            self.schema.fkeys.iter().chain(&self.schema.attrs).any(|op| &op.id == id)
+        };
+        match t {
+            TermInner::Fun(id, args) => is_schema_oper(id) || args.iter().any(|arg| self.is_undetermined(arg)),
+            TermInner::Gen(gid) => self.is_null(gid),
+            _ => false,
+        }
+    }
+}
+
+fn replace_gen(t: &Rc<TermInner>, from: &GenId, to: &GenId) -> Rc<TermInner> {
+    match t.as_ref() {
+        TermInner::Gen(gid) if gid == from => Rc::new(TermInner::Gen(to.clone())),
+        TermInner::Fun(id, args) => Rc::new(TermInner::Fun(
+            id.clone(),
+            args.iter().map(|arg| replace_gen(arg, from, to)).collect(),
+        )),
+        _ => t.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use combine::{eof, stream::position::Stream, EasyParser, Parser};
+
+    use crate::{
+        constraint::Repair, instance::Instance, parser::instance::instance_parser, workspace::Workspace,
+    };
+
+    fn parse(ws: &Workspace, input: &str) -> Instance {
+        instance_parser(ws)
+            .skip(eof())
+            .easy_parse(Stream::new(input))
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn test_check_constraints_path_equations_hold() {
+        let ws = Workspace::new("example");
+        let instance = ws.instance("i").unwrap();
+        let violations = instance.check_constraints();
+        // パス等式(mgr/wrk/sec)も、組み込みのleで評価するstも成り立つ
+        assert!(violations.is_empty());
+    }
+
+    #[test]
+    fn test_check_constraints_violation() {
+        let ws = Workspace::new("example");
+        // e2の上司e1はd3で働いているが、e2はd2で働いている
+        let input = "#schema s\n#elem e1 e2: Emp\n#elem d2 d3: Dept\n\
+            #data wrk!e1 = d3\n#data mgr!e1 = e1\n\
+            #data wrk!e2 = d2\n#data mgr!e2 = e1\n";
+        let instance = parse(&ws, input);
+        let violations = instance
+            .check_constraints()
+            .into_iter()
+            .filter(|v| v.equation.left_term().to_string() == "wrk!mgr!e2")
+            .collect::<Vec<_>>();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].left.to_string(), "d3");
+        assert_eq!(violations[0].right.to_string(), "d2");
+
+        assert!(instance.enforce_constraints(Repair::Reject).is_err());
+    }
+
+    #[test]
+    fn test_enforce_constraints_merge() {
+        let ws = Workspace::new("example");
+        let input = "#schema s\n#elem e1 e2: Emp\n#elem d2 d3: Dept\n\
+            #data wrk!e1 = d3\n#data mgr!e1 = e1\n\
+            #data wrk!e2 = d2\n#data mgr!e2 = e1\n";
+        let repaired = parse(&ws, input).enforce_constraints(Repair::Merge).unwrap();
+        // d2とd3が同一視される
+        assert_eq!(repaired.generators_of(&ws.types.get("Dept").unwrap()).count(), 1);
+        assert!(repaired.check_constraints().is_empty());
+    }
+}
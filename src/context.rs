@@ -1,10 +1,10 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 use crate::id::VarId;
 use crate::r#type::Type;
 
 #[derive(PartialEq, Clone, Default)]
-pub struct Context(pub HashMap<VarId, Type>);
+pub struct Context(pub BTreeMap<VarId, Type>);
 
 impl std::fmt::Debug for Context {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -1,16 +1,17 @@
 // #[derive(Debug)]
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::rc::Rc;
 
 use autoincrement::AsyncIncrement;
 use autoincrement::AsyncIncremental;
 
 use crate::id::Symbol;
 use crate::id::{CtxtId, VarId};
-use crate::symbol_table::SymbolTable;
+use crate::symbol_table::{Names, SymbolTable};
 
 pub struct CtxtTable {
-    pub vars: RefCell<HashMap<CtxtId, SymbolTable<VarId>>>,
+    pub vars: RefCell<BTreeMap<CtxtId, SymbolTable<VarId>>>,
     pub generator: AsyncIncrement<CtxtId>,
 }
 
@@ -23,14 +24,14 @@ impl std::fmt::Debug for CtxtTable {
 impl CtxtTable {
     pub fn new() -> Self {
         CtxtTable {
-            vars: RefCell::new(HashMap::new()),
+            vars: RefCell::new(BTreeMap::new()),
             generator: CtxtId::init_with(CtxtId(1)),
         }
     }
 
     pub fn init_with(v: CtxtId) -> Self {
         CtxtTable {
-            vars: RefCell::new(HashMap::new()),
+            vars: RefCell::new(BTreeMap::new()),
             generator: CtxtId::init_with(v),
         }
     }
@@ -44,12 +45,16 @@ impl CtxtTable {
             .assign(name)
     }
 
-    pub fn var_id_from_current(&self, name: &str) -> VarId {
+    pub fn find_var_from_current(&self, name: &str) -> Option<VarId> {
         let current_ctxt_id = self.generator.current();
         self.vars
             .borrow()
             .get(&current_ctxt_id)
             .and_then(|table| table.get(name))
+    }
+
+    pub fn var_id_from_current(&self, name: &str) -> VarId {
+        self.find_var_from_current(name)
             .expect(
                 format!(
                     "Variable '{}' not found in current context {:?}",
@@ -63,10 +68,17 @@ impl CtxtTable {
         self.generator.pull();
     }
 
-    pub fn current_var_table(&self) -> HashMap<String, Symbol> {
+    /// 現在の文脈の変数を、`parent`の名前に文脈ローカルな変数として重ねた表
+    pub fn scoped_var_table(&self, parent: Names) -> Names {
+        let mut names = Names::scope(Rc::new(parent));
+        names.extend(self.current_var_table());
+        names
+    }
+
+    pub fn current_var_table(&self) -> Names {
         let current_ctxt_id = self.generator.current();
         // dbg!(&current_ctxt_id, &self.vars);
-        let mut var_names = HashMap::new();
+        let mut var_names = Names::new();
         self.vars
             .borrow()
             .get(&current_ctxt_id)
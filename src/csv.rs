@@ -0,0 +1,261 @@
+use std::path::Path;
+
+use combine::EasyParser;
+
+use crate::{
+    instance::Instance,
+    parser::csv::csv_parser,
+    r#type::Type,
+    schema::Schema,
+    table::{Row, TableError},
+    workspace::Workspace,
+};
+
+impl Instance {
+    /// entityごとの`{dir}/{entity}.csv`を、schemaのinstanceとして読む
+    /// 1行目は`id`とfkey/attrの列名。値の読み方は`from_tables`と同じで、空のセルは値がないことを表す。nullには`?`が要る。
+    /// エラーはファイル名と行番号を付けて返す。
+    pub fn from_csv_dir(ws: &Workspace, schema: &Schema, dir: impl AsRef<Path>) -> Result<Instance, String> {
+        let dir = dir.as_ref();
+        let mut files = std::fs::read_dir(dir)
+            .map_err(|e| format!("Failed to read directory: {}: {}", dir.display(), e))?
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "csv"))
+            .collect::<Vec<_>>();
+        files.sort();
+
+        let mut tables = vec![];
+        for path in &files {
+            let entity = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default().to_string();
+            let src = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            let ((header, records), _) = csv_parser()
+                .easy_parse(combine::stream::position::Stream::new(src.as_str()))
+                .map_err(|e| format!("Failed to parse CSV: {}\n{}", path.display(), e))?;
+            let id = match header.iter().filter(|column| column.as_str() == "id").count() {
+                1 => header.iter().position(|column| column == "id").unwrap_or_default(),
+                _ => return Err(format!("{}:1: the header must have exactly one id column", path.display())),
+            };
+            let mut rows = vec![];
+            for (line, fields) in records {
+                if fields.len() != header.len() {
+                    return Err(format!(
+                        "{}:{}: expected {} fields, found {}",
+                        path.display(),
+                        line,
+                        header.len(),
+                        fields.len()
+                    ));
+                }
+                let cells = header
+                    .iter()
+                    .zip(&fields)
+                    .enumerate()
+                    .filter(|(i, (_, value))| *i != id && !value.is_empty())
+                    .map(|(_, (column, value))| (column.clone(), value.clone()))
+                    .collect();
+                rows.push(Row { id: fields[id].clone(), cells, line });
+            }
+            tables.push((entity, rows));
+        }
+
+        Instance::from_tables(ws, schema, &tables, false).map_err(|TableError { entity, line, message }| {
+            let path = dir.join(format!("{}.csv", entity));
+            match (entity.as_str(), line) {
+                ("", _) => message,
+                (_, 0) => format!("{}: {}", path.display(), message),
+                (_, line) => format!("{}:{}: {}", path.display(), line, message),
+            }
+        })
+    }
+
+    /// entityごとに`{dir}/{entity}.csv`を書く。saturateしたinstanceに使う
+    /// 列は`id`とschemaのfkey/attrの宣言順、行は`id`の順(数字の部分は数として比べる)。
+    pub fn to_csv_dir(&self, dir: impl AsRef<Path>) -> Result<(), String> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create directory: {}: {}", dir.display(), e))?;
+        for (entity, mut rows) in self.to_tables()? {
+            let tid = self.names.type_id(&entity);
+            let columns = self
+                .schema
+                .fkeys
+                .iter()
+                .chain(&self.schema.attrs)
+                .filter(|op| tid.as_ref().is_some_and(|tid| op.dom.as_ref() == &Type::Unary(tid.clone())))
+                .map(|op| self.names.oper_name(&op.id).unwrap_or("?").to_string())
+                .collect::<Vec<_>>();
+            rows.sort_by(|a, b| natural_key(&a.id).cmp(&natural_key(&b.id)).then(a.id.cmp(&b.id)));
+
+            let mut csv = write_record(std::iter::once("id").chain(columns.iter().map(String::as_str)));
+            for row in &rows {
+                let value = |column: &String| {
+                    row.cells.iter().find(|(c, _)| c == column).map(|(_, v)| v.as_str()).unwrap_or_default()
+                };
+                csv += &write_record(std::iter::once(row.id.as_str()).chain(columns.iter().map(value)));
+            }
+            let path = dir.join(format!("{}.csv", entity));
+            std::fs::write(&path, csv).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        }
+        Ok(())
+    }
+}
+
+/// カンマ・改行・`"`を含む値や前後に空白のある値はクォートする
+fn write_record<'a>(fields: impl Iterator<Item = &'a str>) -> String {
+    let fields = fields
+        .map(|field| {
+            if field.contains([',', '"', '\n', '\r']) || field.trim() != field {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect::<Vec<_>>();
+    format!("{}\n", fields.join(","))
+}
+
+/// `e2`が`e10`より前に来るように、数字の並びは数として比べる
+fn natural_key(id: &str) -> Vec<(String, u64)> {
+    let mut key = vec![];
+    let mut text = String::new();
+    let mut chars = id.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() {
+            let mut digits = c.to_string();
+            while let Some(d) = chars.next_if(char::is_ascii_digit) {
+                digits.push(d);
+            }
+            key.push((std::mem::take(&mut text), digits.parse().unwrap_or(u64::MAX)));
+        } else {
+            text.push(c);
+        }
+    }
+    key.push((text, 0));
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use combine::{eof, EasyParser, Parser};
+
+    use crate::{eval::eval, instance::Instance, parser::query::query_parser, util::instance::data, workspace::Workspace};
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("otz-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_from_csv_dir() {
+        let ws = Workspace::new("example");
+        let i = ws.instance("i").unwrap();
+        let imported = Instance::from_csv_dir(&ws, &i.schema, "example/csv/i").unwrap();
+        assert_eq!(data(&imported), data(&i));
+        assert_eq!(imported.generators().count(), i.generators().count());
+        // 同じ名前でも、iの生成元とは別の生成元になる
+        assert!(imported.generators().all(|g| i.generators().all(|h| h.id != g.id)));
+    }
+
+    #[test]
+    fn test_from_csv_dir_errors() {
+        let ws = Workspace::new("example");
+        let schema = ws.schema("s").unwrap();
+        let dir = temp_dir("csv-errors");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("Emp.csv"), "id,last,sal\ne1,Gauss,250\ne2,Noether,2.5\n").unwrap();
+        let error = Instance::from_csv_dir(&ws, &schema, &dir).err().unwrap();
+        assert_eq!(error, format!("{}:3: sal!e2: cannot read '2.5' as Int", dir.join("Emp.csv").display()));
+
+        // YAMLと違い、`?`のない識別子はnullにしない
+        std::fs::write(dir.join("Emp.csv"), "id,last,sal\ne1,Gauss,250\ne2,Noether,abc\n").unwrap();
+        let error = Instance::from_csv_dir(&ws, &schema, &dir).err().unwrap();
+        assert_eq!(error, format!("{}:3: sal!e2: cannot read 'abc' as Int", dir.join("Emp.csv").display()));
+
+        std::fs::write(dir.join("Emp.csv"), "id,last\ne1,Gauss,250\n").unwrap();
+        let error = Instance::from_csv_dir(&ws, &schema, &dir).err().unwrap();
+        assert!(error.ends_with("Emp.csv:2: expected 2 fields, found 3"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_to_csv_dir() {
+        let ws = Workspace::new("example");
+        let i = ws.instance("i").unwrap().saturate();
+        let dir = temp_dir("csv-export");
+        i.to_csv_dir(&dir).unwrap();
+        let emp = std::fs::read_to_string(dir.join("Emp.csv")).unwrap();
+        assert!(emp.starts_with("id,mgr,wrk,last,sal\ne1,e1,d3,Gauss,250\ne2,e4,d2,Noether,200\n"));
+        assert!(emp.ends_with("e7,e7,d2,,\n"));
+        // constraintから導いたe7の値も書くので、読み戻すと元のデータより増える
+        let exported = Instance::from_csv_dir(&ws, &i.schema, &dir).unwrap();
+        let added = data(&exported).difference(&data(&i)).cloned().collect::<Vec<_>>();
+        assert_eq!(added, ["mgr!e7 = e7", "wrk!e7 = d2"]);
+
+        // queryの結果も、行き先のschemaのinstanceとして書ける
+        let (instance, query) = ws.query("blocks").unwrap();
+        let result = eval(instance, query).unwrap().saturate();
+        let dir = temp_dir("csv-eval");
+        result.to_csv_dir(&dir).unwrap();
+        let exported = Instance::from_csv_dir(&ws, &result.schema, &dir).unwrap();
+        assert_eq!(data(&exported), data(&result));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // ブロックのないqueryの結果は、`#target`のentityの表に書く
+        let (instance, query) = ws.query("_").unwrap();
+        let result = eval(instance, query).unwrap().saturate();
+        let dir = temp_dir("csv-eval-rows");
+        result.to_csv_dir(&dir).unwrap();
+        let rows = std::fs::read_to_string(dir.join("Row.csv")).unwrap();
+        assert!(rows.starts_with("id,boss,name\nRow[e -> e2],Row[e -> e4],Noether\n"));
+        let exported = Instance::from_csv_dir(&ws, &result.schema, &dir).unwrap();
+        assert_eq!(data(&exported), data(&result));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // `#target`がなければ、行はどのentityにも属さないので書かない
+        let q = query_parser::<combine::easy::Stream<&str>>(&ws).skip(eof()).easy_parse("#instance i\n#for e: Emp\n").unwrap().0;
+        let result = eval(ws.instance("i").unwrap(), q).unwrap().saturate();
+        let dir = temp_dir("csv-eval-no-target");
+        assert!(result.to_csv_dir(&dir).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_csv_round_trip_many_rows() {
+        let ws = Workspace::new("example");
+        let schema = ws.schema("s").unwrap();
+        let dir = temp_dir("csv-many");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // 部署d0..d9と、e1..e10を部署の長とする300人。クォートの要る名前とnullも混ぜる
+        let mut emp = String::from("id,mgr,wrk,last,sal\n");
+        for k in 1..=300 {
+            let head = (k - 1) % 10 + 1;
+            let sal = match k {
+                _ if k <= 10 => "1000".to_string(),
+                _ if k % 50 == 0 => format!("?n{}", k),
+                _ => (100 + k).to_string(),
+            };
+            emp += &format!("e{},e{},d{},\"Name, {}\",{}\n", k, head, k % 10, k, sal);
+        }
+        let dept = (0..10).map(|j| format!("d{},Dept {},e{}\n", j, j, j + 10)).collect::<String>();
+        std::fs::write(dir.join("Emp.csv"), emp).unwrap();
+        std::fs::write(dir.join("Dept.csv"), format!("id,name,sec\n{}", dept)).unwrap();
+
+        let imported = Instance::from_csv_dir(&ws, &schema, &dir).unwrap();
+        assert_eq!(imported.generators().count(), 310);
+        assert_eq!(imported.nulls().count(), 6);
+
+        let saturated = imported.saturate();
+        let exported = temp_dir("csv-many-export");
+        saturated.to_csv_dir(&exported).unwrap();
+        let emp = std::fs::read_to_string(exported.join("Emp.csv")).unwrap();
+        assert!(emp.starts_with("id,mgr,wrk,last,sal\ne1,e1,d1,\"Name, 1\",1000\n"));
+        assert!(emp.contains("\ne50,e10,d0,\"Name, 50\",?n50\n"));
+        assert!(emp.ends_with("\ne300,e10,d0,\"Name, 300\",?n300\n"));
+
+        let reimported = Instance::from_csv_dir(&ws, &schema, &exported).unwrap();
+        assert_eq!(data(&reimported), data(&saturated));
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::remove_dir_all(&exported).unwrap();
+    }
+}
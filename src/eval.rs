@@ -1,7 +1,7 @@
-use std::{collections::HashMap, rc::Rc};
+use std::{collections::BTreeMap, rc::Rc};
 
 use crate::{
-    completion::subst::{Subst, Var}, context::Context, equation::Equation, id::{OperId, TypeId, VarId}, instance::{Elem, Instance}, symbol_table::Names, term::{Term, TermInner}
+    completion::{rule::Rule, subst::{Subst, Var}}, context::Context, equation::Equation, id::{GenId, OperId, Symbol, TypeId, VarId}, aggregate::Aggregate, instance::{collect_gens, replace_gens, Elem, Generator, Instance}, predicate::{Predicate, Truth}, r#type::Type, saturate::{Carrier, MAX_CARRIER}, schema::Schema, symbol_table::Names, term::{Term, TermInner}
 };
 
 /// A tableau over a schema S is a pair of:
@@ -17,192 +17,543 @@ use crate::{
 /// section 4.3.1.
 
 #[derive(Default)]
-pub struct Query(pub Vec<QueryEntity>);
+pub struct Query {
+    /// 問い合わせるinstanceの名前
+    pub instance: Option<String>,
+    /// `#target`で宣言した出力のschema。ないときは元のschemaのまま出力する
+    pub target: Option<Schema>,
+    pub entities: Vec<QueryEntity>,
+    /// 出力のentity・attr・fkeyと、queryの変数の名前
+    pub names: Rc<Names>,
+}
 
 #[derive(Default, Debug)]
 pub struct QueryEntity {
-    pub entity: Vec<TypeId>,
+    pub instance: Option<String>,
+    /// `#entity`で宣言した出力のentity。ブロックのないqueryではNone
+    pub entity: Option<TypeId>,
     pub fr: Vec<Context>,
-    pub wh: Vec<Equation>,
+    pub wh: Vec<Predicate>,
     pub ret: Vec<(OperId, TermInner)>,
+    /// `#return total := sum[...] ...`の集約
+    pub aggs: Vec<(OperId, Aggregate)>,
     // keys: t -> t'
     // transform from tableau for t' to tableau for t
-    pub keys: Vec<(OperId, VarId, TermInner)>,
+    // t'はtransformの変数を`#for`で束縛するブロックのentity
+    pub keys: Vec<(OperId, Option<TypeId>, Transform)>,
+}
+
+/// `#keys`の`[d -> wrk!e, ...]`
+pub type Transform = Vec<(VarId, TermInner)>;
+
+/// 出力のschemaと合わないqueryの宣言
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum QueryError {
+    /// `#entity`のentityが出力のschemaにない
+    UnknownEntity { entity: String },
+    /// `#return`のattrが出力のschemaのそのentityのattrにない
+    UnknownAttr { entity: String, attr: String },
+    /// `#keys`のfkeyが出力のschemaのそのentityからのfkeyにない
+    UnknownFkey { entity: String, fkey: String },
+    /// `#return`/`#keys`の項のsortが宣言と合わない
+    IllTyped { oper: String, expected: String, found: String },
+    /// 集約する値がnullや値のないattrで決まらない
+    UnknownAggregated { attr: String, row: String, value: String },
+    /// 集約できない値(数でない値のsum、数と文字列の混ざったmin/maxなど)や桁あふれ
+    NotAggregable { attr: String, func: String, value: String },
+    /// fkeyをたどっても台集合が閉じず、`#for`の変数を列挙しきれない
+    UnboundedCarrier { entity: String },
+    /// 定数として書いた生成元が、問い合わせるinstanceにない
+    UnknownGen { gen: String },
+    /// ブロックも`#target`もないqueryの`#return`/`#keys`。行はどのentityにも属さないので値を持てない
+    NoTarget { oper: String },
+}
+
+impl std::fmt::Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryError::UnknownEntity { entity } => write!(f, "#entity {} is not an entity of the target schema", entity),
+            QueryError::UnknownAttr { entity, attr } => {
+                write!(f, "#return {}: the target schema has no attribute {}: {} -> _", attr, attr, entity)
+            }
+            QueryError::UnknownFkey { entity, fkey } => {
+                write!(f, "#keys {}: the target schema has no foreign key {}: {} -> _", fkey, fkey, entity)
+            }
+            QueryError::IllTyped { oper, expected, found } => {
+                write!(f, "{}: expected {}, found {}", oper, expected, found)
+            }
+            QueryError::UnknownAggregated { attr, row, value } => {
+                write!(f, "#return {}: cannot aggregate the unknown value {} at [{}]", attr, value, row)
+            }
+            QueryError::NotAggregable { attr, func, value } => {
+                write!(f, "#return {}: {} cannot aggregate {}", attr, func, value)
+            }
+            QueryError::UnboundedCarrier { entity } => {
+                write!(f, "#for: {} has more than {} elements along foreign keys", entity, MAX_CARRIER)
+            }
+            QueryError::UnknownGen { gen } => write!(f, "generator {} is not declared in the instance", gen),
+            QueryError::NoTarget { oper } => {
+                write!(f, "{}: a query without #entity blocks needs a #target with one entity", oper)
+            }
+        }
+    }
+}
+
+impl QueryEntity {
+    /// `#where`/`#return`/`#keys`に定数として書かれた生成元
+    pub fn gens(&self) -> Vec<GenId> {
+        let preds = self.wh.iter().chain(self.aggs.iter().flat_map(|(_, agg)| &agg.wh));
+        let terms = preds
+            .flat_map(|pred| [pred.equation().left.as_ref(), pred.equation().right.as_ref()])
+            .chain(self.ret.iter().map(|(_, t)| t))
+            .chain(self.keys.iter().flat_map(|(_, _, transform)| transform.iter().map(|(_, t)| t)))
+            .chain(self.aggs.iter().filter_map(|(_, agg)| agg.term.as_ref()));
+        let mut gens = vec![];
+        terms.for_each(|t| collect_gens(t, &mut gens));
+        gens
+    }
+
+    /// 定数として書かれた生成元を`gens`に従って置き換える
+    pub fn replace_gens(&mut self, gens: &BTreeMap<GenId, Rc<TermInner>>) {
+        let replace = |t: &mut TermInner| *t = replace_gens(&Rc::new(t.clone()), gens).as_ref().clone();
+        self.wh = self.wh.iter().map(|pred| pred.replace_gens(gens)).collect();
+        self.ret.iter_mut().for_each(|(_, t)| replace(t));
+        self.keys
+            .iter_mut()
+            .for_each(|(_, _, transform)| transform.iter_mut().for_each(|(_, t)| replace(t)));
+        for (_, agg) in self.aggs.iter_mut() {
+            agg.wh = agg.wh.iter().map(|pred| pred.replace_gens(gens)).collect();
+            agg.term.iter_mut().for_each(replace);
+        }
+    }
+}
+
+impl Query {
+    /// 定数として書かれた生成元を、名前で`instance`の生成元に読み替える
+    /// 生成元はinstanceごとに採番されるので、評価する前に読み替える。
+    pub fn localize(&mut self, instance: &Instance) -> Result<(), Vec<QueryError>> {
+        let mut gens = BTreeMap::new();
+        let mut errors = vec![];
+        for gid in self.entities.iter().flat_map(QueryEntity::gens) {
+            let name = self.names.gen_name(&gid).unwrap_or("?");
+            match instance.names.gen_id(name) {
+                Some(local) => {
+                    gens.insert(gid, Rc::new(TermInner::Gen(local)));
+                }
+                None => {
+                    let error = QueryError::UnknownGen { gen: name.to_string() };
+                    if !errors.contains(&error) {
+                        errors.push(error);
+                    }
+                }
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        self.entities.iter_mut().for_each(|query_entity| query_entity.replace_gens(&gens));
+        Ok(())
+    }
+
+    /// 各ブロックの`#return`/`#keys`が`#target`のattr/fkeyとsortまで一致するか調べる
+    /// ブロックも`#target`もないqueryの行は元のschemaのどのentityにも属さないので、`#return`/`#keys`はすべて誤りとする。
+    pub fn check_target(&self, instance: &Instance) -> Vec<QueryError> {
+        let names = &self.names;
+        let sort_name = |tid: &TypeId| names.sort_name(tid).unwrap_or("?").to_string();
+        let oper_name = |oid: &OperId| names.oper_name(oid).unwrap_or("?").to_string();
+        let mut errors = vec![];
+
+        let Some(target) = &self.target else {
+            for qe in self.entities.iter().filter(|qe| qe.entity.is_none()) {
+                let returns = qe.ret.iter().map(|(oid, _)| oid).chain(qe.aggs.iter().map(|(oid, _)| oid));
+                errors.extend(returns.map(|oid| QueryError::NoTarget { oper: format!("#return {}", oper_name(oid)) }));
+                errors.extend(qe.keys.iter().map(|(oid, _, _)| QueryError::NoTarget { oper: format!("#keys {}", oper_name(oid)) }));
+            }
+            return errors;
+        };
+
+        for qe in &self.entities {
+            let Some(entity) = &qe.entity else {
+                continue;
+            };
+            let dom = Type::Unary(entity.clone());
+            if !target.entities.contains(&dom) {
+                errors.push(QueryError::UnknownEntity { entity: sort_name(entity) });
+                continue;
+            }
+
+            // 集約の値のsortはcountならInt、それ以外は集約する項のsort
+            let returns = qe.ret.iter().map(|(operid, term)| (operid, term_sort(instance, &qe.fr, term)));
+            let aggs = qe.aggs.iter().map(|(operid, agg)| {
+                let found = match &agg.term {
+                    None => instance.names.type_id("Int"),
+                    Some(term) => term_sort(instance, &[qe.fr.as_slice(), std::slice::from_ref(&agg.vars)].concat(), term),
+                };
+                (operid, found)
+            });
+            for (operid, found) in returns.chain(aggs) {
+                let Some(attr) = target.attrs.iter().find(|op| &op.id == operid && op.dom.as_ref() == &dom) else {
+                    errors.push(QueryError::UnknownAttr { entity: sort_name(entity), attr: oper_name(operid) });
+                    continue;
+                };
+                if let (Type::Unary(expected), Some(found)) = (attr.cod.as_ref(), found) {
+                    if expected != &found {
+                        errors.push(QueryError::IllTyped {
+                            oper: format!("#return {}", oper_name(operid)),
+                            expected: sort_name(expected),
+                            found: sort_name(&found),
+                        });
+                    }
+                }
+            }
+
+            for (operid, cod, transform) in &qe.keys {
+                let fkey = target.fkeys.iter().find(|op| &op.id == operid && op.dom.as_ref() == &dom);
+                let Some(fkey) = fkey else {
+                    errors.push(QueryError::UnknownFkey { entity: sort_name(entity), fkey: oper_name(operid) });
+                    continue;
+                };
+                let Type::Unary(expected) = fkey.cod.as_ref() else {
+                    continue;
+                };
+                if let Some(cod) = cod.as_ref().filter(|cod| cod != &expected) {
+                    errors.push(QueryError::IllTyped {
+                        oper: format!("#keys {}", oper_name(operid)),
+                        expected: sort_name(expected),
+                        found: sort_name(cod),
+                    });
+                    continue;
+                }
+                // 変換の各項は、行き先のブロックの変数のsortを持つ
+                let target_fr = self
+                    .entities
+                    .iter()
+                    .find(|other| other.entity.as_ref() == Some(expected))
+                    .map(|other| other.fr.as_slice())
+                    .unwrap_or_default();
+                for (varid, term) in transform {
+                    let expected = var_sort(target_fr, varid);
+                    let found = term_sort(instance, &qe.fr, term);
+                    if let (Some(expected), Some(found)) = (expected, found) {
+                        if expected != found {
+                            errors.push(QueryError::IllTyped {
+                                oper: format!("#keys {}", oper_name(operid)),
+                                expected: sort_name(&expected),
+                                found: sort_name(&found),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        errors
+    }
+}
+
+fn var_sort(fr: &[Context], varid: &VarId) -> Option<TypeId> {
+    fr.iter().find_map(|context| match context.0.get(varid)? {
+        Type::Unary(tid) => Some(tid.clone()),
+        _ => None,
+    })
+}
+
+/// `#for`の文脈での元のschemaの項のsort(分からなければNone)
+fn term_sort(instance: &Instance, fr: &[Context], t: &TermInner) -> Option<TypeId> {
+    match t {
+        TermInner::Var(varid) => var_sort(fr, varid),
+        TermInner::Fun(oid, _) => match instance.oper(oid)?.cod.as_ref() {
+            Type::Unary(tid) => Some(tid.clone()),
+            _ => None,
+        },
+        TermInner::Lit(lit) => instance.names.type_id(lit.sort().name()),
+        TermInner::Gen(gid) => instance
+            .generators()
+            .chain(instance.nulls())
+            .find(|g| &g.id == gid)
+            .map(|g| g.entity.clone()),
+        _ => None,
+    }
 }
 
-pub fn eval(instance: Instance, query: Query) -> Instance {
+/// 出力の生成元1つ
+/// `#entity`のブロックでは新しい生成元、ブロックのないqueryでは代入そのものを項として使う。
+struct Row {
+    subst: Subst,
+    term: Rc<TermInner>,
+}
+
+/// queryを評価する。`#target`があれば、出力のinstanceはそのschemaを持つ
+pub fn eval(instance: Instance, mut query: Query) -> Result<Instance, Vec<QueryError>> {
+    query.localize(&instance)?;
+    let errors = query.check_target(&instance);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
     let saturated = instance.saturate();
+    // 生成元を同一視する前の規則なので、#whereに書いた同一視された生成元も正規形に書き換わる
+    let deduction = instance.deduction_rules();
+    let builtins = &instance.schema.theory.builtins;
+    let normalize = |inner: Rc<TermInner>| {
+        let term = Term {
+            context: Rc::new(Context::default()),
+            names: Rc::new(Names::default()),
+            inner,
+        };
+        term.normalize_with(&deduction, builtins).inner.clone()
+    };
+
+    let mut names = instance.names.as_ref().clone();
+    // queryの名前の表にある生成元はworkspaceの番号なので、instanceの生成元の名前を上書きしないよう除く
+    names.extend(
+        query
+            .names
+            .entries()
+            .into_iter()
+            .filter(|(_, symbol)| !matches!(symbol, Symbol::Gen(_)))
+            .collect(),
+    );
+    let mut next_gen = names.next_gen_id();
+    let carrier = saturated.carrier(&deduction).map_err(|entity| {
+        vec![QueryError::UnboundedCarrier { entity: instance.names.sort_name(&entity).unwrap_or("?").to_string() }]
+    })?;
 
     // generator
-    let substs = query.0.iter().map(|query_entity| {
-        eval_generators(&saturated, query_entity)
+    let rows = query.entities.iter().map(|query_entity| {
+        eval_generators(&saturated, &deduction, &carrier, &query_entity.fr, &query_entity.wh).into_iter().map(|subst| {
+            let term = match &query_entity.entity {
+                Some(entity) => {
+                    let gid = GenId(next_gen);
+                    next_gen += 1;
+                    let name = row_name(&names, entity, &subst);
+                    names.insert(name.clone(), Symbol::Gen(gid.clone()));
+                    Rc::new(TermInner::Gen(gid))
+                }
+                None => Rc::new(TermInner::Subst(subst.0.iter().map(|(varid, inner)| {
+                    (varid.clone(), inner.clone())
+                }).collect())),
+            };
+            Row { subst, term }
+        }).collect::<Vec<_>>()
     })
-    // .inspect(|subst| { dbg!(&subst); })
     .collect::<Vec<_>>();
 
-    let elems = substs.iter().flatten().map(|s| {
-        Elem::Subst(s.clone())
+    let names = &names;
+    let elems = query.entities.iter().zip(&rows).flat_map(|(qe, rows)| {
+        rows.iter().map(move |row| match (&qe.entity, row.term.as_ref()) {
+            (Some(entity), TermInner::Gen(gid)) => {
+                let name = names.gen_name(gid).unwrap_or_default().to_string();
+                Elem::Gen(Generator::new(gid.clone(), name, entity.clone()))
+            }
+            _ => Elem::Subst(row.subst.clone()),
+        })
     }).collect::<Vec<_>>();
+    // 値にnullが残ることがあるので、元のinstanceのnullを引き継ぐ
+    let nulls = instance.nulls().cloned().map(Elem::Null).collect::<Vec<_>>();
 
     // attの処理
-    let attrs = query.0.iter().map(|qe| {
-        qe.ret.iter().map(|(operid, term)| {
-            // dbg!(operid, term);
-            let attrs = elems.iter().filter_map(|e| {
-                if let Elem::Subst(subst) = e {
-                    let substed_term = term.substitute(subst);
-                    let subst_vec = subst.0.iter().map(|(varid, inner)| {
-                        (varid.clone(), inner.clone())
-                    }).collect::<Vec<_>>();
-
-                    // TODO: to_ruleが左->右であることを仮定している
-                    let rules = instance.data.iter().map(Equation::to_rule).collect::<Vec<_>>();
-                    let right_term = Term {
-                        context: Rc::new(Context::default()),
-                        names: Rc::new(Names::default()),
-                        inner: substed_term.clone(),
-                    };
-                    let right = right_term.normalize(&rules);
-                    Some(Equation {
-                        context: Rc::new(Context::default()),
-                        names: instance.names.clone(),
-                        left: Rc::new(TermInner::Fun(
-                            operid.clone(),
-                            vec![
-                                Rc::new(TermInner::Subst(subst_vec))
-                            ],
-                        )),
-                        right: right.inner.clone(),
-                    })
-                } else {
-                    None
+    let attrs = query.entities.iter().zip(&rows).flat_map(|(qe, rows)| {
+        qe.ret.iter().flat_map(|(operid, term)| {
+            rows.iter().filter_map(|row| {
+                let right = normalize(term.substitute(&row.subst));
+                // 元のinstanceで値のないfkey/attrが残るなら、出力でも値を与えない
+                if has_no_value(&instance, &right) {
+                    return None;
                 }
-            }).collect::<Vec<_>>();
-            // dbg!(&attrs);
-            attrs
-        }).flatten().collect::<Vec<_>>()
-    }).flatten().collect::<Vec<_>>();
-    
+                Some(Equation {
+                    context: Rc::new(Context::default()),
+                    names: instance.names.clone(),
+                    left: Rc::new(TermInner::Fun(operid.clone(), vec![row.term.clone()])),
+                    right,
+                })
+            }).collect::<Vec<_>>()
+        }).collect::<Vec<_>>()
+    }).collect::<Vec<_>>();
+
     // foreign keyの処理
-    let keys = query.0.iter().map(|qe| {
-        qe.keys.iter().map(|(operid, varid, term)| {
-            let keys = elems.iter().filter_map(|e| {
-                if let Elem::Subst(subst) = e {
-                    let substed_term = term.substitute(subst);
-                    let subst_vec = subst.0.iter().map(|(varid, inner)| {
-                        (varid.clone(), inner.clone())
-                    }).collect::<Vec<_>>();
-
-                    let keys_map = HashMap::from([(
-                        Var::Id(varid.clone()),
-                        substed_term.clone(),
-                    )]);
-                    let keys_subst = Subst::new(keys_map);
-                    let right_subst = subst.compose(&keys_subst);
-                    let right_subst_vec = right_subst.0.iter().map(|(varid, inner)| {
-                        let term = Term {
-                            context: Rc::new(Context(HashMap::new())),
-                            names: Rc::new(HashMap::new()),
-                            inner: inner.clone(),
-                        };
-                        // TODO: to_ruleが左->右であることを仮定している
-                        let rules = instance.data.iter().map(Equation::to_rule).collect::<Vec<_>>();
-                        let inner = term.normalize(&rules).inner.clone();
-                        (varid.clone(), inner)
-                    }).collect::<Vec<_>>();
-
-                    Some(Equation {
-                        context: Rc::new(Context(HashMap::new())),
+    let keys = query.entities.iter().zip(&rows).flat_map(|(qe, own_rows)| {
+        qe.keys.iter().flat_map(|(operid, target, transform)| {
+            own_rows.iter().filter_map(|row| {
+                // 行き先のブロックの変数に、この生成元の代入で値を与える
+                let keys_map = transform.iter().map(|(varid, term)| {
+                    (Var::Id(varid.clone()), term.substitute(&row.subst))
+                }).collect::<BTreeMap<_, _>>();
+                let right = match target {
+                    Some(target) => {
+                        let key_subst = Subst::new(keys_map.into_iter().map(|(var, inner)| (var, normalize(inner))).collect());
+                        // 行き先の生成元がwhereで除かれていれば、値は与えない
+                        let (_, target_rows) = query.entities.iter().zip(&rows).find(|(qe, _)| qe.entity.as_ref() == Some(target))?;
+                        target_rows.iter().find(|r| r.subst == key_subst)?.term.clone()
+                    }
+                    None => {
+                        let right_subst = row.subst.compose(&Subst::new(keys_map));
+                        Rc::new(TermInner::Subst(right_subst.0.iter().map(|(varid, inner)| {
+                            (varid.clone(), normalize(inner.clone()))
+                        }).collect()))
+                    }
+                };
+                Some(Equation {
+                    context: Rc::new(Context(BTreeMap::new())),
+                    names: instance.names.clone(),
+                    left: Rc::new(TermInner::Fun(operid.clone(), vec![row.term.clone()])),
+                    right,
+                })
+            }).collect::<Vec<_>>()
+        }).collect::<Vec<_>>()
+    }).collect::<Vec<_>>();
+
+    // 集約の処理
+    let mut errors = vec![];
+    let mut aggs = vec![];
+    for (qe, rows) in query.entities.iter().zip(&rows) {
+        for (operid, agg) in &qe.aggs {
+            let attr = names.oper_name(operid).unwrap_or("?");
+            for row in rows {
+                match agg.eval(&saturated, &deduction, &carrier, names, attr, &row.subst) {
+                    Ok(Some(lit)) => aggs.push(Equation {
+                        context: Rc::new(Context::default()),
                         names: instance.names.clone(),
-                        left: Rc::new(TermInner::Fun(
-                            operid.clone(),
-                            vec![
-                                Rc::new(TermInner::Subst(subst_vec))
-                            ],
-                        )),
-                        right: Rc::new(TermInner::Subst(right_subst_vec)),
-                    })
-                } else {
-                    None
+                        left: Rc::new(TermInner::Fun(operid.clone(), vec![row.term.clone()])),
+                        right: Rc::new(TermInner::Lit(lit)),
+                    }),
+                    Ok(None) => {}
+                    Err(error) => errors.push(error),
                 }
-            }).collect::<Vec<_>>();
-            keys
-        }).flatten().collect::<Vec<_>>()
-    }).flatten().collect::<Vec<_>>();
-                    
-    let data = [attrs, keys].concat();
+            }
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let data = [attrs, keys, aggs].concat();
+    let names = Rc::new(names.clone());
+    let data = data.into_iter().map(|eq| Equation { names: names.clone(), ..eq }).collect();
 
-    Instance {
-        names: instance.names.clone(),
-        schema: instance.schema,
-        elems: elems,
-        data: data,
+    Ok(Instance {
+        names,
+        schema: query.target.unwrap_or(instance.schema),
+        elems: [elems, nulls].concat(),
+        data,
+        lines: BTreeMap::new(),
+    })
+}
+
+/// 元のschemaのfkey/attrを含む項か(nullは値が分からないだけなので含めない)
+fn has_no_value(instance: &Instance, t: &TermInner) -> bool {
+    match t {
+        TermInner::Fun(oid, args) => {
+            instance.schema.fkeys.iter().chain(&instance.schema.attrs).any(|op| &op.id == oid)
+                || args.iter().any(|arg| has_no_value(instance, arg))
+        }
+        _ => false,
     }
 }
 
+/// `Result[e -> e2, d -> d2]`のような、出力の生成元の名前
+fn row_name(names: &Names, entity: &TypeId, subst: &Subst) -> String {
+    format!("{}[{}]", names.sort_name(entity).unwrap_or("?"), assigns_name(names, subst))
+}
+
+/// 代入を`e -> e2, d -> d2`のように表示する
+pub(crate) fn assigns_name(names: &Names, subst: &Subst) -> String {
+    subst
+        .0
+        .iter()
+        .map(|(var, inner)| {
+            let var = match var {
+                Var::Id(vid) => names.var_name(vid).map(str::to_string).unwrap_or_else(|| format!("v{}", vid.0)),
+                _ => format!("{:?}", var),
+            };
+            let value = match inner.as_ref() {
+                TermInner::Gen(gid) => names.gen_name(gid).map(str::to_string).unwrap_or_else(|| format!("g{}", gid.0)),
+                _ => Term { context: Rc::new(Context::default()), names: Rc::new(names.clone()), inner: inner.clone() }.to_string(),
+            };
+            format!("{} -> {}", var, value)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 /// define the generators of entity tin eval(Q)(I) to be those I_EA environments for fr(t) which satisfy wh(t).
 /// t: entity
 /// fr(t) := {−−−→ v_i : s_i}:
 /// eval(Q)(I)(t) := { [−−−−→v_i→e_i] | I⊢eq[−−−−→v_i→e_i], ∀eq ∈ wh(t), ∀e_i ∈ I_EA(s_i)}
-fn eval_generators(instance: &Instance, query_entity: &QueryEntity) -> Vec<Subst> {
-    query_entity
-        .fr
-        .iter()
-        .map(|context| {
-            let substs = instance
-                .elems
-                .iter().filter_map(|e| {
-                    // frからsubstを作る
-                    let init = HashMap::new();
-                    // for句のそれぞれのentityについて
-                    let subst = context
-                        .0
-                        .iter().try_fold(init, |mut subst, (varid, tp)| {
-                            if let Elem::Oper(e) = e {
-                                (e.cod.as_ref() == tp).then(|| {
-                                    subst.insert(
-                                        Var::Id(varid.clone()),
-                                        Rc::new(TermInner::Fun(e.id.clone(), vec![])),
-                                    );
-                                    subst
-                                })
-                            } else {
-                                None
-                            }
-
-                        })
-                        .map(|m| Subst::new(m));
-                    subst
-                })
-                .collect::<Vec<_>>();
-
-            // 一回Vecにせずに繋げた方が効率的だが分かりやすさのため一時的にこうする
-            let substs = substs
-                .iter()
-                .filter(|subst| {
-                    // すべての等式を満たす必要がある(all)
-                    query_entity.wh.iter().all(|eq| {
-                        let left_substed = eq.left_term().substitute(&subst);
-                        let right_substed = eq.right_term().substitute(&subst);
-
-                        let substituted_equation = Equation {
-                            context: left_substed.context,
-                            names: left_substed.names,
-                            left: left_substed.inner,
-                            right: right_substed.inner,
-                        };
-                        println!("substituted_equation: {}", substituted_equation);
-                        // substituted_equationがsaturatedから導けるかどうか
-                        // TODO: 同じ型だとcompleteが複数回走るので効率が悪い
-                        instance.deducible(&substituted_equation)
-                    })
-                })
-                .cloned()
-                .collect::<Vec<_>>();
-            substs
-        })
-        .flatten()
-        .collect()
+/// `rules`は`deduction_rules`、`carrier`はsaturateしたinstanceの台集合。集約の`[...]`の変数の列挙にも使う
+pub(crate) fn eval_generators(
+    instance: &Instance,
+    rules: &Vec<Rule>,
+    carrier: &Carrier,
+    fr: &[Context],
+    wh: &[Predicate],
+) -> Vec<Subst> {
+    // すべての#forの変数をまとめて一つの文脈として列挙する(同じ変数は最初の宣言を使う)
+    let mut vars: Vec<(VarId, Type)> = vec![];
+    for (varid, tp) in fr.iter().flat_map(|context| context.0.iter()) {
+        if vars.iter().all(|(v, _)| v != varid) {
+            vars.push((varid.clone(), tp.clone()));
+        }
+    }
+
+    // whereは必要な変数がすべて決まった時点で一度だけ調べ、満たさなければそれ以降の組み合わせを列挙しない
+    // ready[k]: k個目までの変数が決まると調べられる条件
+    let mut ready = vec![vec![]; vars.len() + 1];
+    for pred in wh {
+        let depth = pred
+            .vars()
+            .into_iter()
+            .map(|var| {
+                vars.iter()
+                    .position(|(varid, _)| Var::Id(varid.clone()) == var)
+                    .map_or(vars.len(), |i| i + 1)
+            })
+            .max()
+            .unwrap_or(0);
+        ready[depth].push(pred);
+    }
+
+    let mut generators = vec![];
+    assign(instance, rules, carrier, &vars, &ready, BTreeMap::new(), &mut generators);
+
+    // 実行ごとに出力が変わらないように生成元を整列しておく
+    generators.sort();
+    generators.dedup();
+    generators
+}
+
+/// 決まった変数の数がsubst.len()のときの探索
+fn assign(
+    instance: &Instance,
+    rules: &Vec<Rule>,
+    carrier: &Carrier,
+    vars: &[(VarId, Type)],
+    ready: &[Vec<&Predicate>],
+    subst: BTreeMap<Var, Rc<TermInner>>,
+    generators: &mut Vec<Subst>,
+) {
+    let depth = subst.len();
+    let current = Subst::new(subst.clone());
+    // すべての条件が真でなければならない(Unknownも満たさないとみなす)
+    let satisfied = ready[depth].iter().all(|pred| {
+        instance.decide(&pred.substitute(&current), rules) == Truth::True
+    });
+    if !satisfied {
+        return;
+    }
+
+    let Some((varid, tp)) = vars.get(depth) else {
+        generators.push(current);
+        return;
+    };
+    // entityの台集合(生成元とfkeyの項の正規形)から選ぶ
+    let Type::Unary(entity) = tp else {
+        return;
+    };
+    for t in carrier.get(entity).into_iter().flatten() {
+        let mut subst = subst.clone();
+        subst.insert(Var::Id(varid.clone()), t.clone());
+        assign(instance, rules, carrier, vars, ready, subst, generators);
+    }
 }
 
 impl TermInner {
@@ -210,3 +561,347 @@ impl TermInner {
         TermInner::Var(varid)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use combine::{eof, stream::position::Stream, EasyParser, Parser};
+    use rstest::rstest;
+
+    use crate::{
+        eval::{eval, Query, QueryError},
+        id::GenId,
+        instance::Elem,
+        parser::query::query_parser,
+        r#type::Type,
+        term::TermInner,
+        workspace::Workspace,
+    };
+
+    fn query(ws: &Workspace, input: &str) -> Query {
+        query_parser::<combine::easy::Stream<&str>>(ws)
+            .skip(eof())
+            .easy_parse(input)
+            .unwrap()
+            .0
+    }
+
+    /// ブロックのないqueryの`#target`。行をentity `Row`の生成元とし、attrを1つ持つ
+    fn row_target(attr: &str, sort: &str) -> String {
+        format!("#target {{\n#theory \"test\"\n#sort Row\n#attr {}: Row -> {}\n}}\n", attr, sort)
+    }
+
+    #[test]
+    fn test_eval_join() {
+        let ws = Workspace::new("example");
+        let q = query(&ws, "#instance i\n#for e: Emp d: Dept\n#where wrk!e = d\n#where name!d = \"Admin\"\n");
+        let queried = eval(ws.instance("i").unwrap(), q).unwrap();
+        let generators = queried
+            .elems
+            .iter()
+            .filter_map(|e| match e {
+                Elem::Subst(subst) => Some(
+                    subst
+                        .0
+                        .values()
+                        .map(|g| queried.names.gen_name(&g_id(g)).unwrap().to_string())
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                ),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        // e2, e4, e6がd2(Admin)で働いている。e7はconstraintから、上司のe6と同じd2で働いていると分かる
+        assert_eq!(generators, ["e2 d2", "e4 d2", "e6 d2", "e7 d2"]);
+    }
+
+    #[test]
+    fn test_eval_display_rows() {
+        let ws = Workspace::new("example");
+        let q = query(&ws, "#instance i\n#for e: Emp\n#where wrk!e = d2\n");
+        let queried = eval(ws.instance("i").unwrap(), q).unwrap();
+        // 代入の行は変数と生成元の名前で表示する
+        assert!(queried.to_string().contains("\n#subst e -> e2\n#subst e -> e4\n"));
+    }
+
+    #[test]
+    fn test_eval_unknown_gen() {
+        let ws = Workspace::new("example");
+        // d2はiの生成元なので、d2のないinstanceでは評価できない
+        let q = query(&ws, "#instance i\n#for e: Emp\n#where wrk!e = d2\n");
+        let input = "#schema s\n#elem e1: Emp\n#elem d1: Dept\n#data wrk!e1 = d1\n";
+        let instance = crate::parser::instance::instance_parser(&ws).skip(eof()).easy_parse(Stream::new(input)).unwrap().0;
+        assert_eq!(eval(instance, q).err(), Some(vec![QueryError::UnknownGen { gen: "d2".to_string() }]));
+    }
+
+    #[test]
+    fn test_eval_same_entity_pairs() {
+        let ws = Workspace::new("example");
+        let q = query(&ws, "#instance i\n#for e f: Emp\n#where mgr!e = f\n#where wrk!f = d2\n");
+        let queried = eval(ws.instance("i").unwrap(), q).unwrap();
+        // 上司がd2で働いている(e2->e4, e4->e4, e6->e7, e7->e7)
+        let count = queried.elems.iter().filter(|e| matches!(e, Elem::Subst(_))).count();
+        assert_eq!(count, 4);
+    }
+
+    #[test]
+    fn test_eval_entity_blocks() {
+        let ws = Workspace::new("example");
+        let (instance, q) = ws.query("blocks").unwrap();
+        let queried = eval(instance, q).unwrap();
+
+        let worker = ws.types.get("Worker").unwrap();
+        let division = ws.types.get("Division").unwrap();
+        let names = |entity| {
+            queried
+                .generators_of(entity)
+                .map(|g| g.name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&worker), ["Worker[e -> e2]", "Worker[e -> e4]", "Worker[e -> e6]", "Worker[e -> e7]"]);
+        assert_eq!(names(&division).len(), 3);
+
+        let data = queried
+            .data
+            .iter()
+            .map(|eq| format!("{} = {}", eq.left_term(), eq.right_term()))
+            .collect::<Vec<_>>();
+        assert!(data.contains(&"ename!Worker[e -> e4] = \"Turing\"".to_string()));
+        assert!(data.contains(&"dname!Division[d -> d1] = \"HR\"".to_string()));
+        // keysはWorkerの生成元からDivisionの生成元へのfkeyになる
+        assert!(data.contains(&"dept!Worker[e -> e6] = Division[d -> d2]".to_string()));
+    }
+
+    #[test]
+    fn test_eval_uses_target_schema() {
+        let ws = Workspace::new("example");
+        let (instance, q) = ws.query("blocks").unwrap();
+        let queried = eval(instance, q).unwrap();
+        let worker = Type::Unary(ws.types.get("Worker").unwrap());
+        assert!(queried.schema.entities.contains(&worker));
+        assert_eq!(queried.schema.attrs.len(), 2);
+        assert_eq!(queried.schema.fkeys.len(), 1);
+    }
+
+    #[test]
+    fn test_check_target() {
+        let ws = Workspace::new("example");
+        let parse = |input: &str| {
+            query_parser::<combine::easy::Stream<&str>>(&ws)
+                .skip(eof())
+                .easy_parse(input)
+                .unwrap()
+                .0
+        };
+        let instance = ws.instance("i").unwrap();
+
+        let q = parse("#instance i\n#target t\n#entity Worker {\n#for e: Emp\n#return ename := sal!e\n#return age := sal!e\n}\n");
+        let errors = q.check_target(&instance);
+        assert_eq!(
+            errors,
+            [
+                QueryError::IllTyped {
+                    oper: "#return ename".to_string(),
+                    expected: "Str".to_string(),
+                    found: "Int".to_string(),
+                },
+                QueryError::UnknownAttr { entity: "Worker".to_string(), attr: "age".to_string() },
+            ]
+        );
+        assert!(eval(instance.clone(), q).is_err());
+
+        // 集約の値のsortも調べる(countはInt、max last!eはStr)
+        let q = parse("#instance i\n#target t\n#entity Division {\n#for d: Dept\n#return dname := count[e: Emp | wrk!e = d]\n}\n");
+        assert!(matches!(&q.check_target(&instance)[..], [QueryError::IllTyped { found, .. }] if found == "Int"));
+        let q = parse("#instance i\n#target t\n#entity Division {\n#for d: Dept\n#return dname := max[e: Emp | wrk!e = d] last!e\n}\n");
+        assert!(q.check_target(&instance).is_empty());
+
+        let q = parse("#instance i\n#target t\n#entity Boss {\n#for e: Emp\n}\n");
+        assert_eq!(q.check_target(&instance), [QueryError::UnknownEntity { entity: "Boss".to_string() }]);
+
+        // fkeyの行き先の変数に、sortの違う項を渡している
+        let q = parse("#instance i\n#target t\n#entity Worker {\n#for e: Emp\n#keys dept := [d -> mgr!e]\n}\n#entity Division {\n#for d: Dept\n}\n");
+        assert_eq!(q.check_target(&instance).len(), 1);
+    }
+
+    #[test]
+    fn test_check_target_without_blocks() {
+        let ws = Workspace::new("example");
+        let instance = ws.instance("i").unwrap();
+        let parse = |input: &str| query_parser::<combine::easy::Stream<&str>>(&ws).skip(eof()).easy_parse(input).ok().map(|(q, _)| q);
+
+        // `#target`がなければ、行はどのentityにも属さないので値を返せない
+        let q = parse("#instance i\n#for e: Emp\n#return name := last!e\n#keys wrk := [d -> wrk!e]\n").unwrap();
+        assert_eq!(
+            q.check_target(&instance),
+            [
+                QueryError::NoTarget { oper: "#return name".to_string() },
+                QueryError::NoTarget { oper: "#keys wrk".to_string() },
+            ]
+        );
+        assert!(eval(instance.clone(), q).is_err());
+
+        // `#target`のただ1つのentityの生成元になり、attrのsortも調べる
+        let q = parse(&format!("#instance i\n{}#for e: Emp\n#return s := last!e\n", row_target("s", "Int"))).unwrap();
+        assert!(matches!(&q.check_target(&instance)[..], [QueryError::IllTyped { found, .. }] if found == "Str"));
+        let (instance, q) = ws.query("_").unwrap();
+        let queried = eval(instance, q).unwrap();
+        let row = ws.types.get("Row").unwrap();
+        assert_eq!(queried.generators_of(&row).count(), 4);
+        assert!(queried.elems.iter().all(|e| !matches!(e, Elem::Subst(_))));
+
+        assert!(parse("#instance i\n#target t\n#for e: Emp\n").is_none());
+    }
+
+    #[test]
+    fn test_query_keys_target_must_exist() {
+        let ws = Workspace::new("example");
+        let input = "#instance i\n#entity W {\n#for e: Emp\n#keys dept := [x -> wrk!e]\n}\n";
+        let result = query_parser::<combine::easy::Stream<&str>>(&ws)
+            .skip(eof())
+            .easy_parse(input);
+        assert!(result.is_err());
+    }
+
+    #[rstest]
+    #[case("sal!e = 200", &["e2"])]
+    #[case("last!e = \"Gauss\"", &["e1"])]
+    #[case("st![sal!e 200] = true", &["e2", "e5", "e6"])]
+    #[case("sal!mgr!e = 250", &["e1", "e5"])]
+    #[case("name!wrk!e = \"IT\"", &["e1", "e5"])]
+    // e7のwrk/mgrはデータにないが、上司のe6からconstraintで導ける
+    #[case("wrk!e = d2", &["e2", "e4", "e6", "e7"])]
+    #[case("mgr!e = e7", &["e6", "e7"])]
+    // e7はlastも給与も決まらないので、否定や比較ではUnknownとして除かれる
+    #[case("wrk!e != d2", &["e1", "e3", "e5"])]
+    #[case("not (mgr!e = e)", &["e2", "e5", "e6"])]
+    #[case("sal!e > 200", &["e1", "e3", "e4"])]
+    #[case("sal!e <= 150", &["e5", "e6"])]
+    #[case("not (sal!e = 200)", &["e1", "e3", "e4", "e5", "e6"])]
+    #[case("last!e < \"F\"", &["e3", "e6"])]
+    fn test_eval_where_attrs(#[case] wh: &str, #[case] expected: &[&str]) {
+        let ws = Workspace::new("example");
+        let q = query(&ws, &format!("#instance i\n#for e: Emp\n#where {}\n", wh));
+        let queried = eval(ws.instance("i").unwrap(), q).unwrap();
+        let generators = queried
+            .elems
+            .iter()
+            .filter_map(|e| match e {
+                Elem::Subst(subst) => subst.0.values().next().map(|g| queried.names.gen_name(&g_id(g)).unwrap().to_string()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(generators, expected);
+    }
+
+    #[rstest]
+    // wrk!sec!d = dから、d1 = wrk!e1 = d2が導けるので、d1とd2は1つの元になる
+    #[case("d != d2", &[])]
+    #[case("d = d2", &["d1"])]
+    fn test_eval_where_uses_constraints(#[case] wh: &str, #[case] expected: &[&str]) {
+        let ws = Workspace::new("example");
+        let input = "#schema s\n#elem e1: Emp\n#elem d1 d2: Dept\n#data wrk!e1 = d2\n#data sec!d1 = e1\n#data sec!d2 = e1\n";
+        let instance = crate::parser::instance::instance_parser(&ws).skip(eof()).easy_parse(Stream::new(input)).unwrap().0;
+        let queried = eval(instance, query(&ws, &format!("#instance i\n#for d: Dept\n#where {}\n", wh))).unwrap();
+        let generators = queried
+            .elems
+            .iter()
+            .filter_map(|e| match e {
+                Elem::Subst(subst) => subst.0.values().next().map(|g| queried.names.gen_name(&g_id(g)).unwrap().to_string()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(generators, expected);
+    }
+
+    #[rstest]
+    // pの所属wrk!pやその担当者sec!wrk!pは生成元ではないが、台集合の元として列挙する
+    #[case("#for e: Emp\n", &["p", "mgr!p", "sec!wrk!p", "mgr!sec!wrk!p"])]
+    #[case("#for d: Dept\n", &["wrk!p"])]
+    #[case("#for e: Emp\n#where wrk!e = wrk!p\n", &["p", "mgr!p", "sec!wrk!p", "mgr!sec!wrk!p"])]
+    #[case("#for e: Emp\n#where mgr!e = e\n", &["mgr!p", "mgr!sec!wrk!p"])]
+    fn test_eval_enumerates_fkey_terms(#[case] input: &str, #[case] expected: &[&str]) {
+        let ws = Workspace::new("example");
+        let q = query(&ws, &format!("#instance link\n{}", input));
+        let queried = eval(ws.instance("link").unwrap(), q).unwrap();
+        let mut rows = queried
+            .elems
+            .iter()
+            .filter_map(|e| match e {
+                Elem::Subst(subst) => subst.0.values().next().map(|t| {
+                    crate::term::Term { context: Default::default(), names: queried.names.clone(), inner: t.clone() }.to_string()
+                }),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        rows.sort_by_key(|row| (row.len(), row.clone()));
+        assert_eq!(rows, expected);
+    }
+
+    fn g_id(t: &TermInner) -> GenId {
+        match t {
+            TermInner::Gen(gid) => gid.clone(),
+            _ => panic!("not a generator: {:?}", t),
+        }
+    }
+
+    #[test]
+    fn test_eval_propagates_nulls() {
+        let ws = Workspace::new("example");
+        let instance = ws.instance("nulls").unwrap();
+        let q = query(&ws, &format!("#instance nulls\n{}#for e: Emp\n#where mgr!e = e7\n#return s := sal!e\n", row_target("s", "Int")));
+        let queried = eval(instance, q).unwrap();
+        // e6とe7の上司はe7。e6自身の給与は分かるが、e7の給与はnullのまま
+        let values = queried
+            .data
+            .iter()
+            .map(|eq| eq.right_term().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(values, ["150", "?x"]);
+
+        let q = query(&ws, &format!("#instance nulls\n{}#for e: Emp\n#where mgr!e = e7\n#return s := sal!mgr!e\n", row_target("s", "Int")));
+        let queried = eval(ws.instance("nulls").unwrap(), q).unwrap();
+        let values = queried
+            .data
+            .iter()
+            .map(|eq| eq.right_term().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(values, ["?x", "?x"]);
+        assert_eq!(queried.nulls().count(), 1);
+    }
+    // 部署ごとの集約。e7はd2で働いているが、給与も名前もないので、値を集約するときは`sal!e >= 0`で除く
+    #[rstest]
+    #[case("sum[e: Emp | wrk!e = d, sal!e >= 0] sal!e", "Int", &["300", "750", "350"])]
+    #[case("count[e: Emp | wrk!e = d]", "Int", &["1", "4", "2"])]
+    #[case("max[e: Emp | wrk!e = d, sal!e >= 0] sal!e", "Int", &["300", "400", "250"])]
+    #[case("min[e: Emp | wrk!e = d, sal!e >= 0] last!e", "Str", &["\"Einstein\"", "\"Euclid\"", "\"Gauss\""])]
+    #[case("count[e: Emp | wrk!e = d, sal!e >= 200]", "Int", &["1", "2", "1"])]
+    #[case("min[e: Emp | wrk!e = d, sal!e > 1000] sal!e", "Int", &[])]
+    fn test_eval_aggregate(#[case] agg: &str, #[case] sort: &str, #[case] expected: &[&str]) {
+        let ws = Workspace::new("example");
+        let q = query(&ws, &format!("#instance i\n{}#for d: Dept\n#return v := {}\n", row_target("v", sort), agg));
+        let queried = eval(ws.instance("i").unwrap(), q).unwrap();
+        let values = queried
+            .data
+            .iter()
+            .map(|eq| eq.right_term().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(values, expected);
+    }
+
+    #[test]
+    fn test_eval_aggregate_rejects_nulls() {
+        let ws = Workspace::new("example");
+        // e7の給与は?xなので合計は決まらない
+        let q = query(&ws, &format!("#instance nulls\n{}#for d: Dept\n#where name!d = \"HR\"\n#return total := sum[e: Emp] sal!e\n", row_target("total", "Int")));
+        let Err(errors) = eval(ws.instance("nulls").unwrap(), q) else {
+            panic!("aggregation over a null must fail");
+        };
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(&errors[0], QueryError::UnknownAggregated { value, .. } if value == "?x"));
+
+        // 条件で除けば集約できる
+        let q = query(&ws, &format!("#instance nulls\n{}#for d: Dept\n#where name!d = \"HR\"\n#return total := sum[e: Emp | sal!e >= 0] sal!e\n", row_target("total", "Int")));
+        let queried = eval(ws.instance("nulls").unwrap(), q).unwrap();
+        assert_eq!(queried.data[0].right_term().to_string(), "1400");
+    }
+}
@@ -0,0 +1,82 @@
+use crate::literal::Literal;
+
+/// Rustで実装するtypesideの演算
+/// `#func`で宣言した演算子に`Theory::register_external`で登録すると、
+/// 引数がすべてリテラルになったところで正規化の途中に呼ばれる。
+pub trait ExternalFn {
+    fn call(&self, args: &[Literal]) -> Result<Literal, String>;
+}
+
+impl<F> ExternalFn for F
+where
+    F: Fn(&[Literal]) -> Result<Literal, String>,
+{
+    fn call(&self, args: &[Literal]) -> Result<Literal, String> {
+        self(args)
+    }
+}
+
+/// 評価中に外部関数が返したエラー
+/// エラーになった項は評価せずにそのまま残す。
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Diagnostic {
+    pub func: String,
+    pub args: Vec<Literal>,
+    pub message: String,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let args = self.args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        write!(f, "error: {}![{}]: {}", self.func, args.join(" "), self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use combine::EasyParser;
+
+    use crate::context_table::CtxtTable;
+    use crate::id::{OperId, TypeId};
+    use crate::literal::Literal;
+    use crate::parser::term::terminner::oper::terminner_parser;
+    use crate::parser::theory::theory_parser;
+    use crate::symbol_table::SymbolTable;
+    use crate::term::TermInner;
+
+    fn parse_int(args: &[Literal]) -> Result<Literal, String> {
+        match args {
+            [Literal::Str(s)] => s.parse::<i64>().map(Literal::Int).map_err(|e| e.to_string()),
+            _ => Err("expected one Str".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_external_fn() {
+        let types = SymbolTable::<TypeId>::new();
+        let opers = SymbolTable::<OperId>::new();
+        let ctxts = CtxtTable::new();
+        let src = "#sort Str\n#sort Int\n#func parse: Str -> Int\n";
+        let mut theory = theory_parser(&types, &opers, &ctxts).easy_parse(src).unwrap().0;
+        theory.register_external("parse", Rc::new(parse_int)).unwrap();
+        assert!(theory.register_external("hash", Rc::new(parse_int)).is_err());
+
+        let term = |s: &str| Rc::new(terminner_parser(&ctxts, &opers, None).easy_parse(s).unwrap().0);
+
+        let evaluated = theory.builtins.eval(&term("parse!\"42\""));
+        assert_eq!(evaluated.as_ref(), &TermInner::Lit(Literal::Int(42)));
+        assert!(theory.builtins.take_diagnostics().is_empty());
+
+        // エラーなら評価せずに残し、診断を記録する
+        let t = term("parse!\"x\"");
+        assert_eq!(theory.builtins.eval(&t), t);
+        let diagnostics = theory.builtins.take_diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "error: parse![\"x\"]: invalid digit found in string"
+        );
+    }
+}
@@ -1,15 +1,17 @@
 use autoincrement::prelude::*;
 
-#[derive(AsyncIncremental, PartialEq, Eq, Clone, Default)]
+#[derive(Hash, AsyncIncremental, PartialEq, Eq, Clone, Default, PartialOrd, Ord)]
 pub struct TypeId(pub usize);
 
-#[derive(AsyncIncremental, PartialEq, Eq, Clone, PartialOrd, Ord)]
+#[derive(Hash, AsyncIncremental, PartialEq, Eq, Clone, PartialOrd, Ord)]
 pub struct OperId(pub usize);
 
-#[derive(Default, Hash, AsyncIncremental, PartialEq, Eq, Clone)]
+#[derive(Default, Hash, AsyncIncremental, PartialEq, Eq, Clone, PartialOrd, Ord)]
 pub struct CtxtId(pub usize);
 #[derive(Hash, AsyncIncremental, PartialEq, Eq, Clone, PartialOrd, Ord)]
 pub struct VarId(pub usize);
+#[derive(Hash, AsyncIncremental, PartialEq, Eq, Clone, PartialOrd, Ord)]
+pub struct GenId(pub usize);
 
 impl std::fmt::Debug for TypeId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -35,9 +37,16 @@ impl std::fmt::Debug for VarId {
     }
 }
 
-#[derive(Clone, PartialEq, Debug)]
+impl std::fmt::Debug for GenId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Gen{}", self.0)
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
 pub enum Symbol {
     Type(TypeId),
     Fun(OperId),
     Var(VarId),
+    Gen(GenId),
 }
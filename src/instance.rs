@@ -1,6 +1,10 @@
+use std::collections::BTreeMap;
 use std::rc::Rc;
 
-use crate::{completion::subst::Subst, equation::Equation, oper::Oper, schema::Schema, symbol_table::Names};
+use crate::{
+    completion::subst::Subst, equation::Equation, id::{GenId, OperId, Symbol, TypeId}, oper::Oper, r#type::Type,
+    schema::Schema, symbol_table::{Names, SymbolTable}, term::TermInner,
+};
 
 #[derive(Clone, Default)]
 pub struct Instance {
@@ -8,14 +12,227 @@ pub struct Instance {
     pub schema: Schema,
     pub elems: Vec<Elem>,
     pub data: Vec<Equation>,
+    /// ファイルから読んだ`#data`が最初に書かれた行(1始まり)。左辺と右辺の組で引く
+    pub lines: BTreeMap<(Rc<TermInner>, Rc<TermInner>), usize>,
 }
 
 #[derive(Clone, Debug)]
 pub enum Elem {
-    Oper(Oper),
+    Gen(Generator),
+    /// `#data`に書かれたラベル付きnull。entityにはtypesideのsortが入る
+    Null(Generator),
     Subst(Subst),
 }
 
+/// `#elem e1 e2: Emp`で宣言される生成元
+/// 名前はfkey/attrとは別の名前空間に属し、entityはSchemaのentitiesのどれか。
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Generator {
+    pub id: GenId,
+    pub name: String,
+    pub entity: TypeId,
+}
+
+impl Generator {
+    pub fn new(id: GenId, name: String, entity: TypeId) -> Self {
+        Generator { id, name, entity }
+    }
+}
+
+impl Instance {
+    /// entityに属する生成元
+    pub fn generators_of<'a>(&'a self, entity: &'a TypeId) -> impl Iterator<Item = &'a Generator> + 'a {
+        self.elems.iter().filter_map(move |e| match e {
+            Elem::Gen(g) if &g.entity == entity => Some(g),
+            _ => None,
+        })
+    }
+
+    /// `#data`に現れたラベル付きnull
+    pub fn nulls(&self) -> impl Iterator<Item = &Generator> + '_ {
+        self.elems.iter().filter_map(|e| match e {
+            Elem::Null(g) => Some(g),
+            _ => None,
+        })
+    }
+
+    pub fn is_null(&self, gid: &GenId) -> bool {
+        self.names.gen_name(gid).is_some_and(|name| name.starts_with('?'))
+    }
+
+    /// `#elem`の生成元と`#data`に現れた生成元(nullを含む)を、このinstanceだけの新しい生成元に付け替える
+    /// workspaceの生成元の表では同じ名前は同じ生成元になるので、instanceごとに分ける。
+    /// 名前はそのまま残し、名前の表ではこのinstanceの生成元だけを引けるようにする。
+    pub fn freshen_gens(&mut self, gens: &SymbolTable<GenId>) {
+        let mut ids = vec![];
+        for elem in &self.elems {
+            match elem {
+                Elem::Gen(g) | Elem::Null(g) => ids.push(g.id.clone()),
+                Elem::Subst(subst) => subst.0.values().for_each(|t| collect_gens(t, &mut ids)),
+            }
+        }
+        for eq in &self.data {
+            collect_gens(&eq.left, &mut ids);
+            collect_gens(&eq.right, &mut ids);
+        }
+
+        let mut names = self
+            .names
+            .entries()
+            .into_iter()
+            .filter(|(_, symbol)| !matches!(symbol, Symbol::Gen(_)))
+            .collect::<Names>();
+        let mut fresh = BTreeMap::new();
+        for gid in ids {
+            if fresh.contains_key(&gid) {
+                continue;
+            }
+            let id = gens.fresh();
+            if let Some(name) = self.names.gen_name(&gid) {
+                names.insert(name.to_string(), Symbol::Gen(id.clone()));
+            }
+            fresh.insert(gid, id);
+        }
+        self.names = Rc::new(names);
+
+        let terms = fresh
+            .iter()
+            .map(|(gid, id)| (gid.clone(), Rc::new(TermInner::Gen(id.clone()))))
+            .collect::<BTreeMap<_, _>>();
+        for elem in &mut self.elems {
+            match elem {
+                Elem::Gen(g) | Elem::Null(g) => g.id = fresh[&g.id].clone(),
+                Elem::Subst(subst) => subst.0.values_mut().for_each(|t| *t = replace_gens(t, &terms)),
+            }
+        }
+        for eq in &mut self.data {
+            *eq = Equation {
+                context: eq.context.clone(),
+                names: self.names.clone(),
+                left: replace_gens(&eq.left, &terms),
+                right: replace_gens(&eq.right, &terms),
+            };
+        }
+    }
+
+    /// `#data`に現れたnullのsortを、書かれた位置の演算子の引数・値域から決めてelemsに加える
+    pub fn collect_nulls(&mut self) -> Result<(), String> {
+        let mut sorts: BTreeMap<GenId, TypeId> = BTreeMap::new();
+        let mut unknown: Vec<GenId> = vec![];
+        for eq in &self.data {
+            let left = self.sort_of(&eq.left, &sorts);
+            self.infer_nulls(&eq.right, left, &mut sorts, &mut unknown)?;
+            let right = self.sort_of(&eq.right, &sorts);
+            self.infer_nulls(&eq.left, right, &mut sorts, &mut unknown)?;
+        }
+        if let Some(gid) = unknown.iter().find(|gid| !sorts.contains_key(gid)) {
+            return Err(format!("Cannot infer the sort of labelled null '{}'", self.null_name(gid)));
+        }
+        let nulls = sorts
+            .into_iter()
+            .map(|(gid, sort)| Elem::Null(Generator::new(gid.clone(), self.null_name(&gid), sort)))
+            .collect::<Vec<_>>();
+        self.elems.extend(nulls);
+        Ok(())
+    }
+
+    fn infer_nulls(
+        &self,
+        t: &TermInner,
+        expected: Option<TypeId>,
+        sorts: &mut BTreeMap<GenId, TypeId>,
+        unknown: &mut Vec<GenId>,
+    ) -> Result<(), String> {
+        match t {
+            TermInner::Gen(gid) if self.is_null(gid) => match (expected, sorts.get(gid)) {
+                (Some(sort), Some(prev)) if &sort != prev => Err(format!(
+                    "Labelled null '{}' is used as both {} and {}",
+                    self.null_name(gid),
+                    self.names.fmt_type(&Type::Unary(prev.clone())),
+                    self.names.fmt_type(&Type::Unary(sort)),
+                )),
+                (Some(sort), _) => {
+                    sorts.insert(gid.clone(), sort);
+                    Ok(())
+                }
+                (None, _) => {
+                    unknown.push(gid.clone());
+                    Ok(())
+                }
+            },
+            TermInner::Fun(oid, args) => {
+                let doms = self.oper(oid).map(|op| op.dom.components()).unwrap_or_default();
+                for (i, arg) in args.iter().enumerate() {
+                    self.infer_nulls(arg, doms.get(i).cloned(), sorts, unknown)?;
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// 項のsort(分からなければNone)
+    fn sort_of(&self, t: &TermInner, sorts: &BTreeMap<GenId, TypeId>) -> Option<TypeId> {
+        match t {
+            TermInner::Fun(oid, _) => match self.oper(oid)?.cod.as_ref() {
+                Type::Unary(sort) => Some(sort.clone()),
+                _ => None,
+            },
+            TermInner::Gen(gid) => sorts.get(gid).cloned().or_else(|| {
+                self.generators().find(|g| &g.id == gid).map(|g| g.entity.clone())
+            }),
+            TermInner::Lit(lit) => self.names.type_id(lit.sort().name()),
+            _ => None,
+        }
+    }
+
+    /// 項のsort。生成元はそのentity、nullは推論したsort(分からなければNone)
+    pub fn term_sort(&self, t: &TermInner) -> Option<TypeId> {
+        let sorts = self.nulls().map(|g| (g.id.clone(), g.entity.clone())).collect();
+        self.sort_of(t, &sorts)
+    }
+
+    /// `#elem`で宣言した生成元
+    pub fn generators(&self) -> impl Iterator<Item = &Generator> + '_ {
+        self.elems.iter().filter_map(|e| match e {
+            Elem::Gen(g) => Some(g),
+            _ => None,
+        })
+    }
+
+    /// schemaのfkey/attrとtheoryの演算子から探す
+    pub fn oper(&self, oid: &OperId) -> Option<&Oper> {
+        self.schema
+            .fkeys
+            .iter()
+            .chain(&self.schema.attrs)
+            .chain(&self.schema.theory.opers)
+            .find(|op| &op.id == oid)
+    }
+
+    fn null_name(&self, gid: &GenId) -> String {
+        self.names.gen_name(gid).unwrap_or("?").to_string()
+    }
+}
+
+/// 項の中の生成元を`gens`に従って置き換える
+pub(crate) fn replace_gens(t: &Rc<TermInner>, gens: &BTreeMap<GenId, Rc<TermInner>>) -> Rc<TermInner> {
+    match t.as_ref() {
+        TermInner::Gen(gid) => gens.get(gid).cloned().unwrap_or_else(|| t.clone()),
+        TermInner::Fun(oid, args) => Rc::new(TermInner::Fun(oid.clone(), args.iter().map(|arg| replace_gens(arg, gens)).collect())),
+        _ => t.clone(),
+    }
+}
+
+/// 項に現れる生成元を、現れた順に集める
+pub(crate) fn collect_gens(t: &TermInner, gens: &mut Vec<GenId>) {
+    match t {
+        TermInner::Gen(gid) => gens.push(gid.clone()),
+        TermInner::Fun(_, args) => args.iter().for_each(|arg| collect_gens(arg, gens)),
+        _ => {}
+    }
+}
+
 impl std::fmt::Debug for Instance {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let data = self
@@ -40,17 +257,17 @@ impl std::fmt::Display for Instance {
 
         for elem in &self.elems {
             match elem {
-                Elem::Oper(op) => {
-                    let oname = self.names.iter()
-                        .find(|(_, sym)| sym == &&crate::id::Symbol::Fun(op.id.clone()));
-                    if let Some((nm, _)) = oname {
-                        writeln!(f, "#elem {}", nm)?;
+                Elem::Gen(g) => {
+                    if let Some(entity) = self.names.sort_name(&g.entity) {
+                        writeln!(f, "#elem {}: {}", g.name, entity)?;
                     } else {
-                        writeln!(f, "#elem {:?}", op)?;
-                    }                    
+                        writeln!(f, "#elem {}: {:?}", g.name, g.entity)?;
+                    }
                 }
+                // nullは`#data`に書かれるので宣言は出力しない
+                Elem::Null(_) => {}
                 Elem::Subst(subst) => {
-                    writeln!(f, "#subst {}", subst)?;
+                    writeln!(f, "#subst {}", crate::eval::assigns_name(&self.names, subst))?;
                 }
             }
         }
@@ -0,0 +1,596 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    builtin::Builtin,
+    completion::{
+        rule::Rule,
+        subst::{Subst, Var},
+    },
+    context::Context,
+    equation::Equation,
+    id::{GenId, OperId, Symbol, TypeId, VarId},
+    instance::{Elem, Generator, Instance},
+    literal::Literal,
+    oper::Oper,
+    r#type::Type,
+    schema::Schema,
+    symbol_table::Names,
+    term::TermInner,
+    theory::Theory,
+    workspace::Workspace,
+};
+
+/// JSONでの項。sort・演算子・生成元・変数は番号ではなく名前で書く
+/// `{"fun": {"name": "mgr", "args": [{"gen": "e1"}]}}`、`{"int": 250}`のように、種類をキーにする。
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TermJson {
+    Var(String),
+    Gen(String),
+    Fun {
+        name: String,
+        args: Vec<TermJson>,
+    },
+    Int(i64),
+    /// `"12.50"`のように文字列で書く
+    Dec(String),
+    Bool(bool),
+    Str(String),
+    /// 無名の`#entity`ブロックの行
+    Subst(Vec<AssignJson>),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AssignJson {
+    pub var: String,
+    pub value: TermJson,
+}
+
+/// 変数とそのsort
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct VarJson {
+    pub name: String,
+    pub sort: String,
+}
+
+/// `#func`/`#fkey`/`#attr`。`dom`は積のsortを左から並べる(`1 -> Bool`なら`["1"]`)
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FuncJson {
+    pub name: String,
+    pub dom: Vec<String>,
+    pub cod: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub builtin: Option<String>,
+}
+
+/// `#rule`/`#data`の等式。`#data`の`context`は空
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EquationJson {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub context: Vec<VarJson>,
+    pub left: TermJson,
+    pub right: TermJson,
+}
+
+/// 完備化で得た書き換え規則
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RuleJson {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub context: Vec<VarJson>,
+    pub before: TermJson,
+    pub after: TermJson,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TheoryJson {
+    pub sorts: Vec<String>,
+    pub funcs: Vec<FuncJson>,
+    pub rules: Vec<EquationJson>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SchemaJson {
+    pub theory: TheoryJson,
+    pub entities: Vec<String>,
+    pub fkeys: Vec<FuncJson>,
+    pub attrs: Vec<FuncJson>,
+    pub rules: Vec<EquationJson>,
+}
+
+/// 生成元とそのentity。nullならtypesideのsort
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ElemJson {
+    pub name: String,
+    pub entity: String,
+}
+
+/// instance。queryの評価結果もこの形になる
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InstanceJson {
+    pub schema: SchemaJson,
+    pub elems: Vec<ElemJson>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub nulls: Vec<ElemJson>,
+    /// 無名の`#entity`ブロックの行
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rows: Vec<Vec<AssignJson>>,
+    pub data: Vec<EquationJson>,
+}
+
+impl Theory {
+    pub fn to_json(&self) -> TheoryJson {
+        TheoryJson {
+            sorts: self
+                .types
+                .iter()
+                .map(|ty| Scope(&self.names, &self.names).ty(ty))
+                .collect(),
+            funcs: self
+                .opers
+                .iter()
+                .map(|op| func(Scope(&self.names, &self.names), op, self.builtins.ops.get(&op.id)))
+                .collect(),
+            rules: self.eqs.iter().map(|eq| eq.to_json(&self.names)).collect(),
+        }
+    }
+
+    /// 名前はworkspaceの表で番号にする。外部関数は登録されないので、`Workspace::register_external`の後で読み直すこと
+    pub fn from_json(ws: &Workspace, json: &TheoryJson) -> Result<Theory, String> {
+        let types = json
+            .sorts
+            .iter()
+            .map(|sort| Type::Unary(ws.types.assign(sort.clone())))
+            .collect();
+        let mut theory = Theory {
+            types,
+            ..Theory::default()
+        };
+        for f in &json.funcs {
+            let op = load_func(ws, f);
+            if let Some(builtin) = &f.builtin {
+                theory.builtins.ops.insert(op.id.clone(), builtin.parse::<Builtin>()?);
+            }
+            theory.opers.push(op);
+        }
+        theory.names = Rc::new(table_names(ws));
+        theory.eqs = json
+            .rules
+            .iter()
+            .map(|eq| load_equation(ws, eq))
+            .collect::<Result<_, _>>()?;
+        Ok(theory)
+    }
+}
+
+impl Schema {
+    pub fn to_json(&self) -> SchemaJson {
+        SchemaJson {
+            theory: self.theory.to_json(),
+            entities: self
+                .entities
+                .iter()
+                .map(|ty| Scope(&self.names, &self.names).ty(ty))
+                .collect(),
+            fkeys: self
+                .fkeys
+                .iter()
+                .map(|op| func(Scope(&self.names, &self.names), op, None))
+                .collect(),
+            attrs: self
+                .attrs
+                .iter()
+                .map(|op| func(Scope(&self.names, &self.names), op, None))
+                .collect(),
+            rules: self.constraints.iter().map(|eq| eq.to_json(&self.names)).collect(),
+        }
+    }
+
+    pub fn from_json(ws: &Workspace, json: &SchemaJson) -> Result<Schema, String> {
+        let theory = Theory::from_json(ws, &json.theory)?;
+        let entities = json
+            .entities
+            .iter()
+            .map(|sort| Type::Unary(ws.types.assign(sort.clone())))
+            .collect();
+        let fkeys = json.fkeys.iter().map(|f| load_func(ws, f)).collect();
+        let attrs = json.attrs.iter().map(|f| load_func(ws, f)).collect();
+        let constraints = json
+            .rules
+            .iter()
+            .map(|eq| load_equation(ws, eq))
+            .collect::<Result<_, _>>()?;
+        Ok(Schema {
+            names: Rc::new(table_names(ws)),
+            theory,
+            entities,
+            fkeys,
+            attrs,
+            constraints,
+        })
+    }
+}
+
+impl Instance {
+    pub fn to_json(&self) -> InstanceJson {
+        let scope = Scope(&self.names, &self.schema.names);
+        let elem = |g: &Generator| ElemJson {
+            name: g.name.clone(),
+            entity: scope.sort(&g.entity),
+        };
+        let mut json = InstanceJson {
+            schema: self.schema.to_json(),
+            elems: vec![],
+            nulls: vec![],
+            rows: vec![],
+            data: self.data.iter().map(|eq| eq.to_json(&self.names)).collect(),
+        };
+        for e in &self.elems {
+            match e {
+                Elem::Gen(g) => json.elems.push(elem(g)),
+                Elem::Null(g) => json.nulls.push(elem(g)),
+                Elem::Subst(subst) => json.rows.push(assigns(scope, subst)),
+            }
+        }
+        json
+    }
+
+    pub fn from_json(ws: &Workspace, json: &InstanceJson) -> Result<Instance, String> {
+        let schema = Schema::from_json(ws, &json.schema)?;
+        let sort = |e: &ElemJson| ws.types.get(&e.entity).ok_or_else(|| format!("Unknown sort '{}'", e.entity));
+        let mut elems = vec![];
+        for e in &json.elems {
+            elems.push(Elem::Gen(Generator::new(ws.gens.assign(e.name.clone()), e.name.clone(), sort(e)?)));
+        }
+        let mut names = table_names(ws);
+        let mut vars = BTreeMap::new();
+        for row in &json.rows {
+            let mut subst = Subst(BTreeMap::new());
+            for assign in row {
+                let next = VarId(vars.len());
+                let vid = vars.entry(assign.var.clone()).or_insert(next).clone();
+                names.insert(assign.var.clone(), Symbol::Var(vid.clone()));
+                subst.insert(Var::Id(vid), Rc::new(load_term(ws, &vars, &assign.value)?));
+            }
+            elems.push(Elem::Subst(subst));
+        }
+        let data = json
+            .data
+            .iter()
+            .map(|eq| load_equation(ws, eq))
+            .collect::<Result<_, _>>()?;
+        // `#data`で初めて出たnullの名前も引けるようにする
+        names.extend(ws.gens.current_table());
+        let mut instance = Instance {
+            names: Rc::new(names),
+            schema,
+            elems,
+            data,
+            lines: BTreeMap::new(),
+        };
+        // 生成元はこのinstanceだけのものにしてから、付け替えた番号でnullを並べる
+        instance.freshen_gens(&ws.gens);
+        for e in &json.nulls {
+            let gid = instance.names.gen_id(&e.name).unwrap_or_else(|| ws.gens.assign(e.name.clone()));
+            instance.elems.push(Elem::Null(Generator::new(gid, e.name.clone(), sort(e)?)));
+        }
+        Ok(instance)
+    }
+}
+
+impl Equation {
+    /// 等式の持つ名前になければ、`names`(theory/schema/instanceの名前)から引く
+    pub fn to_json(&self, names: &Names) -> EquationJson {
+        let scope = Scope(&self.names, names);
+        EquationJson {
+            context: context(scope, &self.context),
+            left: term(scope, &self.left),
+            right: term(scope, &self.right),
+        }
+    }
+}
+
+impl Rule {
+    /// 規則の持つ名前になければ、`names`(theory/schema/instanceの名前)から引く
+    pub fn to_json(&self, names: &Names) -> RuleJson {
+        let scope = Scope(&self.names, names);
+        RuleJson {
+            context: context(scope, &self.context),
+            before: term(scope, &self.before),
+            after: term(scope, &self.after),
+        }
+    }
+
+    pub fn from_json(ws: &Workspace, json: &RuleJson) -> Result<Rule, String> {
+        let eq = load_equation(
+            ws,
+            &EquationJson {
+                context: json.context.clone(),
+                left: json.before.clone(),
+                right: json.after.clone(),
+            },
+        )?;
+        Ok(Rule::new(eq.context, eq.names, eq.left, eq.right))
+    }
+}
+
+/// 式や規則の名前を先に、なければ持ち主の名前を引く
+#[derive(Clone, Copy)]
+struct Scope<'a>(&'a Names, &'a Names);
+
+impl Scope<'_> {
+    fn name<'b>(&'b self, f: impl Fn(&'b Names) -> Option<&'b str>) -> Option<String> {
+        f(self.0).or_else(|| f(self.1)).map(str::to_string)
+    }
+
+    fn sort(&self, tid: &TypeId) -> String {
+        self.name(|names| names.sort_name(tid))
+            .unwrap_or_else(|| "?".to_string())
+    }
+
+    /// 積は`Int * Int`のように書く
+    fn ty(&self, ty: &Type) -> String {
+        ty.components()
+            .iter()
+            .map(|tid| self.sort(tid))
+            .collect::<Vec<_>>()
+            .join(" * ")
+    }
+
+    fn oper(&self, oid: &OperId) -> String {
+        self.name(|names| names.oper_name(oid))
+            .unwrap_or_else(|| "?".to_string())
+    }
+
+    fn gen(&self, gid: &GenId) -> String {
+        self.name(|names| names.gen_name(gid))
+            .unwrap_or_else(|| "?".to_string())
+    }
+
+    fn var(&self, vid: &VarId) -> String {
+        self.name(|names| names.var_name(vid))
+            .unwrap_or_else(|| format!("v{}", vid.0))
+    }
+}
+
+fn func(scope: Scope, op: &Oper, builtin: Option<&Builtin>) -> FuncJson {
+    FuncJson {
+        name: scope.oper(&op.id),
+        dom: op.dom.components().iter().map(|tid| scope.sort(tid)).collect(),
+        cod: scope.ty(&op.cod),
+        builtin: builtin.map(Builtin::to_string),
+    }
+}
+
+fn context(scope: Scope, context: &Context) -> Vec<VarJson> {
+    context
+        .0
+        .iter()
+        .map(|(vid, ty)| VarJson {
+            name: scope.var(vid),
+            sort: scope.ty(ty),
+        })
+        .collect()
+}
+
+fn assigns(scope: Scope, subst: &Subst) -> Vec<AssignJson> {
+    subst
+        .0
+        .iter()
+        .map(|(var, value)| {
+            let (Var::Id(vid) | Var::Ruled(vid, _, _)) = var;
+            AssignJson {
+                var: scope.var(vid),
+                value: term(scope, value),
+            }
+        })
+        .collect()
+}
+
+fn term(scope: Scope, t: &TermInner) -> TermJson {
+    match t {
+        TermInner::Var(vid) | TermInner::RuledVar(vid, _, _) => TermJson::Var(scope.var(vid)),
+        TermInner::Gen(gid) => TermJson::Gen(scope.gen(gid)),
+        TermInner::Fun(oid, args) => TermJson::Fun {
+            name: scope.oper(oid),
+            args: args.iter().map(|arg| term(scope, arg)).collect(),
+        },
+        TermInner::Lit(Literal::Int(i)) => TermJson::Int(*i),
+        TermInner::Lit(Literal::Dec(d)) => TermJson::Dec(d.to_string()),
+        TermInner::Lit(Literal::Bool(b)) => TermJson::Bool(*b),
+        TermInner::Lit(Literal::Str(s)) => TermJson::Str(s.clone()),
+        TermInner::Subst(subst) => TermJson::Subst(assigns(scope, &Subst(subst.iter().cloned().collect()))),
+    }
+}
+
+/// sort・演算子・生成元の、workspaceで今までに付けた名前
+fn table_names(ws: &Workspace) -> Names {
+    let mut names = ws.types.current_table();
+    names.extend(ws.opers.current_table());
+    names.extend(ws.gens.current_table());
+    names
+}
+
+fn load_func(ws: &Workspace, f: &FuncJson) -> Oper {
+    let dom = f
+        .dom
+        .iter()
+        .map(|sort| Type::Unary(ws.types.assign(sort.clone())))
+        .reduce(|left, right| Type::Binary(ws.opers.assign("*".to_string()), Rc::new(left), Rc::new(right)))
+        .unwrap_or_else(|| Type::Unary(ws.types.assign("1".to_string())));
+    let cod = Type::Unary(ws.types.assign(f.cod.clone()));
+    Oper::new(ws.opers.assign(f.name.clone()), Rc::new(dom), Rc::new(cod))
+}
+
+/// 変数には`context`の順に番号を付ける
+fn load_equation(ws: &Workspace, json: &EquationJson) -> Result<Equation, String> {
+    let mut names = table_names(ws);
+    let mut context = Context::default();
+    let mut vars = BTreeMap::new();
+    for (i, var) in json.context.iter().enumerate() {
+        let sort = ws
+            .types
+            .get(&var.sort)
+            .ok_or_else(|| format!("Unknown sort '{}'", var.sort))?;
+        vars.insert(var.name.clone(), VarId(i));
+        names.insert(var.name.clone(), Symbol::Var(VarId(i)));
+        context.0.insert(VarId(i), Type::Unary(sort));
+    }
+    let (left, right) = (load_term(ws, &vars, &json.left)?, load_term(ws, &vars, &json.right)?);
+    // 式の中で初めて現れたnullの名前も引けるようにする
+    names.extend(ws.gens.current_table());
+    Ok(Equation {
+        context: Rc::new(context),
+        names: Rc::new(names),
+        left: Rc::new(left),
+        right: Rc::new(right),
+    })
+}
+
+fn load_term(ws: &Workspace, vars: &BTreeMap<String, VarId>, json: &TermJson) -> Result<TermInner, String> {
+    Ok(match json {
+        TermJson::Var(name) => TermInner::Var(
+            vars.get(name)
+                .cloned()
+                .ok_or_else(|| format!("Variable '{}' is not in the context", name))?,
+        ),
+        TermJson::Gen(name) if name.starts_with('?') => TermInner::Gen(ws.gens.assign(name.clone())),
+        TermJson::Gen(name) => TermInner::Gen(
+            ws.gens
+                .get(name)
+                .ok_or_else(|| format!("Generator '{}' is not declared", name))?,
+        ),
+        TermJson::Fun { name, args } => TermInner::Fun(
+            ws.opers
+                .get(name)
+                .ok_or_else(|| format!("Unknown function '{}'", name))?,
+            args.iter()
+                .map(|arg| load_term(ws, vars, arg).map(Rc::new))
+                .collect::<Result<_, _>>()?,
+        ),
+        TermJson::Int(i) => TermInner::Lit(Literal::Int(*i)),
+        TermJson::Dec(d) => TermInner::Lit(Literal::Dec(
+            d.parse().map_err(|e| format!("Invalid decimal '{}': {}", d, e))?,
+        )),
+        TermJson::Bool(b) => TermInner::Lit(Literal::Bool(*b)),
+        TermJson::Str(s) => TermInner::Lit(Literal::Str(s.clone())),
+        TermJson::Subst(assigns) => TermInner::Subst(
+            assigns
+                .iter()
+                .map(|assign| {
+                    let vid = vars
+                        .get(&assign.var)
+                        .cloned()
+                        .ok_or_else(|| format!("Variable '{}' is not in the context", assign.var))?;
+                    Ok((Var::Id(vid), Rc::new(load_term(ws, vars, &assign.value)?)))
+                })
+                .collect::<Result<_, String>>()?,
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        completion::{complete, rule::Rule},
+        eval::eval,
+        instance::Instance,
+        json::{InstanceJson, RuleJson, SchemaJson, TermJson, TheoryJson},
+        schema::Schema,
+        theory::Theory,
+        workspace::Workspace,
+    };
+
+    #[test]
+    fn test_instance_json() {
+        let ws = Workspace::new("example");
+        let i = ws.instance("i").unwrap();
+        let json = serde_json::to_string(&i.to_json()).unwrap();
+        assert!(json.contains(r#"{"left":{"fun":{"name":"last","args":[{"gen":"e1"}]}},"right":{"str":"Gauss"}}"#));
+
+        // 別のworkspaceで読んでも、名前で同じinstanceになる
+        let other = Workspace::new("example");
+        other.types.assign("Unused".to_string());
+        let parsed: InstanceJson = serde_json::from_str(&json).unwrap();
+        let loaded = Instance::from_json(&other, &parsed).unwrap();
+        assert_eq!(serde_json::to_string(&loaded.to_json()).unwrap(), json);
+        assert_eq!(loaded.data.len(), i.data.len());
+        assert_eq!(loaded.nulls().count(), 0);
+
+        // nullも名前で読み戻す
+        let nulls = ws.instance("nulls").unwrap();
+        let json = serde_json::to_string(&nulls.to_json()).unwrap();
+        let loaded = Instance::from_json(&other, &serde_json::from_str(&json).unwrap()).unwrap();
+        assert_eq!(serde_json::to_string(&loaded.to_json()).unwrap(), json);
+        assert_eq!(loaded.nulls().count(), 1);
+    }
+
+    #[test]
+    fn test_theory_schema_json() {
+        let ws = Workspace::new("example");
+        let schema = ws.schema("s").unwrap();
+        let json = schema.to_json();
+        assert!(json
+            .theory
+            .funcs
+            .iter()
+            .any(|f| f.name == "st" && f.dom == ["Int", "Int"] && f.builtin.as_deref() == Some("le")));
+        assert!(json
+            .rules
+            .iter()
+            .any(|eq| eq.context.len() == 1 && eq.context[0].sort == "Emp"));
+
+        let other = Workspace::new("example");
+        let text = serde_json::to_string(&json).unwrap();
+        let loaded = Schema::from_json(&other, &serde_json::from_str::<SchemaJson>(&text).unwrap()).unwrap();
+        assert_eq!(loaded.to_json(), json);
+        let theory = Theory::from_json(
+            &other,
+            &serde_json::from_str::<TheoryJson>(&serde_json::to_string(&json.theory).unwrap()).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(theory.to_json(), json.theory);
+        assert!(theory.builtins.ops.len() >= 3);
+    }
+
+    #[test]
+    fn test_rules_and_query_results_json() {
+        let ws = Workspace::new("example");
+        let i = ws.instance("i").unwrap();
+        let rules = complete(i.data.clone(), 0);
+        let json = rules.iter().map(|r| r.to_json(&i.names)).collect::<Vec<_>>();
+        let text = serde_json::to_string(&json).unwrap();
+        let loaded = serde_json::from_str::<Vec<RuleJson>>(&text)
+            .unwrap()
+            .iter()
+            .map(|r| Rule::from_json(&ws, r))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(loaded.iter().map(|r| r.to_json(&i.names)).collect::<Vec<_>>(), json);
+
+        let (instance, query) = ws.query("_").unwrap();
+        let result = eval(instance, query).unwrap().to_json();
+        let text = serde_json::to_string(&result).unwrap();
+        assert_eq!(serde_json::from_str::<InstanceJson>(&text).unwrap(), result);
+
+        assert!(Rule::from_json(
+            &ws,
+            &RuleJson {
+                context: vec![],
+                before: TermJson::Var("x".to_string()),
+                after: TermJson::Int(1)
+            }
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_json_schema_document() {
+        let doc: serde_json::Value = serde_json::from_str(include_str!("../doc/otz.schema.json")).unwrap();
+        for def in ["Term", "Equation", "Rule", "Theory", "Schema", "Instance"] {
+            assert!(doc["$defs"].get(def).is_some(), "{} is not documented", def);
+        }
+    }
+}
@@ -0,0 +1,41 @@
+extern crate combine;
+
+pub mod aggregate;
+pub mod builtin;
+pub mod colimit;
+pub mod completion;
+pub mod consistency;
+pub mod compose;
+pub mod constraint;
+pub mod context;
+pub mod csv;
+pub mod equation;
+pub mod external;
+pub mod id;
+#[cfg(feature = "serde")]
+pub mod json;
+pub mod literal;
+pub mod mapping;
+pub mod migration;
+pub mod oper;
+pub mod predicate;
+pub mod preservation;
+pub mod reduct;
+pub mod saturate;
+pub mod subterm;
+pub mod table;
+pub mod term;
+pub mod theory;
+pub mod transform;
+pub mod r#type;
+pub mod validate;
+pub mod yaml;
+
+pub mod context_table;
+pub mod eval;
+pub mod instance;
+pub mod parser;
+pub mod schema;
+pub mod symbol_table;
+pub mod util;
+pub mod workspace;
@@ -0,0 +1,200 @@
+/// 項に直接書けるリテラル
+/// 種類ごとに属するsortが決まっていて、theoryの同名のsort(`Int`/`Dec`/`Bool`/`Str`)に対応する。
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Literal {
+    Int(i64),
+    Dec(Decimal),
+    Bool(bool),
+    Str(String),
+}
+
+/// リテラルの属するsort
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum LitSort {
+    Int,
+    Dec,
+    Bool,
+    Str,
+}
+
+impl LitSort {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LitSort::Int => "Int",
+            LitSort::Dec => "Dec",
+            LitSort::Bool => "Bool",
+            LitSort::Str => "Str",
+        }
+    }
+}
+
+impl Literal {
+    pub fn sort(&self) -> LitSort {
+        match self {
+            Literal::Int(_) => LitSort::Int,
+            Literal::Dec(_) => LitSort::Dec,
+            Literal::Bool(_) => LitSort::Bool,
+            Literal::Str(_) => LitSort::Str,
+        }
+    }
+
+    /// 数値なら小数として取り出す(IntとDecの比較・演算用)
+    pub fn as_decimal(&self) -> Option<Decimal> {
+        match self {
+            Literal::Int(i) => Some(Decimal::new(*i, 0)),
+            Literal::Dec(d) => Some(*d),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Literal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Literal::Int(i) => write!(f, "{}", i),
+            Literal::Dec(d) => write!(f, "{}", d),
+            Literal::Bool(b) => write!(f, "{}", b),
+            Literal::Str(s) => write!(f, "{:?}", s),
+        }
+    }
+}
+
+/// 固定小数点数 `mantissa * 10^-scale`
+/// 末尾の0は取り除いて保持するので、`12.5`と`12.50`は等しい。
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Decimal {
+    mantissa: i64,
+    scale: u32,
+}
+
+impl Decimal {
+    pub fn new(mut mantissa: i64, mut scale: u32) -> Self {
+        while scale > 0 && mantissa % 10 == 0 {
+            mantissa /= 10;
+            scale -= 1;
+        }
+        Decimal { mantissa, scale }
+    }
+
+    /// 小数点以下の桁をそろえた仮数
+    fn aligned(&self, other: &Decimal) -> Option<(i64, i64, u32)> {
+        let scale = self.scale.max(other.scale);
+        let a = self.mantissa.checked_mul(10i64.checked_pow(scale - self.scale)?)?;
+        let b = other.mantissa.checked_mul(10i64.checked_pow(scale - other.scale)?)?;
+        Some((a, b, scale))
+    }
+
+    pub fn checked_add(&self, other: &Decimal) -> Option<Decimal> {
+        let (a, b, scale) = self.aligned(other)?;
+        Some(Decimal::new(a.checked_add(b)?, scale))
+    }
+
+    pub fn checked_sub(&self, other: &Decimal) -> Option<Decimal> {
+        let (a, b, scale) = self.aligned(other)?;
+        Some(Decimal::new(a.checked_sub(b)?, scale))
+    }
+
+    pub fn checked_mul(&self, other: &Decimal) -> Option<Decimal> {
+        let mantissa = self.mantissa.checked_mul(other.mantissa)?;
+        Some(Decimal::new(mantissa, self.scale.checked_add(other.scale)?))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        use std::cmp::Ordering;
+
+        let sign = self.mantissa.signum().cmp(&other.mantissa.signum());
+        if sign != Ordering::Equal || self.mantissa == 0 {
+            return sign;
+        }
+        // 同じ符号なら絶対値をi128でそろえて比べる
+        // そろえる側だけが桁あふれしうるので、あふれた方はi64のどの値よりも絶対値が大きい。
+        let scale = self.scale.max(other.scale);
+        let aligned = |d: &Decimal| {
+            10i128.checked_pow(scale - d.scale)?.checked_mul(d.mantissa.unsigned_abs() as i128)
+        };
+        let abs = match (aligned(self), aligned(other)) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (None, _) => Ordering::Greater,
+            (_, None) => Ordering::Less,
+        };
+        if self.mantissa < 0 {
+            abs.reverse()
+        } else {
+            abs
+        }
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl std::fmt::Display for Decimal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.scale == 0 {
+            return write!(f, "{}.0", self.mantissa);
+        }
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        let digits = format!("{:0>width$}", self.mantissa.unsigned_abs(), width = self.scale as usize + 1);
+        let (int, frac) = digits.split_at(digits.len() - self.scale as usize);
+        write!(f, "{}{}.{}", sign, int, frac)
+    }
+}
+
+impl std::str::FromStr for Decimal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (int, frac) = s.split_once('.').unwrap_or((s, ""));
+        let mantissa = format!("{}{}", int, frac)
+            .parse::<i64>()
+            .map_err(|e| format!("invalid decimal {}: {}", s, e))?;
+        Ok(Decimal::new(mantissa, frac.len() as u32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use crate::literal::Decimal;
+
+    #[rstest]
+    #[case("12.50", "12.5")]
+    #[case("-0.05", "-0.05")]
+    #[case("3.0", "3.0")]
+    #[case("0.125", "0.125")]
+    fn test_decimal_roundtrip(#[case] input: &str, #[case] expected: &str) {
+        let d = input.parse::<Decimal>().unwrap();
+        assert_eq!(d.to_string(), expected);
+    }
+
+    #[test]
+    fn test_decimal_arith() {
+        let a = "1.25".parse::<Decimal>().unwrap();
+        let b = "0.75".parse::<Decimal>().unwrap();
+        assert_eq!(a.checked_add(&b), Some(Decimal::new(2, 0)));
+        assert_eq!(b.checked_sub(&a), Some("-0.5".parse().unwrap()));
+        assert_eq!(a.checked_mul(&b), Some("0.9375".parse().unwrap()));
+        assert!(a > b);
+        assert_eq!("12.50".parse::<Decimal>(), "12.5".parse::<Decimal>());
+    }
+
+    #[rstest]
+    // 小数点以下40桁の値は、そろえると10^40倍になる
+    #[case("0.0000000000000000000000000000000000000001", "5.0", std::cmp::Ordering::Less)]
+    #[case("-0.0000000000000000000000000000000000000001", "-5.0", std::cmp::Ordering::Greater)]
+    #[case("922337203685477580.7", "0.0000000000000000000000000000000000000001", std::cmp::Ordering::Greater)]
+    #[case("-1.5", "0.0000000000000000000000000000000000000001", std::cmp::Ordering::Less)]
+    #[case("1.5", "1.50", std::cmp::Ordering::Equal)]
+    #[case("0.0", "-0.0000000000000000000000000000000000000001", std::cmp::Ordering::Greater)]
+    fn test_decimal_cmp_with_large_scale(#[case] a: &str, #[case] b: &str, #[case] expected: std::cmp::Ordering) {
+        let (a, b) = (a.parse::<Decimal>().unwrap(), b.parse::<Decimal>().unwrap());
+        assert_eq!(a.cmp(&b), expected);
+        assert_eq!(b.cmp(&a), expected.reverse());
+    }
+}
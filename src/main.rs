@@ -1,79 +1,383 @@
-extern crate combine;
+use otz::constraint::Repair;
 
-mod completion;
-mod context;
+fn main() {
+    let args = std::env::args().collect::<Vec<_>>();
+    match args.get(1).map(String::as_str) {
+        Some("check") => {
+            let Some(path) = args.get(2) else {
+                eprintln!("usage: otz check <path/to/instance/NAME.instance> [--merge]");
+                eprintln!("       otz check <path/to/query/NAME.query>");
+                eprintln!("       otz check <path/to/mapping/NAME.mapping>");
+                eprintln!("       otz check <path/to/transform/NAME.transform>");
+                std::process::exit(2);
+            };
+            let repair = if args.iter().any(|a| a == "--merge") {
+                Repair::Merge
+            } else {
+                Repair::Reject
+            };
+            if path.ends_with(".query") {
+                std::process::exit(check::check_query(path));
+            }
+            if path.ends_with(".mapping") {
+                std::process::exit(check::check_mapping(path));
+            }
+            if path.ends_with(".transform") {
+                std::process::exit(check::check_transform(path));
+            }
+            std::process::exit(check::check(path, repair));
+        }
+        Some("migrate") => {
+            let (Some(functor), Some(path), Some(instance)) = (args.get(2), args.get(3), args.get(4)) else {
+                eprintln!("usage: otz migrate <delta|sigma|pi> <path/to/mapping/NAME.mapping> <INSTANCE>");
+                std::process::exit(2);
+            };
+            std::process::exit(check::migrate(functor, path, instance));
+        }
+        Some("pushout") => {
+            let (Some(f), Some(g)) = (args.get(2), args.get(3)) else {
+                eprintln!("usage: otz pushout <path/to/transform/F.transform> <path/to/transform/G.transform>");
+                std::process::exit(2);
+            };
+            std::process::exit(check::pushout(f, g));
+        }
+        Some("import") => {
+            let (Some(schema), Some(path)) = (args.get(2), args.get(3)) else {
+                eprintln!("usage: otz import <SCHEMA> <path/to/instance/NAME.yaml>");
+                eprintln!("       otz import <SCHEMA> <path/to/DIR of ENTITY.csv>");
+                std::process::exit(2);
+            };
+            std::process::exit(check::import(schema, path));
+        }
+        Some("export") => {
+            let Some(path) = args.get(2) else {
+                eprintln!("usage: otz export <path/to/instance/NAME.instance> [--csv DIR | --json]");
+                eprintln!("       otz export <path/to/query/NAME.query> [--csv DIR | --json]");
+                std::process::exit(2);
+            };
+            let csv = args.iter().position(|a| a == "--csv").and_then(|i| args.get(i + 1));
+            let json = args.iter().any(|a| a == "--json");
+            std::process::exit(check::export(path, csv.map(String::as_str), json));
+        }
+        _ => qu::query(),
+    }
+}
 
-mod equation;
-mod id;
-mod oper;
+mod check {
+    use std::path::Path;
 
-mod reduct;
+    use otz::consistency::Consistency;
+    use otz::constraint::Repair;
+    use otz::instance::Instance;
+    use otz::workspace::Workspace;
 
+    /// `{root}/instance/{name}.instance`を読み込んで検査し、終了コードを返す
+    /// `Repair::Merge`なら、constraintの違反を生成元の同一視で直したinstanceを表示する。
+    /// 異なる定数が等しくなってしまうinstanceは、その導出を表示する。
+    pub fn check(path: &str, repair: Repair) -> i32 {
+        let (ws, name) = open(path);
+        let instance = match ws.instance(&name) {
+            Ok(instance) => instance,
+            Err(e) => {
+                eprintln!("{}", e);
+                return 2;
+            }
+        };
 
-mod saturate;
+        let issues = instance.validate();
+        for issue in &issues {
+            println!("{}: {}", path, issue);
+        }
 
-mod subterm;
-mod term;
-mod theory;
-mod r#type;
+        let violations = match instance.enforce_constraints(repair) {
+            Ok(repaired) => {
+                if repair == Repair::Merge {
+                    println!("{}", repaired);
+                }
+                vec![]
+            }
+            Err(violations) => violations,
+        };
+        for violation in &violations {
+            println!("{}: {}", path, violation);
+        }
 
-pub mod context_table;
-pub mod eval;
-pub mod instance;
-pub mod parser;
-pub mod schema;
-pub mod symbol_table;
-pub mod util;
+        let consistent = match instance.is_consistent() {
+            Consistency::Consistent => true,
+            Consistency::Inconsistent(inconsistency) => {
+                println!("{}: {}", path, inconsistency);
+                false
+            }
+            Consistency::Undecided(incomplete) => {
+                println!("{}: cannot decide consistency: {}", path, incomplete);
+                false
+            }
+        };
 
-fn main() {
-    qu::query();
-}
+        let diagnostics = instance.schema.theory.builtins.take_diagnostics();
+        for diagnostic in &diagnostics {
+            println!("{}: {}", path, diagnostic);
+        }
 
-mod qu {
-    use combine::EasyParser;
+        if issues.is_empty() && violations.is_empty() && consistent && diagnostics.is_empty() {
+            println!("{}: ok", path);
+            0
+        } else {
+            1
+        }
+    }
 
-    use crate::{context_table::CtxtTable, id::{OperId}, symbol_table::SymbolTable};
-    use crate::{id::TypeId};
+    /// `{root}/query/{name}.query`を読み込み、`#target`との整合性と等式保存条件を検査する
+    pub fn check_query(path: &str) -> i32 {
+        let (ws, name) = open(path);
+        let (instance, query) = match ws.query(&name) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                eprintln!("{}", e);
+                return 2;
+            }
+        };
 
-    pub fn query() {
-        let types = SymbolTable::<TypeId>::new();
-        let opers = SymbolTable::<OperId>::new();
-        let ctxts = CtxtTable::new();
+        let errors = query.check_target(&instance);
+        for error in &errors {
+            println!("{}: {}", path, error);
+        }
+        let unpreserved = query.check_preservation(&instance.schema);
+        for u in &unpreserved {
+            println!("{}: {}", path, u);
+        }
 
-        use crate::parser::parse_instance;
-        let path = "example/instance/i.instance";
-        let instance = parse_instance(path);
-        println!("Parsed Instance:\n{}", instance);
+        if errors.is_empty() && unpreserved.is_empty() {
+            println!("{}: ok", path);
+            0
+        } else {
+            1
+        }
+    }
+
+    /// `{root}/mapping/{name}.mapping`を読み込み、写像として成り立つかを検査する
+    pub fn check_mapping(path: &str) -> i32 {
+        let (ws, name) = open(path);
+        let mapping = match ws.mapping(&name) {
+            Ok(mapping) => mapping,
+            Err(e) => {
+                eprintln!("{}", e);
+                return 2;
+            }
+        };
+        let errors = mapping.check();
+        for error in &errors {
+            println!("{}: {}", path, error);
+        }
+        if errors.is_empty() {
+            println!("{}: ok", path);
+            0
+        } else {
+            1
+        }
+    }
 
-        use crate::eval::eval;
-        use crate::eval::Query;
-        
-        let mut q = Query::default();
-        q.0.push(query_entity_file(&types, &opers, &ctxts));
-        let queried = eval(instance, q);
+    /// `{root}/transform/{name}.transform`を読み込み、準同型として成り立つかを検査する
+    pub fn check_transform(path: &str) -> i32 {
+        let (ws, name) = open(path);
+        let transform = match ws.transform(&name) {
+            Ok(transform) => transform,
+            Err(e) => {
+                eprintln!("{}", e);
+                return 2;
+            }
+        };
+        let errors = transform.check();
+        for error in &errors {
+            println!("{}: {}", path, error);
+        }
+        if errors.is_empty() {
+            println!("{}: ok", path);
+            0
+        } else {
+            1
+        }
+    }
+
+    /// mappingに沿って`{root}/instance/{instance}.instance`を移し、結果を表示する
+    pub fn migrate(functor: &str, path: &str, instance: &str) -> i32 {
+        let (ws, name) = open(path);
+        let loaded = ws.mapping(&name).and_then(|mapping| Ok((mapping, ws.instance(instance)?)));
+        let (mapping, instance) = match loaded {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                eprintln!("{}", e);
+                return 2;
+            }
+        };
+        let errors = mapping.check();
+        if !errors.is_empty() {
+            errors.iter().for_each(|e| eprintln!("{}: {}", path, e));
+            return 1;
+        }
+        let migrated = match functor {
+            "delta" => Ok(mapping.delta(&instance)),
+            "sigma" => mapping
+                .sigma(&instance)
+                .map_err(|violations| violations.iter().map(|v| v.to_string()).collect::<Vec<_>>().join("\n")),
+            "pi" => mapping.pi(&instance),
+            _ => {
+                eprintln!("unknown migration: {} (delta, sigma or pi)", functor);
+                return 2;
+            }
+        };
+        match migrated {
+            Ok(migrated) => {
+                println!("{}", migrated);
+                0
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                1
+            }
+        }
+    }
 
-        println!("{}", queried);
+    /// 同じinstanceから出る2つのtransformで、行き先のinstanceを貼り合わせて表示する
+    pub fn pushout(f: &str, g: &str) -> i32 {
+        let (ws, f_name) = open(f);
+        let (_, g_name) = open(g);
+        let loaded = ws.transform(&f_name).and_then(|f| Ok((f, ws.transform(&g_name)?)));
+        let (f, g) = match loaded {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                eprintln!("{}", e);
+                return 2;
+            }
+        };
+        let errors = [(f.name.as_str(), f.check()), (g.name.as_str(), g.check())];
+        if errors.iter().any(|(_, errors)| !errors.is_empty()) {
+            for (name, errors) in &errors {
+                errors.iter().for_each(|e| eprintln!("{}: {}", name, e));
+            }
+            return 1;
+        }
+        match otz::colimit::pushout(&f, &g) {
+            Ok(merged) => {
+                println!("{}", merged);
+                0
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                1
+            }
+        }
     }
 
-    fn query_entity_file(
-        types: &SymbolTable<TypeId>,
-        opers: &SymbolTable<OperId>,
-        ctxts: &CtxtTable,
-    ) -> crate::eval::QueryEntity {
-        use std::fs;
-        use crate::parser::query::query_entity_parser;
-        let path = "example/query/_.query";
-        let input = fs::read_to_string(path).expect("Failed to read query file");
-
-        let r = query_entity_parser::<combine::easy::Stream<&str>>(types, opers, ctxts)
-        .easy_parse(input.as_ref()).unwrap().0;
-        r
+    /// 表形式のファイルをschemaのinstanceとして読み、検査して表示する
+    pub fn import(schema: &str, path: &str) -> i32 {
+        let (ws, _) = open(path);
+        let loaded = ws.schema(schema).and_then(|schema| {
+            if Path::new(path).is_dir() {
+                return Instance::from_csv_dir(&ws, &schema, path);
+            }
+            let src = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+            Instance::from_yaml(&ws, &schema, &src)
+        });
+        let instance = match loaded {
+            Ok(instance) => instance,
+            Err(e) => {
+                eprintln!("{}", e);
+                return 2;
+            }
+        };
+        let issues = instance.validate();
+        issues.iter().for_each(|issue| eprintln!("{}: {}", path, issue));
+        let violations = instance.check_constraints();
+        violations.iter().for_each(|violation| eprintln!("{}: {}", path, violation));
+        println!("{}", instance);
+        if issues.is_empty() && violations.is_empty() {
+            0
+        } else {
+            1
+        }
+    }
+
+    /// instance(queryならその評価結果)をsaturateし、YAML(`json`ならJSON)で表示するか`csv`のディレクトリに書く
+    pub fn export(path: &str, csv: Option<&str>, json: bool) -> i32 {
+        let (ws, name) = open(path);
+        let loaded = if path.ends_with(".query") {
+            ws.query(&name).and_then(|(instance, query)| {
+                otz::eval::eval(instance, query)
+                    .map_err(|errors| errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n"))
+            })
+        } else {
+            ws.instance(&name)
+        };
+        let instance = match loaded {
+            Ok(instance) => instance.saturate(),
+            Err(e) => {
+                eprintln!("{}", e);
+                return 2;
+            }
+        };
+        let exported = match (csv, json) {
+            (Some(dir), _) => instance.to_csv_dir(dir).map(|_| String::new()),
+            (None, true) => to_json(&instance),
+            (None, false) => instance.to_yaml(),
+        };
+        match exported {
+            Ok(yaml) => {
+                print!("{}", yaml);
+                0
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                1
+            }
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    fn to_json(instance: &Instance) -> Result<String, String> {
+        serde_json::to_string_pretty(&instance.to_json()).map(|json| json + "\n").map_err(|e| e.to_string())
+    }
+
+    #[cfg(not(feature = "serde"))]
+    fn to_json(_: &Instance) -> Result<String, String> {
+        Err("--json needs otz built with `--features serde`".to_string())
+    }
+
+    /// `{root}/{kind}/{name}.{kind}`のパスから、rootのWorkspaceとnameを得る
+    fn open(path: &str) -> (Workspace, String) {
+        let path = Path::new(path);
+        let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default().to_string();
+        let root = path
+            .parent()
+            .and_then(Path::parent)
+            .unwrap_or_else(|| Path::new("."));
+        (Workspace::new(root), name)
+    }
+}
+
+mod qu {
+    use otz::workspace::Workspace;
+
+    pub fn query() {
+        let ws = Workspace::new("example");
+        let (instance, q) = ws.query("_").unwrap_or_else(|e| panic!("{}", e));
+        println!("Parsed Instance:\n{}", instance);
+
+        use otz::eval::eval;
+        let builtins = instance.schema.theory.builtins.clone();
+        match eval(instance, q) {
+            Ok(queried) => println!("{}", queried),
+            Err(errors) => errors.iter().for_each(|e| eprintln!("error: {}", e)),
+        }
+        for diagnostic in builtins.take_diagnostics() {
+            eprintln!("{}", diagnostic);
+        }
     }
 }
 
 fn _comp() {
-    use crate::completion::complete;
-    use crate::completion::eqs;
+    use otz::completion::complete;
+    use otz::completion::eqs;
     let rules = complete(eqs(), 0);
-    crate::util::dispv("result:", &rules);
+    otz::util::dispv("result:", &rules);
 }
\ No newline at end of file
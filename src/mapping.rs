@@ -0,0 +1,293 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use crate::{
+    completion::{complete, rule::Rule, subst::{Subst, Var}, COMPLETION_LIMIT},
+    context::Context,
+    equation::Equation,
+    id::{GenId, OperId, Symbol, TypeId, VarId},
+    r#type::Type,
+    schema::Schema,
+    symbol_table::Names,
+    term::{Term, TermInner},
+};
+
+/// schemaの間の写像
+/// entityをentityへ、fkeyを行き先のschemaのfkeyのパスへ、attrを行き先のschemaの項へ写す。
+#[derive(Clone, Debug, Default)]
+pub struct Mapping {
+    /// entity・fkey・attrと、`#fkey`/`#attr`の変数の名前
+    pub names: Rc<Names>,
+    pub source: Schema,
+    pub target: Schema,
+    pub entities: BTreeMap<TypeId, TypeId>,
+    pub fkeys: BTreeMap<OperId, Image>,
+    pub attrs: BTreeMap<OperId, Image>,
+}
+
+/// `#fkey wrk -> w: Worker | dept!w`の右辺
+/// 元のfkey/attrの引数を変数1つで表した、行き先のschemaの項。
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+    pub var: VarId,
+    /// 変数のsort(行き先のschemaのentity)
+    pub sort: TypeId,
+    pub term: Rc<TermInner>,
+}
+
+impl Image {
+    /// 変数に引数を代入する
+    pub fn apply(&self, arg: Rc<TermInner>) -> Rc<TermInner> {
+        let subst = Subst::new(BTreeMap::from([(Var::Id(self.var.clone()), arg)]));
+        self.term.substitute(&subst)
+    }
+}
+
+/// 写像として成り立たない箇所
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MappingError {
+    /// 元のschemaのentity/fkey/attrの行き先がない
+    Unmapped { kind: &'static str, name: String },
+    /// 写す元が元のschemaにないか、行き先が行き先のschemaにない
+    Unknown { kind: &'static str, name: String },
+    /// 行き先の項のsortが合わない
+    IllTyped { oper: String, expected: String, found: String },
+    /// fkeyの行き先が行き先のschemaのfkeyのパスでない
+    NotPath { fkey: String, term: String },
+    /// 元のschemaの`#rule`が行き先のschemaで成り立たない
+    Unpreserved { equation: String, left: String, right: String },
+}
+
+impl std::fmt::Display for MappingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MappingError::Unmapped { kind, name } => write!(f, "#{} {} is not mapped", kind, name),
+            MappingError::Unknown { kind, name } => write!(f, "#{} {}: unknown {}", kind, name, kind),
+            MappingError::IllTyped { oper, expected, found } => {
+                write!(f, "{}: expected {}, found {}", oper, expected, found)
+            }
+            MappingError::NotPath { fkey, term } => {
+                write!(f, "#fkey {}: {} is not a path of foreign keys", fkey, term)
+            }
+            MappingError::Unpreserved { equation, left, right } => {
+                writeln!(f, "not preserved: {}", equation)?;
+                writeln!(f, "  left:  {}", left)?;
+                write!(f, "  right: {}", right)
+            }
+        }
+    }
+}
+
+impl Mapping {
+    /// 元のschemaの項を行き先のschemaの項に写す
+    /// 変数と生成元はそのまま、typesideの演算子は引数だけを写す。
+    pub fn translate(&self, t: &TermInner) -> Rc<TermInner> {
+        match t {
+            TermInner::Fun(oid, args) => {
+                let args = args.iter().map(|arg| self.translate(arg)).collect::<Vec<_>>();
+                match (self.fkeys.get(oid).or_else(|| self.attrs.get(oid)), args.as_slice()) {
+                    (Some(image), [arg]) => image.apply(arg.clone()),
+                    _ => Rc::new(TermInner::Fun(oid.clone(), args)),
+                }
+            }
+            _ => Rc::new(t.clone()),
+        }
+    }
+
+    /// 行き先のschemaの等式と、theoryの等式を完備化した書き換え規則
+    pub fn target_rules(&self) -> Vec<Rule> {
+        let eqs = [self.target.constraints.clone(), self.target.theory.eqs.clone()].concat();
+        complete(eqs, COMPLETION_LIMIT)
+    }
+
+    /// すべてのentity/fkey/attrが写され、sortが合い、元のschemaの`#rule`が保たれるかを調べる
+    pub fn check(&self) -> Vec<MappingError> {
+        let names = &self.names;
+        let sort_name = |tid: &TypeId| names.sort_name(tid).unwrap_or("?").to_string();
+        let oper_name = |oid: &OperId| names.oper_name(oid).unwrap_or("?").to_string();
+        let mut errors = vec![];
+
+        for (src, dst) in &self.entities {
+            if !self.source.entities.contains(&Type::Unary(src.clone())) {
+                errors.push(MappingError::Unknown { kind: "entity", name: sort_name(src) });
+            }
+            if !self.target.entities.contains(&Type::Unary(dst.clone())) {
+                errors.push(MappingError::Unknown { kind: "entity", name: sort_name(dst) });
+            }
+        }
+        for entity in &self.source.entities {
+            if let Type::Unary(tid) = entity {
+                if !self.entities.contains_key(tid) {
+                    errors.push(MappingError::Unmapped { kind: "entity", name: sort_name(tid) });
+                }
+            }
+        }
+
+        for (kind, opers, images) in [("fkey", &self.source.fkeys, &self.fkeys), ("attr", &self.source.attrs, &self.attrs)] {
+            for oid in images.keys() {
+                if !opers.iter().any(|op| &op.id == oid) {
+                    errors.push(MappingError::Unknown { kind, name: oper_name(oid) });
+                }
+            }
+            for op in opers {
+                let Some(image) = images.get(&op.id) else {
+                    errors.push(MappingError::Unmapped { kind, name: oper_name(&op.id) });
+                    continue;
+                };
+                if kind == "fkey" && !self.is_path(&image.term) {
+                    errors.push(MappingError::NotPath {
+                        fkey: oper_name(&op.id),
+                        term: self.display(&image.term),
+                    });
+                    continue;
+                }
+                // 変数のsortは元のdomの行き先、項のsortはfkeyならcodの行き先、attrならtypesideのsortそのもの
+                let (Type::Unary(dom), Type::Unary(cod)) = (op.dom.as_ref(), op.cod.as_ref()) else {
+                    continue;
+                };
+                let expected_dom = self.entities.get(dom).unwrap_or(dom);
+                let expected_cod = if kind == "fkey" { self.entities.get(cod).unwrap_or(cod) } else { cod };
+                let found_cod = self.sort_of(&image.term, image);
+                let oper = format!("#{} {}", kind, oper_name(&op.id));
+                if &image.sort != expected_dom {
+                    errors.push(MappingError::IllTyped {
+                        oper,
+                        expected: sort_name(expected_dom),
+                        found: sort_name(&image.sort),
+                    });
+                } else if let Some(found) = found_cod.filter(|found| found != expected_cod) {
+                    errors.push(MappingError::IllTyped {
+                        oper,
+                        expected: sort_name(expected_cod),
+                        found: sort_name(&found),
+                    });
+                }
+            }
+        }
+
+        // fkey/attrの像がないかsortが合わなければ、`#rule`を行き先のschemaの項に写せない
+        if !errors.is_empty() {
+            return errors;
+        }
+        let rules = self.target_rules();
+        errors.extend(self.source.constraints.iter().filter_map(|eq| self.check_equation(eq, &rules)));
+        errors
+    }
+
+    /// `#rule`の変数を定数に置き換え、両辺を写して行き先のschemaで正規化して比べる
+    fn check_equation(&self, eq: &Equation, rules: &Vec<Rule>) -> Option<MappingError> {
+        let mut names = self.names.as_ref().clone();
+        let mut subst = BTreeMap::new();
+        for (vid, id) in eq.context.0.keys().zip(names.next_gen_id()..) {
+            let gid = GenId(id);
+            names.insert(eq.names.var_name(vid).unwrap_or("_").to_string(), Symbol::Gen(gid.clone()));
+            subst.insert(Var::Id(vid.clone()), Rc::new(TermInner::Gen(gid)));
+        }
+        let subst = Subst::new(subst);
+        let names = Rc::new(names);
+        let normalize = |t: &Rc<TermInner>| {
+            let term = Term {
+                context: Rc::new(Context::default()),
+                names: names.clone(),
+                inner: self.translate(&t.substitute(&subst)),
+            };
+            term.normalize_with(rules, &self.target.theory.builtins)
+        };
+        let (left, right) = (normalize(&eq.left), normalize(&eq.right));
+        (left.inner != right.inner).then(|| MappingError::Unpreserved {
+            equation: format!("{} = {}", eq.left_term(), eq.right_term()),
+            left: left.to_string(),
+            right: right.to_string(),
+        })
+    }
+
+    /// 変数に行き先のschemaのfkeyを0個以上適用した項か
+    fn is_path(&self, t: &TermInner) -> bool {
+        match t {
+            TermInner::Var(_) => true,
+            TermInner::Fun(oid, args) => {
+                self.target.fkeys.iter().any(|op| &op.id == oid) && matches!(args.as_slice(), [arg] if self.is_path(arg))
+            }
+            _ => false,
+        }
+    }
+
+    /// 行き先のschemaでの項のsort(分からなければNone)
+    fn sort_of(&self, t: &TermInner, image: &Image) -> Option<TypeId> {
+        match t {
+            TermInner::Var(vid) if vid == &image.var => Some(image.sort.clone()),
+            TermInner::Fun(oid, _) => {
+                let op = self
+                    .target
+                    .fkeys
+                    .iter()
+                    .chain(&self.target.attrs)
+                    .chain(&self.target.theory.opers)
+                    .find(|op| &op.id == oid)?;
+                match op.cod.as_ref() {
+                    Type::Unary(tid) => Some(tid.clone()),
+                    _ => None,
+                }
+            }
+            TermInner::Lit(lit) => self.names.type_id(lit.sort().name()),
+            _ => None,
+        }
+    }
+
+    fn display(&self, t: &Rc<TermInner>) -> String {
+        Term {
+            context: Rc::new(Context::default()),
+            names: self.names.clone(),
+            inner: t.clone(),
+        }
+        .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use combine::{eof, EasyParser, Parser};
+
+    use crate::{mapping::MappingError, parser::mapping::mapping_parser, workspace::Workspace};
+
+    #[test]
+    fn test_check_mapping() {
+        let ws = Workspace::new("example");
+        assert!(ws.mapping("f").unwrap().check().is_empty());
+        assert!(ws.mapping("flat").unwrap().check().is_empty());
+    }
+
+    #[test]
+    fn test_check_mapping_errors() {
+        let ws = Workspace::new("example");
+        let input = "#source t\n#target s\n#entity Worker -> Emp\n#fkey dept -> w: Emp | mgr!w\n#attr ename -> w: Emp | sal!w\n";
+        let (mapping, _) = mapping_parser(&ws).skip(eof()).easy_parse(input).unwrap();
+        let errors = mapping.check();
+        assert_eq!(errors.len(), 4);
+        assert!(errors.contains(&MappingError::Unmapped { kind: "entity", name: "Division".to_string() }));
+        assert!(errors.contains(&MappingError::Unmapped { kind: "attr", name: "dname".to_string() }));
+        assert!(errors.contains(&MappingError::IllTyped {
+            oper: "#attr ename".to_string(),
+            expected: "Str".to_string(),
+            found: "Int".to_string(),
+        }));
+        assert!(errors.contains(&MappingError::IllTyped {
+            oper: "#fkey dept".to_string(),
+            expected: "Division".to_string(),
+            found: "Emp".to_string(),
+        }));
+    }
+
+    #[test]
+    fn test_check_mapping_preservation() {
+        let ws = Workspace::new("example");
+        // 上司を「部署の秘書」に写すと、mgrとwrkの等式は保たれるが、給与の等式は保たれない
+        let input = "#source s\n#target s\n#entity Emp -> Emp\n#entity Dept -> Dept\n\
+                     #fkey mgr -> e: Emp | sec!wrk!e\n#fkey wrk -> e: Emp | wrk!e\n#fkey sec -> d: Dept | sec!d\n\
+                     #attr last -> e: Emp | last!e\n#attr name -> d: Dept | name!d\n#attr sal -> e: Emp | sal!e\n";
+        let (mapping, _) = mapping_parser(&ws).skip(eof()).easy_parse(input).unwrap();
+        let errors = mapping.check();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(&errors[0], MappingError::Unpreserved { left, .. } if left == "st![sal!e sal!sec!wrk!e]"));
+    }
+}
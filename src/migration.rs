@@ -0,0 +1,550 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use crate::{
+    completion::rule::Rule,
+    constraint::{Repair, Violation},
+    context::Context,
+    equation::Equation,
+    eval::{Query, QueryEntity},
+    id::{GenId, OperId, Symbol, TypeId, VarId},
+    instance::{Elem, Generator, Instance},
+    mapping::Mapping,
+    r#type::Type,
+    symbol_table::Names,
+    term::{Term, TermInner},
+};
+
+/// Piで、行き先のentityから出るパスをこれ以上は列挙しない
+const PATH_LIMIT: usize = 64;
+
+/// Piで、行き先のentity tの生成元を決めるための圏(t ↓ F)
+/// 対象は、tから元のentity sの行き先F(s)へのパスpの組(s, p)。パスは起点の定数`origin`にfkeyを適用した項で表す。
+struct Comma {
+    origin: GenId,
+    objects: Vec<(TypeId, Rc<TermInner>)>,
+    /// 元のfkey f: s -> s'による射 (s, p) -> (s', F(f)∘p) の、両端の添字
+    arrows: Vec<(usize, OperId, usize)>,
+}
+
+impl Mapping {
+    /// Delta: 行き先のschemaのinstanceを、元のschemaのinstanceに引き戻す
+    /// 元のentity sの生成元は、instanceのF(s)の生成元。fkey/attrの値は、写した項をinstanceで正規化して求める。
+    /// 正規化しても生成元(attrならnullを含む値)にならないものは、値を与えない。
+    pub fn delta(&self, instance: &Instance) -> Instance {
+        let rules = instance.deduction_rules();
+        let mut names = instance.names.as_ref().clone();
+        names.extend(self.names.as_ref().clone());
+        let mut next_gen = names.next_gen_id();
+
+        // (元のentity, instanceの生成元, 新しい生成元)
+        let mut gens: Vec<(TypeId, GenId, GenId)> = vec![];
+        let mut elems = vec![];
+        for entity in &self.source.entities {
+            let Type::Unary(src) = entity else {
+                continue;
+            };
+            let Some(dst) = self.entities.get(src) else {
+                continue;
+            };
+            // 同じentityに写る元のentityが複数あれば、名前にentityを付けて区別する
+            let shared = self.entities.values().filter(|tid| tid == &dst).count() > 1;
+            for g in instance.generators_of(dst) {
+                let gid = GenId(next_gen);
+                next_gen += 1;
+                let name = if shared {
+                    format!("{}.{}", names.sort_name(src).unwrap_or("?"), g.name)
+                } else {
+                    g.name.clone()
+                };
+                names.insert(name.clone(), Symbol::Gen(gid.clone()));
+                elems.push(Elem::Gen(Generator::new(gid.clone(), name, src.clone())));
+                gens.push((src.clone(), g.id.clone(), gid));
+            }
+        }
+        let new_gen = |entity: &TypeId, gid: &GenId| {
+            gens.iter()
+                .find(|(e, g, _)| e == entity && g == gid)
+                .map(|(_, _, new)| new.clone())
+        };
+
+        let mut data = vec![];
+        for (src, old, new) in &gens {
+            let arg = Rc::new(TermInner::Gen(old.clone()));
+            let left = |oid: &OperId| Rc::new(TermInner::Fun(oid.clone(), vec![Rc::new(TermInner::Gen(new.clone()))]));
+            for op in self.source.fkeys.iter().filter(|op| op.dom.as_ref() == &Type::Unary(src.clone())) {
+                let (Some(image), Type::Unary(cod)) = (self.fkeys.get(&op.id), op.cod.as_ref()) else {
+                    continue;
+                };
+                let value = normalize(instance, &rules, image.apply(arg.clone()));
+                if let Some(right) = as_gen(&value).and_then(|gid| new_gen(cod, gid)) {
+                    data.push((left(&op.id), Rc::new(TermInner::Gen(right))));
+                }
+            }
+            for op in self.source.attrs.iter().filter(|op| op.dom.as_ref() == &Type::Unary(src.clone())) {
+                let Some(image) = self.attrs.get(&op.id) else {
+                    continue;
+                };
+                let value = normalize(instance, &rules, image.apply(arg.clone()));
+                if is_value(instance, &value) {
+                    data.push((left(&op.id), value));
+                }
+            }
+        }
+
+        let nulls = instance.nulls().cloned().map(Elem::Null).collect::<Vec<_>>();
+        build(names, self.source.clone(), [elems, nulls].concat(), data)
+    }
+
+    /// Deltaと同じ結果になるquery(行き先のschemaから元のschemaへのuber-flower)
+    /// 元のentity sごとに、F(s)を動く変数1つのブロックを作り、fkey/attrの行き先の項を`#keys`/`#return`にする。
+    pub fn delta_query(&self) -> Query {
+        let mut names = self.names.as_ref().clone();
+        let images = self.fkeys.values().chain(self.attrs.values()).map(|image| image.var.0 + 1);
+        let first = images.chain([names.next_var_id()]).max().unwrap_or(0);
+        let mut vars = BTreeMap::new();
+        for (n, src) in (first..).zip(self.entities.keys()) {
+            let vid = VarId(n);
+            names.insert(names.sort_name(src).unwrap_or("x").to_lowercase(), Symbol::Var(vid.clone()));
+            vars.insert(src.clone(), vid);
+        }
+
+        let entities = self
+            .entities
+            .iter()
+            .map(|(src, dst)| {
+                let var = vars[src].clone();
+                let arg = Rc::new(TermInner::Var(var.clone()));
+                let dom = Type::Unary(src.clone());
+                let ret = self
+                    .source
+                    .attrs
+                    .iter()
+                    .filter(|op| op.dom.as_ref() == &dom)
+                    .filter_map(|op| Some((op.id.clone(), self.attrs.get(&op.id)?.apply(arg.clone()).as_ref().clone())))
+                    .collect();
+                let keys = self
+                    .source
+                    .fkeys
+                    .iter()
+                    .filter(|op| op.dom.as_ref() == &dom)
+                    .filter_map(|op| {
+                        let (Some(image), Type::Unary(cod)) = (self.fkeys.get(&op.id), op.cod.as_ref()) else {
+                            return None;
+                        };
+                        let transform = vec![(vars.get(cod)?.clone(), image.apply(arg.clone()).as_ref().clone())];
+                        Some((op.id.clone(), Some(cod.clone()), transform))
+                    })
+                    .collect();
+                QueryEntity {
+                    instance: None,
+                    entity: Some(src.clone()),
+                    fr: vec![Context(BTreeMap::from([(var, Type::Unary(dst.clone()))]))],
+                    wh: vec![],
+                    ret,
+                    aggs: vec![],
+                    keys,
+                }
+            })
+            .collect();
+
+        Query {
+            instance: None,
+            target: Some(self.source.clone()),
+            entities,
+            names: Rc::new(names),
+        }
+    }
+
+    /// Sigma: 元のschemaのinstanceを、行き先のschemaへ押し出す
+    /// 生成元はそのまま行き先のentityへ移し、`#data`の等式はfkey/attrを写して持っていく。
+    /// その後saturateでデータから等しいと分かる生成元を同一視し、行き先のconstraintの違反も同一視で直す。
+    pub fn sigma(&self, instance: &Instance) -> Result<Instance, Vec<Violation>> {
+        let mut names = instance.names.as_ref().clone();
+        names.extend(self.names.as_ref().clone());
+        let elems = instance
+            .elems
+            .iter()
+            .map(|elem| match elem {
+                Elem::Gen(g) => {
+                    let entity = self.entities.get(&g.entity).unwrap_or(&g.entity).clone();
+                    Elem::Gen(Generator::new(g.id.clone(), g.name.clone(), entity))
+                }
+                _ => elem.clone(),
+            })
+            .collect();
+        let data = instance
+            .data
+            .iter()
+            .map(|eq| (self.translate(&eq.left), self.translate(&eq.right)))
+            .collect();
+        build(names, self.target.clone(), elems, data)
+            .saturate()
+            .enforce_constraints(Repair::Merge)
+    }
+
+    /// Pi: 元のschemaのinstanceを、行き先のschemaへ右側から押し出す
+    /// 行き先のentity tの生成元は、(t ↓ F)の各対象(s, p)にinstanceのsの生成元を選んだ組のうち、
+    /// 元のfkeyによる射と両立するもの。fkeyの値が決まらない生成元は、射をたどれないので組に入らない。
+    /// attrは、`#attr a -> x: t | b!x`のように行き先のattr bそのものに写る元のattr aから値を取る。
+    pub fn pi(&self, instance: &Instance) -> Result<Instance, String> {
+        let path_rules = self.target_rules();
+        let rules = instance.deduction_rules();
+        let mut names = instance.names.as_ref().clone();
+        names.extend(self.names.as_ref().clone());
+        let mut next_gen = names.next_gen_id();
+
+        // 行き先のentityごとの圏
+        let mut commas = BTreeMap::new();
+        for entity in &self.target.entities {
+            let Type::Unary(tid) = entity else {
+                continue;
+            };
+            let origin = GenId(next_gen);
+            next_gen += 1;
+            let comma = self.comma(tid, origin, &path_rules)?;
+            commas.insert(tid.clone(), comma);
+        }
+
+        // 元のfkeyを生成元に適用した値(生成元にならなければNone)
+        let mut fkey_values = BTreeMap::new();
+        for op in &self.source.fkeys {
+            let Type::Unary(dom) = op.dom.as_ref() else {
+                continue;
+            };
+            for g in instance.generators_of(dom) {
+                let t = Rc::new(TermInner::Fun(op.id.clone(), vec![Rc::new(TermInner::Gen(g.id.clone()))]));
+                let value = normalize(instance, &rules, t);
+                fkey_values.insert((op.id.clone(), g.id.clone()), as_gen(&value).cloned());
+            }
+        }
+
+        // 生成元: 各対象に生成元を選び、射の両端を選び終えた時点で両立を調べる
+        let mut elements: BTreeMap<TypeId, Vec<(GenId, Vec<GenId>)>> = BTreeMap::new();
+        let mut elems = vec![];
+        for (tid, comma) in &commas {
+            let mut families = vec![];
+            choose(instance, comma, &fkey_values, vec![], &mut families);
+            let entity_name = names.sort_name(tid).unwrap_or("?").to_string();
+            let mut rows = vec![];
+            for family in families {
+                let gid = GenId(next_gen);
+                next_gen += 1;
+                let components = family
+                    .iter()
+                    .map(|g| instance.names.gen_name(g).unwrap_or("?"))
+                    .collect::<Vec<_>>();
+                let name = format!("{}[{}]", entity_name, components.join(", "));
+                names.insert(name.clone(), Symbol::Gen(gid.clone()));
+                elems.push(Elem::Gen(Generator::new(gid.clone(), name, tid.clone())));
+                rows.push((gid, family));
+            }
+            elements.insert(tid.clone(), rows);
+        }
+
+        let mut data = vec![];
+        // fkey g: t -> t'の値は、t'の対象(s, q)ごとに、tの対象(s, q∘g)で選んだ生成元を集めた組
+        for op in &self.target.fkeys {
+            let (Type::Unary(dom), Type::Unary(cod)) = (op.dom.as_ref(), op.cod.as_ref()) else {
+                continue;
+            };
+            let (Some(from), Some(to)) = (commas.get(dom), commas.get(cod)) else {
+                continue;
+            };
+            let step = Rc::new(TermInner::Fun(op.id.clone(), vec![Rc::new(TermInner::Gen(from.origin.clone()))]));
+            let positions = to
+                .objects
+                .iter()
+                .map(|(src, q)| {
+                    let p = self.normalize_path(&path_rules, replace_gen(q, &to.origin, &step));
+                    from.objects.iter().position(|(s, r)| s == src && r == &p)
+                })
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| format!("Pi cannot follow the foreign key {}", names.oper_name(&op.id).unwrap_or("?")))?;
+            for (gid, family) in &elements[dom] {
+                let image = positions.iter().map(|i| family[*i].clone()).collect::<Vec<_>>();
+                if let Some((target, _)) = elements[cod].iter().find(|(_, f)| f == &image) {
+                    data.push((
+                        Rc::new(TermInner::Fun(op.id.clone(), vec![Rc::new(TermInner::Gen(gid.clone()))])),
+                        Rc::new(TermInner::Gen(target.clone())),
+                    ));
+                }
+            }
+        }
+        // attr b: t -> τの値は、bそのものに写る元のattr a: s -> τを(s, t自身)で選んだ生成元に適用したもの
+        for op in &self.target.attrs {
+            let Type::Unary(dom) = op.dom.as_ref() else {
+                continue;
+            };
+            let Some(comma) = commas.get(dom) else {
+                continue;
+            };
+            let origin = Rc::new(TermInner::Gen(comma.origin.clone()));
+            let source = self.source.attrs.iter().find_map(|src| {
+                let image = self.attrs.get(&src.id)?;
+                let direct = Rc::new(TermInner::Fun(op.id.clone(), vec![Rc::new(TermInner::Var(image.var.clone()))]));
+                let Type::Unary(entity) = src.dom.as_ref() else {
+                    return None;
+                };
+                let position = comma.objects.iter().position(|(s, p)| s == entity && p == &origin)?;
+                (image.term == direct).then_some((src.id.clone(), position))
+            });
+            let Some((attr, position)) = source else {
+                continue;
+            };
+            for (gid, family) in &elements[dom] {
+                let t = Rc::new(TermInner::Fun(attr.clone(), vec![Rc::new(TermInner::Gen(family[position].clone()))]));
+                let value = normalize(instance, &rules, t);
+                if is_value(instance, &value) {
+                    data.push((Rc::new(TermInner::Fun(op.id.clone(), vec![Rc::new(TermInner::Gen(gid.clone()))])), value));
+                }
+            }
+        }
+
+        let nulls = instance.nulls().cloned().map(Elem::Null).collect::<Vec<_>>();
+        Ok(build(names, self.target.clone(), [elems, nulls].concat(), data))
+    }
+
+    /// 行き先のentity tから出るパスを列挙し、(t ↓ F)を作る
+    fn comma(&self, tid: &TypeId, origin: GenId, path_rules: &Vec<Rule>) -> Result<Comma, String> {
+        let mut paths = vec![(Rc::new(TermInner::Gen(origin.clone())), tid.clone())];
+        let mut i = 0;
+        while i < paths.len() {
+            if paths.len() > PATH_LIMIT {
+                return Err(format!(
+                    "Pi needs finitely many paths from {}, but found more than {}",
+                    self.names.sort_name(tid).unwrap_or("?"),
+                    PATH_LIMIT
+                ));
+            }
+            let (p, sort) = paths[i].clone();
+            for op in self.target.fkeys.iter().filter(|op| op.dom.as_ref() == &Type::Unary(sort.clone())) {
+                let Type::Unary(cod) = op.cod.as_ref() else {
+                    continue;
+                };
+                let q = self.normalize_path(path_rules, Rc::new(TermInner::Fun(op.id.clone(), vec![p.clone()])));
+                if paths.iter().all(|(r, _)| r != &q) {
+                    paths.push((q, cod.clone()));
+                }
+            }
+            i += 1;
+        }
+
+        let mut objects = vec![];
+        for (p, sort) in &paths {
+            for entity in &self.source.entities {
+                if let Type::Unary(src) = entity {
+                    if self.entities.get(src) == Some(sort) {
+                        objects.push((src.clone(), p.clone()));
+                    }
+                }
+            }
+        }
+
+        let mut arrows = vec![];
+        for (i, (src, p)) in objects.iter().enumerate() {
+            for op in self.source.fkeys.iter().filter(|op| op.dom.as_ref() == &Type::Unary(src.clone())) {
+                let (Some(image), Type::Unary(cod)) = (self.fkeys.get(&op.id), op.cod.as_ref()) else {
+                    continue;
+                };
+                let q = self.normalize_path(path_rules, image.apply(p.clone()));
+                if let Some(j) = objects.iter().position(|(s, r)| s == cod && r == &q) {
+                    arrows.push((i, op.id.clone(), j));
+                }
+            }
+        }
+        Ok(Comma { origin, objects, arrows })
+    }
+
+    fn normalize_path(&self, path_rules: &Vec<Rule>, t: Rc<TermInner>) -> Rc<TermInner> {
+        let term = Term {
+            context: Rc::new(Context::default()),
+            names: self.names.clone(),
+            inner: t,
+        };
+        term.normalize_with(path_rules, &self.target.theory.builtins).inner.clone()
+    }
+}
+
+/// (t ↓ F)の対象に、前から順に生成元を選ぶ
+fn choose(
+    instance: &Instance,
+    comma: &Comma,
+    fkey_values: &BTreeMap<(OperId, GenId), Option<GenId>>,
+    family: Vec<GenId>,
+    families: &mut Vec<Vec<GenId>>,
+) {
+    let depth = family.len();
+    // 両端を選び終えたばかりの射について、fkeyの値が行き先で選んだ生成元と一致するか
+    let compatible = comma.arrows.iter().filter(|(i, _, j)| (*i).max(*j) + 1 == depth).all(|(i, f, j)| {
+        fkey_values.get(&(f.clone(), family[*i].clone())) == Some(&Some(family[*j].clone()))
+    });
+    if !compatible {
+        return;
+    }
+    let Some((entity, _)) = comma.objects.get(depth) else {
+        families.push(family);
+        return;
+    };
+    for g in instance.generators_of(entity) {
+        let mut family = family.clone();
+        family.push(g.id.clone());
+        choose(instance, comma, fkey_values, family, families);
+    }
+}
+
+fn normalize(instance: &Instance, rules: &Vec<Rule>, t: Rc<TermInner>) -> Rc<TermInner> {
+    let term = Term {
+        context: Rc::new(Context::default()),
+        names: instance.names.clone(),
+        inner: t,
+    };
+    term.normalize_with(rules, &instance.schema.theory.builtins).inner.clone()
+}
+
+fn as_gen(t: &TermInner) -> Option<&GenId> {
+    match t {
+        TermInner::Gen(gid) => Some(gid),
+        _ => None,
+    }
+}
+
+/// fkey/attrを含まず、生成元はnullだけの項(attrの値として書けるもの)
+fn is_value(instance: &Instance, t: &TermInner) -> bool {
+    match t {
+        TermInner::Fun(oid, args) => {
+            let schema = &instance.schema;
+            !schema.fkeys.iter().chain(&schema.attrs).any(|op| &op.id == oid) && args.iter().all(|arg| is_value(instance, arg))
+        }
+        TermInner::Gen(gid) => instance.is_null(gid),
+        _ => true,
+    }
+}
+
+/// 項の中の生成元`gid`を`to`に置き換える
+fn replace_gen(t: &Rc<TermInner>, gid: &GenId, to: &Rc<TermInner>) -> Rc<TermInner> {
+    match t.as_ref() {
+        TermInner::Gen(g) if g == gid => to.clone(),
+        TermInner::Fun(oid, args) => Rc::new(TermInner::Fun(
+            oid.clone(),
+            args.iter().map(|arg| replace_gen(arg, gid, to)).collect(),
+        )),
+        _ => t.clone(),
+    }
+}
+
+fn build(names: Names, schema: crate::schema::Schema, elems: Vec<Elem>, data: Vec<(Rc<TermInner>, Rc<TermInner>)>) -> Instance {
+    let names = Rc::new(names);
+    let data = data
+        .into_iter()
+        .map(|(left, right)| Equation {
+            context: Rc::new(Context::default()),
+            names: names.clone(),
+            left,
+            right,
+        })
+        .collect();
+    Instance { names, schema, elems, data, lines: BTreeMap::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use combine::{eof, EasyParser, Parser};
+
+    use crate::{
+        eval::eval,
+        instance::{Elem, Instance},
+        parser::mapping::mapping_parser,
+        util::instance::{gen_names, value},
+        workspace::Workspace,
+    };
+
+    #[test]
+    fn test_delta() {
+        let ws = Workspace::new("example");
+        let f = ws.mapping("f").unwrap();
+        let delta = f.delta(&ws.instance("i").unwrap());
+        assert_eq!(gen_names(&delta, "Worker"), ["e1", "e2", "e3", "e4", "e5", "e6", "e7"]);
+        assert_eq!(gen_names(&delta, "Division"), ["d1", "d2", "d3"]);
+        assert_eq!(value(&delta, "dept!e2").as_deref(), Some("d2"));
+        assert_eq!(value(&delta, "ename!e2").as_deref(), Some("\"Noether\""));
+        // e7の所属はconstraintから上司のe6と同じd2と分かり、名前は分からないので値を与えない
+        assert_eq!(value(&delta, "dept!e7").as_deref(), Some("d2"));
+        assert_eq!(value(&delta, "ename!e7"), None);
+    }
+
+    #[test]
+    fn test_delta_query() {
+        let ws = Workspace::new("example");
+        let f = ws.mapping("f").unwrap();
+        let instance = ws.instance("i").unwrap();
+        let delta = f.delta(&instance);
+        let queried = eval(instance, f.delta_query()).unwrap();
+        assert_eq!(gen_names(&queried, "Worker").len(), 7);
+        assert_eq!(gen_names(&queried, "Division").len(), 3);
+        // 生成元の名前は違うので、従業員ごとの名前と部署名の組で比べる
+        let rows = |instance: &Instance| {
+            let mut rows = gen_names(instance, "Worker")
+                .iter()
+                .map(|g| {
+                    let dept = value(instance, &format!("dept!{}", g));
+                    let dname = dept.and_then(|d| value(instance, &format!("dname!{}", d)));
+                    (value(instance, &format!("ename!{}", g)), dname)
+                })
+                // e7の名前は分からない。Deltaは値を与えず、queryは`last!e7`のまま残す
+                .filter(|(ename, _)| ename.as_ref().is_some_and(|v| v.starts_with('"')))
+                .collect::<Vec<_>>();
+            rows.sort();
+            rows
+        };
+        assert_eq!(rows(&queried).len(), 6);
+        assert_eq!(rows(&queried), rows(&delta));
+    }
+
+    #[test]
+    fn test_sigma() {
+        let ws = Workspace::new("example");
+        let f = ws.mapping("f").unwrap();
+        let sigma = f.sigma(&ws.instance("w").unwrap()).unwrap();
+        assert_eq!(gen_names(&sigma, "Emp"), ["w1", "w2", "w3"]);
+        assert_eq!(value(&sigma, "wrk!w3").as_deref(), Some("v2"));
+        assert_eq!(value(&sigma, "last!w1").as_deref(), Some("\"Hopper\""));
+    }
+
+    #[test]
+    fn test_sigma_merges_generators() {
+        let ws = Workspace::new("example");
+        // mgrを恒等写像に潰すと、従業員はその上司と同じ生成元になる
+        let flat = ws.mapping("flat").unwrap();
+        let sigma = flat.sigma(&ws.instance("i").unwrap()).unwrap();
+        assert_eq!(gen_names(&sigma, "Emp"), ["e1", "e2", "e3", "e6"]);
+        assert_eq!(value(&sigma, "sec!d3").as_deref(), Some("e1"));
+    }
+
+    #[test]
+    fn test_pi() {
+        let ws = Workspace::new("example");
+        let f = ws.mapping("f").unwrap();
+        let pi = f.pi(&ws.instance("w").unwrap()).unwrap();
+        // Empの対象は(Worker, e) (Worker, mgr!e) (Division, wrk!e) (Worker, sec!wrk!e) (Worker, mgr!sec!wrk!e)で、
+        // 同じ部署の労働者の4つ組になる(v1は2人、v2は1人なので2^4 + 1)
+        assert_eq!(gen_names(&pi, "Emp").len(), 17);
+        assert_eq!(gen_names(&pi, "Dept").len(), 5);
+        assert_eq!(value(&pi, "wrk!Emp[w3, w3, v2, w3, w3]").as_deref(), Some("Dept[v2, w3, w3]"));
+        assert_eq!(value(&pi, "last!Emp[w2, w1, v1, w1, w2]").as_deref(), Some("\"Lovelace\""));
+        assert_eq!(value(&pi, "mgr!Emp[w2, w1, v1, w1, w2]").as_deref(), Some("Emp[w1, w1, v1, w1, w2]"));
+        // salに写る元のattrはないので値も与えない
+        assert!(pi.data.iter().all(|eq| !eq.left_term().to_string().starts_with("sal!")));
+        assert_eq!(pi.elems.iter().filter(|e| matches!(e, Elem::Null(_))).count(), 0);
+    }
+
+    #[test]
+    fn test_pi_needs_finite_paths() {
+        let ws = Workspace::new("example");
+        let input = "#source {\n#theory \"test\"\n#sort B\n}\n\
+                     #target {\n#theory \"test\"\n#sort A\n#fkey next: A -> A\n}\n#entity B -> A\n";
+        let (mapping, _) = mapping_parser(&ws).skip(eof()).easy_parse(input).unwrap();
+        assert!(mapping.check().is_empty());
+        assert!(mapping.pi(&Instance::default()).is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 use combine::parser::char::spaces;
 use combine::sep_end_by;
@@ -20,7 +20,7 @@ where
 {
     let var_parser = parse_variable::<Input>(types, ctxts);
     sep_end_by(var_parser, spaces()).map(move |vss: Vec<_>| {
-        let mut res_vss = HashMap::new();
+        let mut res_vss = BTreeMap::new();
         vss.into_iter().for_each(|vs| {
             res_vss.extend(vs);
         });
@@ -0,0 +1,55 @@
+use combine::{
+    attempt, between, eof, many, none_of, optional, position, sep_by1, sep_end_by,
+    parser::char::{char, newline, string},
+    stream::position::SourcePosition,
+    Parser, Stream,
+};
+
+/// 1行目が列名の、カンマ区切りの表。列名と、(行番号, 値)の行を返す
+/// `"..."`の中にはカンマや改行も書け、`""`は`"`になる。空行は読み飛ばす。
+pub fn csv_parser<Input>() -> impl Parser<Input, Output = (Vec<String>, Vec<(usize, Vec<String>)>)>
+where
+    Input: Stream<Token = char, Position = SourcePosition>,
+{
+    let field = || {
+        let escaped = none_of("\"".chars()).or(attempt(string("\"\"")).map(|_| '"'));
+        let quoted = between(char('"'), char('"'), many::<String, _, _>(escaped));
+        quoted.or(many::<String, _, _>(none_of(",\"\r\n".chars())))
+    };
+    let record = || sep_by1::<Vec<String>, _, _, _>(field(), char(','));
+    let eol = || optional(char('\r')).with(newline());
+
+    let rows = sep_end_by::<Vec<_>, _, _, _>(position().and(record()), eol()).map(|rows| {
+        rows.into_iter()
+            .filter(|(_, fields): &(SourcePosition, Vec<String>)| fields != &[""])
+            .map(|(pos, fields)| (pos.line as usize, fields))
+            .collect()
+    });
+    record().and(optional(eol()).with(rows)).skip(eof())
+}
+
+#[cfg(test)]
+mod tests {
+    use combine::{stream::position::Stream, EasyParser};
+
+    use crate::parser::csv::csv_parser;
+
+    #[test]
+    fn test_csv_parser() {
+        let input = "id,last,sal\r\ne1,Gauss,250\r\n\r\ne2,\"Noether, \"\"Emmy\"\"\",\ne3,\"two\nlines\",1\n";
+        let ((header, rows), _) = csv_parser().easy_parse(Stream::new(input)).unwrap();
+        assert_eq!(header, vec!["id", "last", "sal"]);
+        assert_eq!(
+            rows,
+            vec![
+                (2, vec!["e1".to_string(), "Gauss".to_string(), "250".to_string()]),
+                (4, vec!["e2".to_string(), "Noether, \"Emmy\"".to_string(), "".to_string()]),
+                (5, vec!["e3".to_string(), "two\nlines".to_string(), "1".to_string()]),
+            ]
+        );
+
+        let ((header, rows), _) = csv_parser().easy_parse(Stream::new("id,last")).unwrap();
+        assert_eq!((header.len(), rows.len()), (2, 0));
+        assert!(csv_parser().easy_parse(Stream::new("id,last\ne1,\"Gauss\n")).is_err());
+    }
+}
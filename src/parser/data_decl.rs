@@ -1,36 +1,42 @@
 use std::{
-    collections::HashMap, rc::Rc};
+    collections::BTreeMap, rc::Rc};
 
 use combine::{
+    parser,
     parser::char::{spaces, string},
     Parser, Stream,
 };
 
 use crate::{
-    context::Context, context_table::CtxtTable, equation::Equation, id::{OperId}, 
+    context::Context, context_table::CtxtTable, equation::Equation, id::{GenId, OperId}, 
     parser::{DIRECTIVE_SIGN, term::terminner::oper::terminner_parser}, symbol_table::SymbolTable,
 };
 
+/// `#data`の等式
+/// 生成元はschemaの`#rule`の変数とは別の名前空間なので、変数のない空の文脈で読む。
 pub fn data_decl_parser<'a, Input>(
     opers: &'a SymbolTable<OperId>,
-    ctxts: &'a CtxtTable,    
+    gens: &'a SymbolTable<GenId>,
 ) -> impl Parser<Input, Output = Equation> + 'a
 where
     Input: Stream<Token = char> + 'a,
 {
-    let left_parser = terminner_parser(ctxts, opers);
-    let right_parser = terminner_parser(ctxts, opers);
+    let term_parser = move || {
+        parser(move |input: &mut Input| {
+            let ctxts = CtxtTable::new();
+            terminner_parser(&ctxts, opers, Some(gens)).parse_stream(input).into_result()
+        })
+    };
 
     string(DIRECTIVE_SIGN)
         .and(string("data"))
         .and(spaces())
-        .with(left_parser.skip(spaces()).skip(string("=").skip(spaces())))
-        .and(right_parser)
+        .with(term_parser().skip(spaces()).skip(string("=").skip(spaces())))
+        .and(term_parser())
         .map(|(left, right)| -> Equation {
-            let context = Context(HashMap::new());
-            let mut names = ctxts.current_var_table();
-            let oper_names = opers.current_table();
-            names.extend(oper_names);
+            let context = Context(BTreeMap::new());
+            let mut names = opers.current_table();
+            names.extend(gens.current_table());
             Equation {
                 context: context.into(),
                 names: names.into(),
@@ -42,23 +48,72 @@ where
 
 #[cfg(test)]
 mod tests {
-    use crate::{context_table::CtxtTable, id::{OperId}, parser::data_decl::data_decl_parser, symbol_table::SymbolTable};
+    use crate::{id::{GenId, OperId}, parser::data_decl::data_decl_parser, symbol_table::SymbolTable, term::TermInner};
     use combine::EasyParser;
     
     #[test]
     fn test_data_decl_parser() {
+        let input = "#data wrk!e1 = d3";
+
+        let opers = SymbolTable::<OperId>::new();
+        let wrk = opers.assign("wrk".to_string());
+        let gens = SymbolTable::<GenId>::new();
+        let e1 = gens.assign("e1".to_string());
+        let d3 = gens.assign("d3".to_string());
+
+        let result = data_decl_parser(&opers, &gens).easy_parse(input);
+        dbg!(&result);
+        let eq = result.unwrap().0;
+        assert_eq!(eq.left.as_ref(), &TermInner::Fun(wrk, vec![TermInner::Gen(e1).into()]));
+        assert_eq!(eq.right.as_ref(), &TermInner::Gen(d3));
+        assert_eq!(eq.to_string(), "Eq<wrk!e1 = d3>");
+    }
+
+    #[test]
+    fn test_data_decl_parser_semicolon() {
         let input = "#data wrk!e1; = d3;";
 
-        let ctxts = CtxtTable::new();
-        ctxts.assign_to_current("".to_string());
+        let opers = SymbolTable::<OperId>::new();
+        let wrk = opers.assign("wrk".to_string());
+        let gens = SymbolTable::<GenId>::new();
+        let e1 = gens.assign("e1".to_string());
+        let d3 = gens.assign("d3".to_string());
+
+        // 生成元を引数のない演算子として書いていた形も読める
+        let (eq, rest) = data_decl_parser(&opers, &gens).easy_parse(input).unwrap();
+        assert_eq!(rest, "");
+        assert_eq!(eq.left.as_ref(), &TermInner::Fun(wrk, vec![TermInner::Gen(e1).into()]));
+        assert_eq!(eq.right.as_ref(), &TermInner::Gen(d3));
+    }
 
+    #[test]
+    fn test_data_decl_parser_undeclared_generator() {
+        let input = "#data wrk!e9 = d3";
         let opers = SymbolTable::<OperId>::new();
         opers.assign("wrk".to_string());
-        opers.assign("e1".to_string());
-        opers.assign("d3".to_string());
+        let gens = SymbolTable::<GenId>::new();
+        gens.assign("d3".to_string());
 
-        let result = data_decl_parser(&opers, &ctxts).easy_parse(input);
-        dbg!(&result);
-        assert!(result.is_ok());
+        // 宣言されていない生成元も読み、Instance::validateが報告する
+        let (eq, _) = data_decl_parser(&opers, &gens).easy_parse(input).unwrap();
+        let e9 = gens.get("e9").unwrap();
+        assert_eq!(eq.left.as_ref(), &TermInner::Fun(opers.get("wrk").unwrap(), vec![TermInner::Gen(e9).into()]));
+    }
+
+    #[test]
+    fn test_data_decl_parser_gens_named_like_vars() {
+        // `#data`は変数のない文脈で読むので、`#rule`の変数と同じ名前`e`・`d`も生成元になる
+        let opers = SymbolTable::<OperId>::new();
+        let wrk = opers.assign("wrk".to_string());
+        let gens = SymbolTable::<GenId>::new();
+        let e = gens.assign("e".to_string());
+        let d = gens.assign("d".to_string());
+
+        for input in ["#data wrk!e = d", "#data wrk!e; = d;"] {
+            let (eq, rest) = data_decl_parser(&opers, &gens).easy_parse(input).unwrap();
+            assert_eq!(rest, "");
+            assert_eq!(eq.left.as_ref(), &TermInner::Fun(wrk.clone(), vec![TermInner::Gen(e.clone()).into()]));
+            assert_eq!(eq.right.as_ref(), &TermInner::Gen(d.clone()));
+        }
     }
 }
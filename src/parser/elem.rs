@@ -1,21 +1,18 @@
-use std::rc::Rc;
-
 use combine::parser::char::{alpha_num, spaces, string};
 use combine::stream::Stream;
 use combine::Parser;
 use combine::{many1, sep_by};
 
-use crate::id::{OperId, TypeId};
-use crate::instance::Elem;
-use crate::oper::Oper;
+use crate::id::{GenId, TypeId};
+use crate::instance::Generator;
 use crate::parser::r#type::type_unary_parser;
 use crate::r#type::Type;
 use crate::symbol_table::SymbolTable;
 
 pub fn parse_elem<'a, Input>(
     types: &'a SymbolTable<TypeId>,
-    opers: &'a SymbolTable<OperId>,  
-) -> impl Parser<Input, Output = Vec<Elem>> + 'a
+    gens: &'a SymbolTable<GenId>,
+) -> impl Parser<Input, Output = Vec<Generator>> + 'a
 where
     Input: Stream<Token = char> + 'a,
 {
@@ -25,21 +22,22 @@ where
         .skip(spaces())
         .and(type_unary_parser(types))
         .map(move |(v, t): (Vec<Vec<_>>, _)| {
+            let Type::Unary(entity) = t else {
+                unreachable!("type_unary_parser returns a unary type");
+            };
             v.into_iter().map(|ename| {
                 let ename: String = ename.into_iter().collect();
                 let ename = ename.trim().to_string();
 
-                let id = opers.assign(ename);
-                let dom = Rc::new(Type::Unary(TypeId(4)));
-                let cod = Rc::new(t.clone());
-                Elem::Oper(Oper::new(id.clone(), dom, cod))
+                let id = gens.assign(ename.clone());
+                Generator::new(id, ename, entity.clone())
             }).collect::<Vec<_>>()
         })
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::id::{OperId, TypeId};
+    use crate::id::{GenId, TypeId};
     use crate::symbol_table::SymbolTable;
     use crate::parser::elem::parse_elem;
     use combine::EasyParser;
@@ -48,25 +46,27 @@ mod tests {
     fn test_parse_elem() {
         let types = SymbolTable::<TypeId>::new();
         types.insert("Person".to_string(), TypeId(100));
-        let opers = SymbolTable::<OperId>::init_with(OperId(3));
+        let gens = SymbolTable::<GenId>::new();
 
         let ctxt_example = "e: Person";
 
-        let r = parse_elem(&types, &opers).easy_parse(ctxt_example);
+        let r = parse_elem(&types, &gens).easy_parse(ctxt_example);
         dbg!(&r);
-        assert!(r.is_ok());
+        let (generators, _) = r.unwrap();
+        assert_eq!(generators[0].name, "e");
+        assert_eq!(generators[0].entity, TypeId(100));
+        assert_eq!(gens.get("e"), Some(generators[0].id.clone()));
     }
 
     #[test]
     fn test_parse_variable2() {
         let types = SymbolTable::<TypeId>::new();
         types.insert("Person".to_string(), TypeId(100));
-        let opers = SymbolTable::<OperId>::init_with(OperId(3));
+        let gens = SymbolTable::<GenId>::new();
 
         let ctxt_example = "e1 e2 e3: Person";
-        let r = parse_elem(&types, &opers).easy_parse(ctxt_example);
+        let r = parse_elem(&types, &gens).easy_parse(ctxt_example);
         dbg!(&r);
         assert!(r.is_ok());
     }
 }
-
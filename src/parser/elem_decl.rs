@@ -4,25 +4,25 @@ use combine::{
 };
 
 use crate::{
-    id::{OperId, TypeId}, instance::Elem, parser::{DIRECTIVE_SIGN, elems::elems_parser}, symbol_table::SymbolTable
+    id::{GenId, TypeId}, instance::Generator, parser::{DIRECTIVE_SIGN, elems::elems_parser}, symbol_table::SymbolTable
 };
 
 pub fn elem_decl_parser<'a, Input>(
     types: &'a SymbolTable<TypeId>,
-    opers: &'a SymbolTable<OperId>,
-) -> impl Parser<Input, Output = Vec<Elem>> + 'a
+    gens: &'a SymbolTable<GenId>,
+) -> impl Parser<Input, Output = Vec<Generator>> + 'a
 where
     Input: Stream<Token = char> + 'a,
 {
     string(DIRECTIVE_SIGN)
         .and(string("elem"))
         .and(spaces())
-        .with(elems_parser(types, opers))
+        .with(elems_parser(types, gens))
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::id::{OperId, TypeId};
+    use crate::id::{GenId, TypeId};
     use crate::symbol_table::SymbolTable;
     use crate::parser::elem_decl::elem_decl_parser;
     use combine::EasyParser;
@@ -31,10 +31,10 @@ mod tests {
     fn test_parse_elem_decl() {
         let types = SymbolTable::<TypeId>::new();
         types.insert("Emp".to_string(), TypeId(8));
-        let opers = SymbolTable::<OperId>::init_with(OperId(3));
+        let gens = SymbolTable::<GenId>::new();
 
         let input = "#elem e1 e2 e3 e4 e5 e6 e7: Emp";
-        let result = elem_decl_parser(&types, &opers).easy_parse(input);
+        let result = elem_decl_parser(&types, &gens).easy_parse(input);
         dbg!(&result);
         assert!(result.is_ok());
     }
@@ -3,19 +3,19 @@ use combine::sep_end_by;
 use combine::stream::Stream;
 use combine::Parser;
 
-use crate::id::{OperId, TypeId};
-use crate::instance::Elem;
+use crate::id::{GenId, TypeId};
+use crate::instance::Generator;
 use crate::parser::elem::parse_elem;
 use crate::symbol_table::SymbolTable;
 
 pub fn elems_parser<'a, Input>(
     types: &'a SymbolTable<TypeId>,
-    opers: &'a SymbolTable<OperId>,
-) -> impl Parser<Input, Output = Vec<Elem>> + 'a
+    gens: &'a SymbolTable<GenId>,
+) -> impl Parser<Input, Output = Vec<Generator>> + 'a
 where
     Input: Stream<Token = char> + 'a,
 {
-    let var_parser = parse_elem::<Input>(types, opers);
+    let var_parser = parse_elem::<Input>(types, gens);
     sep_end_by(var_parser, spaces()).map(move |vss: Vec<_>| {
         let mut res_vss = vec![];
         vss.into_iter().for_each(|vs| {
@@ -28,7 +28,7 @@ where
 
 #[cfg(test)]
 mod tests {
-    use crate::id::{OperId, TypeId};
+    use crate::id::{GenId, TypeId};
     use crate::symbol_table::SymbolTable;
     use crate::parser::elems::elems_parser;
     use combine::EasyParser;
@@ -38,12 +38,12 @@ mod tests {
         let types = SymbolTable::<TypeId>::init_with(TypeId(3));
         types.insert("Bool".to_string(), TypeId(2));
         types.insert("Int".to_string(), TypeId(3));
-        let opers = SymbolTable::<OperId>::init_with(OperId(3));
+        let gens = SymbolTable::<GenId>::new();
 
         let ctxt_example = "x: Int p q: Bool";
 
-        let r = elems_parser(&types, &opers).easy_parse(ctxt_example);
-        dbg!(&opers);
+        let r = elems_parser(&types, &gens).easy_parse(ctxt_example);
+        dbg!(&gens);
         dbg!(&types);
         dbg!(&r);
         assert!(r.is_ok());
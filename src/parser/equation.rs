@@ -22,8 +22,8 @@ where
     Input: Stream<Token = char> + 'a,
 {
     let context_parser = context_parser::<Input>(types, ctxts);
-    let left_parser = terminner_parser(ctxts, opers);
-    let right_parser = terminner_parser(ctxts, opers);
+    let left_parser = terminner_parser(ctxts, opers, None);
+    let right_parser = terminner_parser(ctxts, opers, None);
 
     context_parser
         .skip(spaces())
@@ -32,9 +32,9 @@ where
         .and(left_parser.skip(spaces()).skip(string("=").skip(spaces())))
         .and(right_parser)
         .map(|((context, left), right)| {
-            let mut names = ctxts.current_var_table();
-            let oper_names = opers.current_table();
-            names.extend(oper_names);
+            let mut global = types.current_table();
+            global.extend(opers.current_table());
+            let names = ctxts.scoped_var_table(global);
             Equation {
                 context: context.into(),
                 names: names.into(),
@@ -50,6 +50,7 @@ mod tests {
 
     use crate::{
         context_table::CtxtTable,
+        id::VarId,
         parser::equation::equation_parser,
         util::{opers, types},
     };
@@ -64,4 +65,22 @@ mod tests {
         let result = equation_parser(&types, &opers, &ctxts).easy_parse(input);
         println!("{}", &result.unwrap().0);
     }
+
+    #[test]
+    fn test_equation_names_are_scoped() {
+        let types = types(vec!["Bool"]);
+        let opers = opers(vec!["f"]);
+        let ctxts = CtxtTable::new();
+
+        let (first, _) = equation_parser(&types, &opers, &ctxts).easy_parse("a: Bool | f![a] = a").unwrap();
+        ctxts.complete();
+        let (second, _) = equation_parser(&types, &opers, &ctxts).easy_parse("b: Bool | f![b] = b").unwrap();
+        // 変数はそれぞれの文脈のものだけを持ち、型・演算子は共通の表から引く
+        assert_eq!(first.names.var_id("a"), Some(VarId(0)));
+        assert_eq!(second.names.var_id("a"), None);
+        assert_eq!(second.names.var_name(&VarId(0)), Some("b"));
+        assert_eq!(second.names.type_id("Bool"), types.get("Bool"));
+        assert_eq!(second.names.oper_id("f"), opers.get("f"));
+        assert_eq!(second.names.entries().len(), 3);
+    }
 }
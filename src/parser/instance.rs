@@ -1,68 +1,196 @@
 use std::rc::Rc;
 
-use combine::{attempt, parser::char::spaces, sep_end_by, Parser, Stream};
+use combine::{attempt, parser::char::spaces, position, sep_end_by, stream::position::SourcePosition, Parser, Stream};
 
 use crate::{
-    context_table::CtxtTable, equation::Equation, id::{OperId, TypeId}, instance::{Elem, Instance},
+    equation::Equation, instance::{Elem, Generator, Instance},
     parser::{
-        data_decl::data_decl_parser, elem_decl::elem_decl_parser, schema_decl::schema_decl_parser,
+        data_decl::data_decl_parser, elem_decl::elem_decl_parser, parse_error,
+        schema_decl::schema_decl_parser,
     },
-    schema::Schema, symbol_table::SymbolTable, 
+    r#type::Type, schema::Schema, workspace::Workspace,
 };
 
+/// instanceファイル全体
+/// `#data`の行を`Instance::lines`に残すので、入力は位置を行と列で数えるものに限る。
 pub fn instance_parser<'a, Input>(
-    types: &'a SymbolTable<TypeId>,
-    opers: &'a SymbolTable<OperId>,
-    ctxts: &'a CtxtTable,
+    ws: &'a Workspace,
 ) -> impl Parser<Input, Output = Instance> + 'a
 where
-    Input: Stream<Token = char> + 'a,
+    Input: Stream<Token = char, Position = SourcePosition> + 'a,
 {
     #[derive(Clone)]
     enum Decl {
-        Schema(Schema),
-        Elem(Vec<Elem>),
-        Data(Equation),
+        Schema(Box<Schema>),
+        Elem(Vec<Generator>),
+        Data(usize, Equation),
     }
 
-    let elem_parser = elem_decl_parser(types, opers);
-    let data_parser = data_decl_parser(opers, ctxts);
+    let (types, opers, gens) = (&ws.types, &ws.opers, &ws.gens);
+    let elem_parser = elem_decl_parser(types, gens);
+    let data_parser = data_decl_parser(opers, gens);
 
-    let decl_parsers = attempt(schema_decl_parser(types, opers, ctxts).map(Decl::Schema))
+    let decl_parsers = attempt(schema_decl_parser(ws).map(|sch| Decl::Schema(Box::new(sch))))
         .or(attempt(elem_parser.map(Decl::Elem)))
-        .or(data_parser.map(Decl::Data));
+        .or(position().and(data_parser).map(|(pos, eq): (SourcePosition, _)| Decl::Data(pos.line as usize, eq)));
 
-    sep_end_by(decl_parsers, spaces()).map(|decls: Vec<Decl>| {
+    sep_end_by(decl_parsers, spaces()).and_then(move |decls: Vec<Decl>| {
         let mut instance = Instance::default();
         let mut names = types.current_table().clone();
         names.extend(opers.current_table().clone());
+        names.extend(gens.current_table().clone());
         instance.names = Rc::new(names);
+        let mut lines = vec![];
         for decl in decls {
             match decl {
-                Decl::Schema(sch) => instance.schema = sch,
+                Decl::Schema(sch) => instance.schema = *sch,
                 Decl::Elem(elems) => {
-                    instance.elems.extend(elems);
+                    instance.elems.extend(elems.into_iter().map(Elem::Gen));
+                }
+                Decl::Data(line, eq) => {
+                    lines.push(line);
+                    instance.data.push(eq);
+                }
+            }
+        }
+
+        // 生成元のentityはschemaで宣言されていなければならない
+        for elem in &instance.elems {
+            if let Elem::Gen(g) = elem {
+                if !instance.schema.entities.contains(&Type::Unary(g.entity.clone())) {
+                    let entity = instance.names.sort_name(&g.entity).unwrap_or("?").to_string();
+                    return Err(parse_error::<Input>(format!(
+                        "Generator '{}' belongs to '{}', which is not an entity of the schema",
+                        g.name, entity
+                    )));
                 }
-                Decl::Data(eq) => instance.data.push(eq),
             }
         }
 
-        instance
+        instance.freshen_gens(gens);
+        instance.collect_nulls().map_err(parse_error::<Input>)?;
+        for (eq, line) in instance.data.iter().zip(lines).rev() {
+            instance.lines.insert((eq.left.clone(), eq.right.clone()), line);
+        }
+
+        Ok(instance)
     })
 }
 
 #[test]
 fn test_parse_instance() {
-    use combine::EasyParser;
+    use combine::{stream::position::Stream, EasyParser};
 
-    let f = "instance/i.instance";
+    let f = "example/instance/i.instance";
     let input = std::fs::read_to_string(f).expect("Failed to read");
 
-    let types = SymbolTable::<TypeId>::new();
-    let opers = SymbolTable::<OperId>::new();
-    let ctxts = CtxtTable::new();
+    let ws = Workspace::new("example");
 
-    let result = instance_parser::<combine::easy::Stream<&str>>(&types, &opers, &ctxts)
-        .easy_parse(input.as_ref());
+    let result = instance_parser(&ws)
+        .easy_parse(Stream::new(input.as_str()));
     println!("{}", result.unwrap().0);
 }
+
+#[test]
+fn test_parse_instance_unknown_entity() {
+    use combine::{eof, stream::position::Stream, EasyParser};
+
+    let input = "#schema s\n#elem e1: Emp\n#elem x1: Nothing\n";
+    let ws = Workspace::new("example");
+
+    let result = instance_parser(&ws)
+        .skip(eof())
+        .easy_parse(Stream::new(input));
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_parse_instance_nulls() {
+    use combine::{eof, stream::position::Stream, EasyParser};
+
+    let ws = Workspace::new("example");
+    let input = "#schema s\n#elem e1 e2: Emp\n#data sal!e1 = ?x\n#data sal!e2 = ?y\n#data last!e1 = ?n\n";
+    let instance = instance_parser(&ws)
+        .skip(eof())
+        .easy_parse(Stream::new(input))
+        .unwrap()
+        .0;
+    let nulls = instance
+        .nulls()
+        .map(|g| (g.name.clone(), instance.names.sort_name(&g.entity).unwrap().to_string()))
+        .collect::<std::collections::BTreeSet<_>>();
+    let expected = [("?n", "Str"), ("?x", "Int"), ("?y", "Int")]
+        .map(|(n, s)| (n.to_string(), s.to_string()));
+    assert_eq!(nulls, expected.into_iter().collect());
+    assert!(instance.to_string().contains("sal!e1 = ?x"));
+
+    // 異なるsortで使われたnull
+    let input = "#schema s\n#elem e1: Emp\n#data sal!e1 = ?z\n#data last!e1 = ?z\n";
+    let result = instance_parser(&ws)
+        .skip(eof())
+        .easy_parse(Stream::new(input));
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_parse_instance_nulls_are_per_instance() {
+    use combine::{eof, stream::position::Stream, EasyParser};
+
+    let ws = Workspace::new("example");
+    let input = "#schema s\n#elem e1: Emp\n#data sal!e1 = ?x\n";
+    let parse = || {
+        instance_parser(&ws)
+            .skip(eof())
+            .easy_parse(Stream::new(input))
+            .unwrap()
+            .0
+    };
+    let (i1, i2) = (parse(), parse());
+    let null_of = |i: &Instance| i.nulls().map(|g| (g.id.clone(), g.name.clone())).collect::<Vec<_>>();
+    let (n1, n2) = (null_of(&i1), null_of(&i2));
+    // 同じ名前のnullでも、instanceが違えば別の生成元になる
+    assert_eq!((n1.len(), n1[0].1.as_str(), n2[0].1.as_str()), (1, "?x", "?x"));
+    assert_ne!(n1[0].0, n2[0].0);
+    assert_eq!(i1.to_string(), i2.to_string());
+}
+
+#[test]
+fn test_parse_instance_gens_are_per_instance() {
+    use combine::{eof, stream::position::Stream, EasyParser};
+
+    let ws = Workspace::new("example");
+    let i = ws.instance("i").unwrap();
+    let input = "#schema s\n#elem e1: Emp\n#data wrk!e1 = d1\n";
+    let other = instance_parser(&ws)
+        .skip(eof())
+        .easy_parse(Stream::new(input))
+        .unwrap()
+        .0;
+    // iと同じ名前の生成元でも別の生成元になり、iだけで宣言された名前は引けない
+    let gen_id = |instance: &Instance, name: &str| instance.names.gen_id(name);
+    assert_ne!(gen_id(&other, "e1"), gen_id(&i, "e1"));
+    assert_ne!(gen_id(&other, "d1"), gen_id(&i, "d1"));
+    assert_eq!(gen_id(&other, "e2"), None);
+    assert!(other.generators().all(|g| g.name == "e1"));
+}
+
+#[test]
+fn test_parse_instance_gens_named_like_rule_vars() {
+    use combine::{eof, stream::position::Stream, EasyParser};
+
+    // sの`#rule`の変数`e`・`d`と同じ名前の生成元も、`#data`で生成元として読む
+    let ws = Workspace::new("example");
+    let input = "#schema s\n#elem e: Emp\n#elem d: Dept\n#data wrk!e = d\n#data mgr!e; = e;\n";
+    let instance = instance_parser(&ws)
+        .skip(eof())
+        .easy_parse(Stream::new(input))
+        .unwrap()
+        .0;
+    assert_eq!(instance.data.iter().map(|eq| eq.to_string()).collect::<Vec<_>>(), ["Eq<wrk!e = d>", "Eq<mgr!e = e>"]);
+    // wrk・mgrは生成元に値があるので、値のないものだけが残る
+    let issues = instance.validate().iter().map(|issue| issue.to_string()).collect::<Vec<_>>();
+    assert_eq!(
+        issues,
+        ["missing: last!e has no value", "missing: sal!e has no value", "missing: sec!d has no value", "missing: name!d has no value"]
+    );
+}
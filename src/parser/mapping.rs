@@ -0,0 +1,142 @@
+use std::rc::Rc;
+
+use combine::{
+    attempt, many1, sep_end_by,
+    parser::char::{alpha_num, spaces, string},
+    Parser, Stream,
+};
+
+use crate::{
+    id::{OperId, TypeId},
+    mapping::{Image, Mapping},
+    parser::{parse_error, schema_decl::schema_ref_parser, term::terminner::oper::terminner_parser, variable::parse_variable, DIRECTIVE_SIGN},
+    r#type::Type,
+    schema::Schema,
+    symbol_table::Names,
+    workspace::Workspace,
+};
+
+/// mappingファイル全体
+/// ```text
+/// #source t
+/// #target s
+/// #entity Worker -> Emp
+/// #fkey dept -> w: Emp | wrk!w
+/// #attr ename -> w: Emp | last!w
+/// ```
+pub fn mapping_parser<'a, Input>(
+    ws: &'a Workspace,
+) -> impl Parser<Input, Output = Mapping> + 'a
+where
+    Input: Stream<Token = char> + 'a,
+{
+    #[derive(Clone)]
+    enum Decl {
+        Source(Schema),
+        Target(Schema),
+        Entity((TypeId, TypeId)),
+        Fkey((OperId, Image)),
+        Attr((OperId, Image)),
+    }
+
+    let directive = |name: &'static str| attempt(string(DIRECTIVE_SIGN).and(string(name))).skip(spaces());
+    let name = || many1::<String, _, _>(alpha_num());
+    let arrow = || spaces().skip(string("->")).skip(spaces());
+
+    let source_parser = directive("source").with(schema_ref_parser(ws));
+    let target_parser = directive("target").with(schema_ref_parser(ws));
+    let entity_parser = directive("entity")
+        .with(name().skip(arrow()).and(name()))
+        .map(move |(src, dst)| (ws.types.assign(src), ws.types.assign(dst)));
+    let fkey_parser = directive("fkey")
+        .with(name().skip(arrow()).and(image_parser(ws)))
+        .map(move |(src, image)| (ws.opers.assign(src), image));
+    let attr_parser = directive("attr")
+        .with(name().skip(arrow()).and(image_parser(ws)))
+        .map(move |(src, image)| (ws.opers.assign(src), image));
+
+    let decl_parsers = source_parser.map(Decl::Source)
+        .or(target_parser.map(Decl::Target))
+        .or(entity_parser.map(Decl::Entity))
+        .or(fkey_parser.map(Decl::Fkey))
+        .or(attr_parser.map(Decl::Attr));
+
+    sep_end_by(decl_parsers, spaces()).and_then(move |decls: Vec<Decl>| {
+        let mut mapping = Mapping::default();
+        let (mut source, mut target) = (None, None);
+        for decl in decls {
+            match decl {
+                Decl::Source(schema) => source = Some(schema),
+                Decl::Target(schema) => target = Some(schema),
+                Decl::Entity((src, dst)) => {
+                    mapping.entities.insert(src, dst);
+                }
+                Decl::Fkey((oid, image)) => {
+                    mapping.fkeys.insert(oid, image);
+                }
+                Decl::Attr((oid, image)) => {
+                    mapping.attrs.insert(oid, image);
+                }
+            }
+        }
+        let (Some(source), Some(target)) = (source, target) else {
+            return Err(parse_error::<Input>("A mapping needs both #source and #target".to_string()));
+        };
+        mapping.source = source;
+        mapping.target = target;
+
+        let mut global = ws.types.current_table();
+        global.extend(ws.opers.current_table());
+        let names = match ws.ctxts.vars.borrow().contains_key(&ws.ctxts.generator.current()) {
+            true => ws.ctxts.scoped_var_table(global),
+            false => Names::scope(Rc::new(global)),
+        };
+        mapping.names = Rc::new(names);
+        Ok(mapping)
+    })
+}
+
+/// `w: Emp | wrk!w`
+fn image_parser<'a, Input>(
+    ws: &'a Workspace,
+) -> impl Parser<Input, Output = Image> + 'a
+where
+    Input: Stream<Token = char> + 'a,
+{
+    parse_variable(&ws.types, &ws.ctxts)
+        .skip(spaces())
+        .skip(string("|"))
+        .skip(spaces())
+        .and(terminner_parser(&ws.ctxts, &ws.opers, None))
+        .and_then(|(vars, term)| {
+            // 引数は1つなので、変数もちょうど1つ
+            let mut vars = vars.into_iter();
+            match (vars.next(), vars.next()) {
+                (Some((var, Type::Unary(sort))), None) => Ok(Image { var, sort, term: Rc::new(term) }),
+                _ => Err(parse_error::<Input>("An image binds exactly one variable of an entity".to_string())),
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use combine::{eof, EasyParser, Parser};
+
+    use crate::{parser::mapping::mapping_parser, workspace::Workspace};
+
+    #[test]
+    fn test_mapping_parser() {
+        let ws = Workspace::new("example");
+        let input = "#source t\n#target s\n#entity Worker -> Emp\n#entity Division -> Dept\n\
+                     #fkey dept -> w: Emp | wrk!w\n#attr ename -> w: Emp | last!w\n#attr dname -> d: Dept | name!d\n";
+        let (mapping, _) = mapping_parser(&ws).skip(eof()).easy_parse(input).unwrap();
+        assert_eq!(mapping.entities.len(), 2);
+        assert_eq!(mapping.fkeys.len(), 1);
+        assert_eq!(mapping.attrs.len(), 2);
+        let dept = ws.opers.get("dept").unwrap();
+        assert_eq!(mapping.fkeys[&dept].sort, ws.types.get("Emp").unwrap());
+
+        assert!(mapping_parser(&ws).skip(eof()).easy_parse("#source t\n#entity Worker -> Emp\n").is_err());
+        assert!(mapping_parser(&ws).skip(eof()).easy_parse("#source t\n#target s\n#fkey dept -> w: Emp v: Emp | wrk!w\n").is_err());
+    }
+}
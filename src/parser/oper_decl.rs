@@ -1,17 +1,18 @@
+use crate::builtin::Builtin;
 use crate::id::{OperId, TypeId};
 use crate::oper::Oper;
 use crate::parser::oper::oper_parser;
-use crate::parser::DIRECTIVE_SIGN;
+use crate::parser::{parse_error, DIRECTIVE_SIGN};
 use crate::symbol_table::SymbolTable;
 use combine::parser::char;
-use combine::parser::char::spaces;
+use combine::parser::char::{alpha_num, spaces};
 use combine::stream::Stream;
-use combine::Parser;
+use combine::{attempt, many, many1, one_of, optional, Parser};
 
 pub fn oper_decl_parser<'a, Input>(
     types: &'a SymbolTable<TypeId>,
     opers: &'a SymbolTable<OperId>,
-) -> impl Parser<Input, Output = Oper> + 'a
+) -> impl Parser<Input, Output = (Oper, Option<Builtin>)> + 'a
 where
     Input: Stream<Token = char> + 'a,
 {
@@ -19,6 +20,20 @@ where
         .and(char::string("func"))
         .and(spaces())
         .with(oper_parser(types, opers))
+        .and(optional(builtin_parser()))
+}
+
+/// `#func`と同じ行に書く`#builtin lt`
+fn builtin_parser<Input>() -> impl Parser<Input, Output = Builtin>
+where
+    Input: Stream<Token = char>,
+{
+    let blank = || many::<String, _, _>(one_of(" \t".chars()));
+    // `#builtin`まで読めたら、名前が不正なときはエラーにする
+    attempt(blank().skip(char::string(DIRECTIVE_SIGN)).skip(char::string("builtin")))
+        .skip(blank())
+        .with(many1(alpha_num()))
+        .and_then(|name: String| name.parse::<Builtin>().map_err(parse_error::<Input>))
 }
 
 #[test]
@@ -36,3 +51,24 @@ fn test_oper_decl_parser() {
     dbg!(&result);
     assert!(result.is_ok());
 }
+
+#[test]
+fn test_oper_decl_parser_builtin() {
+    use crate::combine::EasyParser;
+
+    let opers = SymbolTable::<OperId>::new();
+    let types = SymbolTable::<TypeId>::new();
+
+    let input = "#func st: Int * Int -> Bool #builtin le\n#sort Int";
+    let ((_, builtin), rest) = oper_decl_parser(&types, &opers).easy_parse(input).unwrap();
+    assert_eq!(builtin, Some(Builtin::Le));
+    assert_eq!(rest, "\n#sort Int");
+
+    // 次の行の宣言は注釈ではない
+    let input = "#func st: Int * Int -> Bool\n#builtin le";
+    let ((_, builtin), _) = oper_decl_parser(&types, &opers).easy_parse(input).unwrap();
+    assert_eq!(builtin, None);
+
+    let input = "#func st: Int * Int -> Bool #builtin pow";
+    assert!(oper_decl_parser(&types, &opers).easy_parse(input).is_err());
+}
@@ -1,4 +1,4 @@
-use combine::{Parser, Stream};
+use combine::{attempt, many, Parser, Stream};
 
 use crate::{context::Context, context_table::CtxtTable, id::TypeId, parser::variable::parse_variable, symbol_table::SymbolTable};
 
@@ -11,12 +11,15 @@ where
 {
     use crate::combine::parser::char::{spaces, string};
 
+    // `#for e: Emp d: Dept`のように型の異なる変数を並べられる
     string("#for")
         .skip(spaces())
         .with(
             parse_variable::<Input>(&types, &ctxts)
         )
-        .map(|map| {
+        .and(many(attempt(spaces().with(parse_variable::<Input>(types, ctxts)))))
+        .map(|(mut map, rest): (_, Vec<_>)| {
+            rest.into_iter().for_each(|m| map.extend(m));
             Context(map)
         })
 }
@@ -36,4 +39,16 @@ mod tests {
         dbg!(&result);
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_for_decl_parser_multiple_sorts() {
+        let input = "#for e: Emp d1 d2: Dept\n#where";
+        let ctxts = CtxtTable::new();
+        let types = SymbolTable::<TypeId>::new();
+        types.insert("Emp".to_string(), TypeId(1));
+        types.insert("Dept".to_string(), TypeId(2));
+        let (context, rest) = for_decl_parser(&types, &ctxts).easy_parse(input).unwrap();
+        assert_eq!(context.0.len(), 3);
+        assert_eq!(rest, "\n#where");
+    }
 }   
@@ -1,32 +1,33 @@
-use combine::{Parser, Stream, many1, parser::char::alpha_num};
+use combine::{Parser, Stream, attempt, many1, sep_by1, parser::char::{alpha_num, char}};
 
-use crate::{context_table::CtxtTable, id::{OperId, VarId}, parser::{term::terminner::oper::terminner_parser}, symbol_table::SymbolTable, term::{TermInner}};
+use crate::{context_table::CtxtTable, id::{GenId, OperId, VarId}, parser::term::terminner::oper::terminner_parser, symbol_table::SymbolTable, term::{TermInner}};
 
 pub fn keys_decl_parser<'a, Input>( 
     opers: &'a SymbolTable<OperId>,
+    gens: &'a SymbolTable<GenId>,
     ctxts: &'a CtxtTable,
-) -> impl Parser<Input, Output = (OperId, VarId, TermInner)> + 'a
+) -> impl Parser<Input, Output = (OperId, Vec<(VarId, TermInner)>)> + 'a
 where
     Input: Stream<Token = char> + 'a,
 {
     use crate::combine::parser::char::{spaces, string};
 
     // Example:
-    // #keys wrk := [d -> e.wrk]
+    // #keys wrk := [d -> wrk!e]
+    // #keys pair := [d -> wrk!e, f -> mgr!e]
     // wrk: OperId
     // d: VarId (key)
-    // e.wrk: TermInner
+    // wrk!e: TermInner
+    let assign = keys_variable_parser(ctxts)
+        .skip(spaces().skip(string("->")).skip(spaces()))
+        .and(terminner_parser(ctxts, opers, Some(gens)));
+
     string("#keys")
         .skip(spaces())
         .with(keys_oper_parser(opers).skip(spaces()).skip(string(":=").skip(spaces())))
         .skip(string("[").skip(spaces()))
-        .and(keys_variable_parser(ctxts))
-        .skip(spaces().skip(string("->")).skip(spaces()))
-        .and(terminner_parser(ctxts, opers))
-        .skip(string("]").skip(spaces()))
-        .map(|((operid, varid), terminner)| {
-            (operid, varid, terminner)
-        })
+        .and(sep_by1(assign, attempt(spaces().skip(char(',')).skip(spaces()))))
+        .skip(spaces().skip(string("]")).skip(spaces()))
 }
 
 fn keys_oper_parser<'a, Input>(
@@ -35,6 +36,7 @@ fn keys_oper_parser<'a, Input>(
 where
     Input: Stream<Token = char> + 'a,
 {
+    // returnのattrと同じく、出力のschemaのfkeyとして名前を割り当てる
     many1(alpha_num())
         .map(move |name: String| {
             opers.assign(name)
@@ -51,4 +53,30 @@ where
         .map(move |name: String| {
             ctxts.assign_to_current(name)
         })
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use combine::EasyParser;
+
+    use crate::{context_table::CtxtTable, id::{GenId, OperId}, parser::query::keys_decl::keys_decl_parser, symbol_table::SymbolTable};
+
+    #[test]
+    fn test_keys_decl_parser() {
+        let ctxts = CtxtTable::new();
+        let opers = SymbolTable::<OperId>::new();
+        let gens = SymbolTable::<GenId>::new();
+        opers.assign("wrk".to_string());
+        opers.assign("mgr".to_string());
+        let e = ctxts.assign_to_current("e".to_string());
+
+        let (r, _) = keys_decl_parser(&opers, &gens, &ctxts).easy_parse("#keys wrk := [d -> wrk!e]").unwrap();
+        assert_eq!(r.0, opers.get("wrk").unwrap());
+        assert_eq!(r.1.len(), 1);
+
+        let input = "#keys pair := [d -> wrk!e, f -> mgr!e]";
+        let (r, _) = keys_decl_parser(&opers, &gens, &ctxts).easy_parse(input).unwrap();
+        assert_eq!(r.0, opers.get("pair").unwrap());
+        assert_eq!(r.1.len(), 2);
+        assert!(r.1[1].1.vars().contains(&crate::completion::subst::Var::Id(e)));
+    }
+}
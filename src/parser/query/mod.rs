@@ -1,36 +1,38 @@
-use combine::{attempt, parser::char::{spaces}, sep_end_by};
+use std::{collections::BTreeSet, rc::Rc};
 
-use crate::{context::Context, context_table::CtxtTable, equation::Equation, eval::QueryEntity, id::{OperId, TypeId, VarId}, instance::Instance, parser::{instance_decl::instance_decl_parser, query::{for_decl::for_decl_parser, keys_decl::keys_decl_parser, return_decl::return_decl_parser, where_decl::where_decl_parser}}, symbol_table::SymbolTable, term::TermInner};
+use combine::{attempt, many1, optional, parser::char::{alpha_num, char, spaces, string}, sep_end_by, sep_end_by1};
+
+use crate::{context::Context, eval::{Query, QueryEntity}, id::{OperId, TypeId, VarId}, parser::{DIRECTIVE_SIGN, instance_decl::instance_decl_parser, parse_error, query::{for_decl::for_decl_parser, keys_decl::keys_decl_parser, return_decl::{return_decl_parser, Return}, target_decl::target_decl_parser, where_decl::where_decl_parser}}, predicate::Predicate, r#type::Type, schema::Schema, symbol_table::Names, term::TermInner, workspace::Workspace};
 use combine::Parser;
 
 mod for_decl;
 mod keys_decl;
 mod return_decl;
+mod target_decl;
 mod where_decl;
 
 pub fn query_entity_parser<'a, Input>(
-    types: &'a SymbolTable<TypeId>,
-    opers: &'a SymbolTable<OperId>,
-    ctxts: &'a CtxtTable,
+    ws: &'a Workspace,
 ) -> impl combine::Parser<Input, Output = crate::eval::QueryEntity> + 'a
 where
     Input: combine::Stream<Token = char> + 'a,
 {
     #[derive(Clone)]
     enum Decl {
-        #[allow(unused)]Instance(Instance),
+        Instance(String),
         For(Context),
-        Where(Equation),
-        Attr((OperId, TermInner)),
-        Keys((OperId, VarId, TermInner)),
+        Where(Predicate),
+        Attr((OperId, Return)),
+        Keys((OperId, Vec<(VarId, TermInner)>)),
     }
 
-    let instance_parser = instance_decl_parser(types, opers, ctxts);
+    let (types, opers, gens, ctxts) = (&ws.types, &ws.opers, &ws.gens, &ws.ctxts);
+    let instance_parser = instance_decl_parser(ws);
 
     let for_parser = for_decl_parser(types, ctxts);
-    let where_parser = where_decl_parser(opers, ctxts);
-    let return_parser = return_decl_parser(opers, ctxts);
-    let keys_parser = keys_decl_parser(opers, ctxts);
+    let where_parser = where_decl_parser(opers, gens, ctxts);
+    let return_parser = return_decl_parser(types, opers, gens, ctxts);
+    let keys_parser = keys_decl_parser(opers, gens, ctxts);
 
     let decl_parsers = attempt(instance_parser.map(Decl::Instance))
         .or(attempt(for_parser.map(Decl::For)))
@@ -43,34 +45,143 @@ where
 
         for decl in decls {
             match decl {
-                Decl::Instance(_) => {},  // opersがほしい
+                Decl::Instance(name) => query_entity.instance = Some(name),
                 Decl::For(fr) => query_entity.fr.push(fr),
-                Decl::Where(eq) => {
-                    query_entity.wh.push(eq);
+                Decl::Where(pred) => {
+                    query_entity.wh.push(pred);
                 }
-                Decl::Attr(eq) => query_entity.ret.push(eq),
-                Decl::Keys(key) => query_entity.keys.push(key),
+                Decl::Attr((operid, Return::Term(term))) => query_entity.ret.push((operid, term)),
+                Decl::Attr((operid, Return::Aggregate(agg))) => query_entity.aggs.push((operid, agg)),
+                Decl::Keys((operid, transform)) => query_entity.keys.push((operid, None, transform)),
             }
         }
         query_entity
     })
 }
 
+/// `#entity Result { ... }`のブロック
+fn entity_block_parser<'a, Input>(
+    ws: &'a Workspace,
+) -> impl combine::Parser<Input, Output = QueryEntity> + 'a
+where
+    Input: combine::Stream<Token = char> + 'a,
+{
+    string(DIRECTIVE_SIGN)
+        .and(string("entity"))
+        .and(spaces())
+        .with(many1::<String, _, _>(alpha_num()))
+        .skip(spaces())
+        .skip(char('{'))
+        .skip(spaces())
+        .and(query_entity_parser(ws))
+        .skip(char('}'))
+        .map(move |(name, mut query_entity)| {
+            query_entity.entity = Some(ws.types.assign(name));
+            query_entity
+        })
+}
+
+/// queryファイル全体
+/// `#instance`(と`#target`)に続けて`#entity`ブロックを並べる形と、ブロックのない1つのentityだけの形がある。
+/// ブロックのない形で`#target`を書かなければ、行はどのentityにも属さない代入のままになる。
+pub fn query_parser<'a, Input>(
+    ws: &'a Workspace,
+) -> impl combine::Parser<Input, Output = Query> + 'a
+where
+    Input: combine::Stream<Token = char> + 'a,
+{
+    let blocks = instance_decl_parser(ws)
+        .skip(spaces())
+        .and(optional(target_decl_parser(ws).skip(spaces())))
+        .and(sep_end_by1(entity_block_parser(ws), spaces()))
+        .map(|((instance, target), entities)| (Some(instance), target, entities));
+    // ブロックのないqueryも`#instance`に続けて`#target`を書ける。行はその1つのentityの生成元になる
+    let anonymous = optional(attempt(
+        instance_decl_parser(ws).skip(spaces()).and(target_decl_parser(ws).skip(spaces())),
+    ))
+    .and(query_entity_parser(ws))
+    .and_then(|(header, mut query_entity): (Option<(String, Schema)>, QueryEntity)| {
+        let Some((instance, target)) = header else {
+            return Ok((query_entity.instance.clone(), None, vec![query_entity]));
+        };
+        let [Type::Unary(entity)] = target.entities.as_slice() else {
+            return Err(parse_error::<Input>("The #target of a query without #entity blocks must have exactly one entity".to_string()));
+        };
+        query_entity.instance = Some(instance.clone());
+        query_entity.entity = Some(entity.clone());
+        Ok((Some(instance), Some(target), vec![query_entity]))
+    });
+
+    attempt(blocks).or(anonymous).and_then(move |(instance, target, mut entities): (Option<String>, Option<Schema>, Vec<QueryEntity>)| {
+        // `#for`の変数は、このqueryの文脈ローカルな名前とする
+        let mut global = ws.types.current_table();
+        global.extend(ws.opers.current_table());
+        global.extend(ws.gens.current_table());
+        let names = match ws.ctxts.vars.borrow().contains_key(&ws.ctxts.generator.current()) {
+            true => ws.ctxts.scoped_var_table(global),
+            false => Names::scope(Rc::new(global)),
+        };
+
+        if let Err(msg) = resolve_keys(&mut entities, &names) {
+            return Err(parse_error::<Input>(msg));
+        }
+
+        // 宣言されていない生成元も項としては読めるので、`#instance`で宣言されているかをここで確かめる
+        if let Some(instance_name) = &instance {
+            let source = ws.instance(instance_name).map_err(parse_error::<Input>)?;
+            for gid in entities.iter().flat_map(QueryEntity::gens) {
+                let name = names.gen_name(&gid).unwrap_or("?");
+                if !source.generators().any(|g| g.name == name) {
+                    return Err(parse_error::<Input>(format!("Generator '{}' is not declared in instance {}", name, instance_name)));
+                }
+            }
+        }
+
+        Ok(Query { instance, target, entities, names: Rc::new(names) })
+    })
+}
+
+/// `#keys`の行き先を、変換の変数がちょうど`#for`の変数になっているブロックに決める
+fn resolve_keys(entities: &mut [QueryEntity], names: &Names) -> Result<(), String> {
+    let mut seen = BTreeSet::new();
+    if let Some(dup) = entities.iter().filter_map(|qe| qe.entity.clone()).find(|tid| !seen.insert(tid.clone())) {
+        return Err(format!("#entity {} is declared more than once", names.sort_name(&dup).unwrap_or("?")));
+    }
+
+    let targets = entities
+        .iter()
+        .filter_map(|qe| {
+            let vars = qe.fr.iter().flat_map(|c| c.0.keys().cloned()).collect::<BTreeSet<_>>();
+            qe.entity.clone().map(|tid| (tid, vars))
+        })
+        .collect::<Vec<(TypeId, BTreeSet<VarId>)>>();
+
+    for qe in entities.iter_mut().filter(|qe| qe.entity.is_some()) {
+        for (operid, target, transform) in qe.keys.iter_mut() {
+            let vars = transform.iter().map(|(varid, _)| varid.clone()).collect::<BTreeSet<_>>();
+            let candidates = targets.iter().filter(|(_, v)| v == &vars).collect::<Vec<_>>();
+            let fkey = names.oper_name(operid).unwrap_or("?");
+            match candidates.as_slice() {
+                [(tid, _)] => *target = Some(tid.clone()),
+                [] => return Err(format!("No #entity block binds exactly the variables of #keys {}", fkey)),
+                _ => return Err(format!("The target of #keys {} is ambiguous", fkey)),
+            }
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::{context_table::CtxtTable, id::{OperId, TypeId}, parser::query::query_entity_parser, symbol_table::SymbolTable};
+    use crate::{parser::query::{query_entity_parser, query_parser}, workspace::Workspace};
 
     #[test]
     fn test_query_entity_parser() {
-        let types = SymbolTable::<TypeId>::init_with(TypeId(0));
-        let opers = SymbolTable::<OperId>::init_with(OperId(0));
-        let ctxts = CtxtTable::new();
+        let ws = Workspace::new("example");
         use combine::EasyParser;
 
-        let f = "query/_.query";
-        let input = std::fs::read_to_string(f).expect("Failed to read");
-
-        let result = query_entity_parser::<combine::easy::Stream<&str>>(&types, &opers, &ctxts).easy_parse(input.as_ref());
+        let input = "#instance i\n#for e: Emp\n#where wrk!e = d2\n#return name := last!e\n#keys wrk := [d -> wrk!e]\n";
+        let result = query_entity_parser::<combine::easy::Stream<&str>>(&ws).easy_parse(input);
         assert!(result.is_ok(), "Parser failed: {:?}", result);
         let (query_entity, remaining) = result.unwrap();
         assert!(remaining.is_empty(), "Parser did not consume all input");
@@ -78,5 +189,24 @@ mod tests {
         assert_eq!(query_entity.wh.len(), 1);
         assert_eq!(query_entity.ret.len(), 1);
         assert_eq!(query_entity.keys.len(), 1);
+        assert_eq!(query_entity.instance, Some("i".to_string()));
+    }
+
+    #[test]
+    fn test_query_parser_without_blocks() {
+        let ws = Workspace::new("example");
+        use combine::EasyParser;
+
+        let f = "example/query/_.query";
+        let input = std::fs::read_to_string(f).expect("Failed to read");
+
+        // `#target`のただ1つのentityが、ブロックのないqueryの出力のentityになる
+        let (query, remaining) = query_parser::<combine::easy::Stream<&str>>(&ws).easy_parse(input.as_ref()).unwrap();
+        assert!(remaining.is_empty(), "Parser did not consume all input");
+        assert_eq!(query.instance, Some("i".to_string()));
+        assert!(query.target.is_some());
+        assert_eq!(query.entities.len(), 1);
+        assert_eq!(query.entities[0].entity, ws.types.get("Row"));
+        assert_eq!(query.entities[0].keys[0].1, ws.types.get("Row"));
     }
 }
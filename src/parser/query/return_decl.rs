@@ -1,16 +1,26 @@
-use combine::{Parser, Stream, many1, parser::char::{alpha_num, spaces, string}};
+use combine::{Parser, Stream, attempt, choice, many, many1, optional, sep_by1, parser::char::{alpha_num, char, spaces, string}};
 
-use crate::{context_table::CtxtTable, id::{OperId}, parser::{DIRECTIVE_SIGN, term::terminner::oper::terminner_parser}, symbol_table::SymbolTable, term::TermInner};
+use crate::{aggregate::{Aggregate, AggregateFn}, context::Context, context_table::CtxtTable, id::{GenId, OperId, TypeId}, parser::{DIRECTIVE_SIGN, parse_error, query::where_decl::predicate_parser, term::terminner::oper::terminner_parser, variable::parse_variable}, symbol_table::SymbolTable, term::TermInner};
+
+/// `#return`の右辺
+#[derive(Clone, Debug)]
+pub enum Return {
+    Term(TermInner),
+    Aggregate(Aggregate),
+}
 
 pub fn return_decl_parser<'a, Input>(
+    types: &'a SymbolTable<TypeId>,
     opers: &'a SymbolTable<OperId>,
+    gens: &'a SymbolTable<GenId>,
     ctxts: &'a CtxtTable,
-) -> impl Parser<Input, Output = (OperId, TermInner)> + 'a
+) -> impl Parser<Input, Output = (OperId, Return)> + 'a
 where
     Input: Stream<Token = char> + 'a,
 {
     let left_parser = return_oper_parser(opers);
-    let right_parser = terminner_parser(ctxts, opers);
+    let right_parser = attempt(aggregate_parser(types, opers, gens, ctxts).map(Return::Aggregate))
+        .or(terminner_parser(ctxts, opers, Some(gens)).map(Return::Term));
 
     string(DIRECTIVE_SIGN)
         .and(string("return"))
@@ -34,10 +44,59 @@ where
         })
 }
 
+fn aggregate_parser<'a, Input>(
+    types: &'a SymbolTable<TypeId>,
+    opers: &'a SymbolTable<OperId>,
+    gens: &'a SymbolTable<GenId>,
+    ctxts: &'a CtxtTable,
+) -> impl Parser<Input, Output = Aggregate> + 'a
+where
+    Input: Stream<Token = char> + 'a,
+{
+    // Example:
+    // sum[e: Emp | wrk!e = d] sal!e
+    // count[e: Emp | mgr!e = m, e != m]
+    // max[e: Emp] sal!e
+    let func_parser = choice((
+        attempt(string("count")).map(|_| AggregateFn::Count),
+        attempt(string("sum")).map(|_| AggregateFn::Sum),
+        attempt(string("min")).map(|_| AggregateFn::Min),
+        string("max").map(|_| AggregateFn::Max),
+    ));
+    let vars_parser = parse_variable::<Input>(types, ctxts)
+        .and(many(attempt(spaces().with(parse_variable::<Input>(types, ctxts)))))
+        .map(|(mut map, rest): (_, Vec<_>)| {
+            rest.into_iter().for_each(|m| map.extend(m));
+            Context(map)
+        });
+    let wh_parser = spaces()
+        .skip(char('|'))
+        .skip(spaces())
+        .with(sep_by1(predicate_parser(opers, gens, ctxts), attempt(spaces().skip(char(',')).skip(spaces()))));
+
+    func_parser
+        .skip(char('['))
+        .skip(spaces())
+        .and(vars_parser)
+        .and(optional(attempt(wh_parser)))
+        .skip(spaces().skip(char(']')))
+        .and(optional(attempt(spaces().with(terminner_parser(ctxts, opers, Some(gens))))))
+        .and_then(|(((func, vars), wh), term): (_, Option<TermInner>)| {
+            // countだけは集約する値を書かない
+            match (func, &term) {
+                (AggregateFn::Count, Some(_)) => return Err(parse_error::<Input>("count takes no value".to_string())),
+                (AggregateFn::Count, None) => {}
+                (_, None) => return Err(parse_error::<Input>(format!("{} needs a value to aggregate", func.name()))),
+                (_, Some(_)) => {}
+            }
+            Ok(Aggregate { func, vars, wh: wh.unwrap_or_default(), term })
+        })
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::{context_table::CtxtTable, id::{OperId}, parser::query::return_decl::return_decl_parser, symbol_table::SymbolTable};
-    use combine::EasyParser;
+    use crate::{aggregate::AggregateFn, context_table::CtxtTable, id::{GenId, OperId, TypeId}, parser::query::return_decl::{return_decl_parser, Return}, symbol_table::SymbolTable};
+    use combine::{eof, EasyParser, Parser};
      
     #[test]
     fn test_return_decl_parser() {
@@ -46,8 +105,37 @@ mod tests {
         ctxts.assign_to_current("e".to_string());
         let opers = SymbolTable::<OperId>::new();
         opers.insert("last".to_string(), OperId(2)); // Mocking an oper for testing
-        let result = return_decl_parser(&opers, &ctxts).easy_parse(input);
+        let gens = SymbolTable::<GenId>::new();
+        let types = SymbolTable::<TypeId>::new();
+
+        let result = return_decl_parser(&types, &opers, &gens, &ctxts).easy_parse(input);
         dbg!(&result);
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_return_decl_parser_aggregate() {
+        let ctxts = CtxtTable::new();
+        ctxts.assign_to_current("d".to_string());
+        let opers = SymbolTable::<OperId>::new();
+        opers.insert("wrk".to_string(), OperId(0));
+        opers.insert("sal".to_string(), OperId(1));
+        let gens = SymbolTable::<GenId>::new();
+        let types = SymbolTable::<TypeId>::new();
+        types.insert("Emp".to_string(), TypeId(1));
+        let mut parser = return_decl_parser::<combine::easy::Stream<&str>>(&types, &opers, &gens, &ctxts).skip(eof());
+
+        let (_, ret) = parser.easy_parse("#return total := sum[e: Emp | wrk!e = d, sal!e > 0] sal!e").unwrap().0;
+        let Return::Aggregate(agg) = ret else { panic!("not an aggregate: {:?}", ret) };
+        assert_eq!(agg.func, AggregateFn::Sum);
+        assert_eq!(agg.vars.0.len(), 1);
+        assert_eq!(agg.wh.len(), 2);
+        assert!(agg.term.is_some());
+
+        let (_, ret) = parser.easy_parse("#return n := count[e: Emp]").unwrap().0;
+        assert!(matches!(ret, Return::Aggregate(agg) if agg.func == AggregateFn::Count && agg.wh.is_empty()));
+
+        assert!(parser.easy_parse("#return n := count[e: Emp] sal!e").is_err());
+        assert!(parser.easy_parse("#return total := sum[e: Emp | wrk!e = d]").is_err());
+    }
 }   
\ No newline at end of file
@@ -0,0 +1,45 @@
+use combine::{
+    attempt,
+    parser::char::{spaces, string},
+    Parser, Stream,
+};
+
+use crate::{
+    parser::{schema_decl::schema_ref_parser, DIRECTIVE_SIGN},
+    schema::Schema,
+    workspace::Workspace,
+};
+
+/// queryの出力のschema
+/// `#target t`でschemaファイルを参照するか、`#target { ... }`にschemaの宣言をそのまま書く。
+pub fn target_decl_parser<'a, Input>(
+    ws: &'a Workspace,
+) -> impl Parser<Input, Output = Schema> + 'a
+where
+    Input: Stream<Token = char> + 'a,
+{
+    attempt(string(DIRECTIVE_SIGN).and(string("target")))
+        .skip(spaces())
+        .with(schema_ref_parser(ws))
+}
+
+#[cfg(test)]
+mod tests {
+    use combine::{eof, EasyParser, Parser};
+
+    use crate::{parser::query::target_decl::target_decl_parser, workspace::Workspace};
+
+    #[test]
+    fn test_target_decl_parser() {
+        let ws = Workspace::new("example");
+        let (schema, _) = target_decl_parser(&ws).skip(eof()).easy_parse("#target t").unwrap();
+        assert_eq!(schema.entities.len(), 2);
+
+        let input = "#target {\n#theory \"test\"\n#sort Worker\n#attr ename: Worker -> Str\n}";
+        let (schema, _) = target_decl_parser(&ws).skip(eof()).easy_parse(input).unwrap();
+        assert_eq!(schema.attrs.len(), 1);
+        assert_eq!(schema.attrs[0].id, ws.opers.get("ename").unwrap());
+
+        assert!(target_decl_parser(&ws).easy_parse("#target nothing").is_err());
+    }
+}
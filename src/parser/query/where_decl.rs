@@ -1,35 +1,131 @@
-use std::{collections::HashMap, rc::Rc};
+use std::{collections::BTreeMap, rc::Rc};
 
-use combine::{Parser, Stream, parser::char::{spaces, string}};
+use combine::{Parser, Stream, attempt, choice, count_min_max, many, parser::char::{char, spaces, string}};
 
-use crate::{context::Context, context_table::CtxtTable, equation::Equation, id::OperId, parser::{DIRECTIVE_SIGN, term::terminner::oper::terminner_parser}, symbol_table::SymbolTable};
+use crate::{context::Context, context_table::CtxtTable, equation::Equation, id::{GenId, OperId}, parser::{DIRECTIVE_SIGN, term::terminner::oper::terminner_parser}, predicate::{Comparison, Predicate}, symbol_table::SymbolTable};
+
+#[derive(Clone, Copy)]
+enum Relation {
+    Eq,
+    Neq,
+    Cmp(Comparison),
+}
 
 pub fn where_decl_parser<'a, Input>(
     opers: &'a SymbolTable<OperId>,
+    gens: &'a SymbolTable<GenId>,
     ctxts: &'a CtxtTable,
-) -> impl Parser<Input, Output = Equation> + 'a
+) -> impl Parser<Input, Output = Predicate> + 'a
 where
     Input: Stream<Token = char> + 'a,
 {
-    let left_parser = terminner_parser(ctxts, opers);
-    let right_parser = terminner_parser(ctxts, opers);
-
+    // Example:
+    // #where wrk!e = d
+    // #where wrk!e != d
+    // #where sal!e > 200
+    // #where not (mgr!e = e)
     string(DIRECTIVE_SIGN)
         .and(string("where"))
         .and(spaces())
-        .with(left_parser.skip(spaces()).skip(string("=").skip(spaces())))
+        .with(predicate_parser(opers, gens, ctxts))
+}
+
+/// `#where`の後や集約の`[... | ...]`に書く条件
+pub fn predicate_parser<'a, Input>(
+    opers: &'a SymbolTable<OperId>,
+    gens: &'a SymbolTable<GenId>,
+    ctxts: &'a CtxtTable,
+) -> impl Parser<Input, Output = Predicate> + 'a
+where
+    Input: Stream<Token = char> + 'a,
+{
+    let left_parser = terminner_parser(ctxts, opers, Some(gens));
+    let right_parser = terminner_parser(ctxts, opers, Some(gens));
+
+    // 2文字の演算子を先に試す
+    let relation_parser = choice((
+        attempt(string("!=")).map(|_| Relation::Neq),
+        attempt(string("<=")).map(|_| Relation::Cmp(Comparison::Le)),
+        attempt(string(">=")).map(|_| Relation::Cmp(Comparison::Ge)),
+        string("<").map(|_| Relation::Cmp(Comparison::Lt)),
+        string(">").map(|_| Relation::Cmp(Comparison::Gt)),
+        string("=").map(|_| Relation::Eq),
+    ));
+
+    // `not`はtypesideの演算子名にもなりうるので、`not (`まで読めたときだけ否定とする
+    let not_parser = attempt(string("not").skip(spaces()).skip(char('(')).skip(spaces()));
+
+    let predicate = left_parser
+        .skip(spaces())
+        .and(relation_parser.skip(spaces()))
         .and(right_parser)
-        .map(|(left, right)| -> Equation {
-            let context = Context(HashMap::new());
-            let mut names = ctxts.current_var_table();
-            let oper_names = opers.current_table();
-            names.extend(oper_names);
-            Equation {
+        .map(|((left, relation), right)| {
+            let context = Context(BTreeMap::new());
+            let mut global = opers.current_table();
+            global.extend(gens.current_table());
+            let names = ctxts.scoped_var_table(global);
+            let eq = Equation {
                 context: context.into(),
                 names: names.into(),
                 left: Rc::new(left),
                 right: Rc::new(right),
+            };
+            match relation {
+                Relation::Eq => Predicate::Eq(eq),
+                Relation::Neq => Predicate::Neq(eq),
+                Relation::Cmp(cmp) => Predicate::Cmp(cmp, eq),
             }
+        });
+
+    many(not_parser)
+        .and(predicate)
+        .then(|(nots, predicate): (Vec<_>, Predicate)| {
+            let n = nots.len();
+            count_min_max::<Vec<_>, _, _>(n, n, spaces().with(char(')'))).map(move |_| {
+                (0..n).fold(predicate.clone(), |p, _| Predicate::Not(Box::new(p)))
+            })
         })
 }
 
+#[cfg(test)]
+mod tests {
+    use combine::{eof, EasyParser, Parser};
+    use rstest::rstest;
+
+    use crate::{context_table::CtxtTable, id::{GenId, OperId}, predicate::Predicate, symbol_table::SymbolTable};
+
+    use super::where_decl_parser;
+
+    fn parse(input: &str) -> Result<Predicate, String> {
+        let ctxts = CtxtTable::new();
+        ctxts.assign_to_current("e".to_string());
+        ctxts.assign_to_current("d".to_string());
+        let opers = SymbolTable::<OperId>::new();
+        opers.insert("wrk".to_string(), OperId(0));
+        opers.insert("mgr".to_string(), OperId(1));
+        opers.insert("sal".to_string(), OperId(2));
+        let gens = SymbolTable::<GenId>::new();
+        let result = where_decl_parser::<combine::easy::Stream<&str>>(&opers, &gens, &ctxts)
+            .skip(eof())
+            .easy_parse(input)
+            .map(|(predicate, _)| predicate)
+            .map_err(|e| e.to_string());
+        result
+    }
+
+    #[rstest]
+    #[case("#where wrk!e = d", "wrk!e = d")]
+    #[case("#where wrk!e != d", "wrk!e != d")]
+    #[case("#where sal!e >= 200", "sal!e >= 200")]
+    #[case("#where sal!e<200", "sal!e < 200")]
+    #[case("#where not (mgr!e = e)", "not (mgr!e = e)")]
+    #[case("#where not ( not (sal!e > 200) )", "not (not (sal!e > 200))")]
+    fn test_where_decl_parser(#[case] input: &str, #[case] expected: &str) {
+        assert_eq!(parse(input).unwrap().to_string(), expected);
+    }
+
+    #[test]
+    fn test_where_decl_parser_unbalanced() {
+        assert!(parse("#where not (mgr!e = e").is_err());
+    }
+}
@@ -22,8 +22,8 @@ where
     Input: Stream<Token = char> + 'a,
 {
     let context_parser = context_parser::<Input>(types, ctxts);
-    let left_parser = terminner_parser(ctxts, opers);
-    let right_parser = terminner_parser(ctxts, opers);
+    let left_parser = terminner_parser(ctxts, opers, None);
+    let right_parser = terminner_parser(ctxts, opers, None);
 
     context_parser
         .skip(spaces())
@@ -32,9 +32,9 @@ where
         .and(left_parser.skip(spaces()).skip(string("->").skip(spaces())))
         .and(right_parser)
         .map(|((context, before), after)| {
-            let mut names = ctxts.current_var_table();
-            let oper_names = opers.current_table();
-            names.extend(oper_names);
+            let mut global = types.current_table();
+            global.extend(opers.current_table());
+            let names = ctxts.scoped_var_table(global);
             Rule::new(
                 context.into(),
                 names.into(),
@@ -7,9 +7,7 @@ use combine::{attempt, sep_end_by};
 
 use crate::schema::Schema;
 
-use crate::context_table::CtxtTable;
-use crate::id::{OperId, TypeId};
-use crate::symbol_table::SymbolTable;
+use crate::workspace::Workspace;
 
 use crate::equation::Equation;
 use crate::oper::Oper;
@@ -23,9 +21,7 @@ use crate::parser::theory_decl::theory_decl_parser;
 use crate::parser::type_decl::type_decl_parser;
 
 pub fn schema_parser<'a, Input>(
-    types: &'a SymbolTable<TypeId>,
-    opers: &'a SymbolTable<OperId>,
-    ctxts: &'a CtxtTable,
+    ws: &'a Workspace,
 ) -> impl Parser<Input, Output = Schema> + 'a
 where
     Input: Stream<Token = char> + 'a,
@@ -39,7 +35,8 @@ where
         Equation(Equation),
     }
 
-    let theory_decl_parser = theory_decl_parser::<Input>(types, opers, ctxts);
+    let (types, opers, ctxts) = (&ws.types, &ws.opers, &ws.ctxts);
+    let theory_decl_parser = theory_decl_parser::<Input>(ws);
     let entity_parser = type_decl_parser::<Input>(types);
     let fkey_parser = fkey_decl_parser::<Input>(opers, types);
     let attr_parser = attr_decl_parser::<Input>(opers, types);
@@ -73,21 +70,17 @@ where
 
 #[cfg(test)]
 mod tests {
-    use crate::id::{OperId, TypeId};
-    use crate::context_table::CtxtTable;
     use crate::parser::schema::schema_parser;
     use crate::combine::EasyParser;
-    use crate::symbol_table::SymbolTable;
+    use crate::workspace::Workspace;
     
     #[test]
     fn test_schema_parser() {
-        let f = "schema/s.schema";
+        let f = "example/schema/s.schema";
         let schema_example = std::fs::read_to_string(f).expect("Failed to read");
 
-        let types = SymbolTable::<TypeId>::new();
-        let opers = SymbolTable::<OperId>::new();
-        let ctxts = CtxtTable::new();
-        let result = schema_parser::<combine::easy::Stream<&str>>(&types, &opers, &ctxts)
+        let ws = Workspace::new("example");
+        let result = schema_parser::<combine::easy::Stream<&str>>(&ws)
             .easy_parse(schema_example.as_ref());
 
         println!("{}", result.unwrap().0);
@@ -1,23 +1,17 @@
-use std::fs::read_to_string;
-
 use combine::{
     many1,
-    parser::char::{alpha_num, spaces, string},
-    EasyParser, Parser, Stream,
+    parser::char::{alpha_num, char, spaces, string},
+    Parser, Stream,
 };
 
 use crate::{
-    context_table::CtxtTable,
-    id::{OperId, TypeId},
-    parser::{schema::schema_parser, DIRECTIVE_SIGN},
+    parser::{parse_error, schema::schema_parser, DIRECTIVE_SIGN},
     schema::Schema,
-    symbol_table::SymbolTable,
+    workspace::Workspace,
 };
 
 pub fn schema_decl_parser<'a, Input>(
-    types: &'a SymbolTable<TypeId>,
-    opers: &'a SymbolTable<OperId>,
-    ctxts: &'a CtxtTable,
+    ws: &'a Workspace,
 ) -> impl Parser<Input, Output = Schema> + 'a
 where
     Input: Stream<Token = char> + 'a,
@@ -26,28 +20,37 @@ where
         .and(string("schema"))
         .and(spaces())
         .with(many1::<Vec<_>, Input, _>(alpha_num()))
-        .map(|chars: Vec<_>| {
+        .and_then(|chars: Vec<_>| {
             let name = chars.into_iter().collect::<String>();
-            let path = format!("example/schema/{}.schema", name);
-            let src =
-                read_to_string(&path).expect(&format!("Failed to read theory file: {}", path));
-            let schema = schema_parser::<combine::easy::Stream<&str>>(types, opers, ctxts)
-                .easy_parse(src.as_ref())
-                .expect(&format!("Failed to parse theory from file: {}", path))
-                .0;
-            schema
+            ws.schema(&name).map_err(parse_error::<Input>)
         })
 }
 
+/// `t`でschemaファイルを参照するか、`{ ... }`にschemaの宣言をそのまま書く
+pub fn schema_ref_parser<'a, Input>(
+    ws: &'a Workspace,
+) -> impl Parser<Input, Output = Schema> + 'a
+where
+    Input: Stream<Token = char> + 'a,
+{
+    let inline = char('{')
+        .skip(spaces())
+        .with(schema_parser(ws))
+        .skip(spaces())
+        .skip(char('}'));
+    let reference = many1::<String, _, _>(alpha_num())
+        .and_then(move |name| ws.schema(&name).map_err(parse_error::<Input>));
+
+    inline.or(reference)
+}
+
 #[test]
 fn test_parse_schema_decl() {
     use combine::EasyParser;
 
-    let types = SymbolTable::<TypeId>::new();
-    let opers = SymbolTable::<OperId>::new();
-    let ctxts = CtxtTable::new();
+    let ws = Workspace::new("example");
 
-    let result = schema_decl_parser(&types, &opers, &ctxts).easy_parse("#schema s");
+    let result = schema_decl_parser(&ws).easy_parse("#schema s");
     dbg!(&result);
     assert!(result.is_ok());
 }
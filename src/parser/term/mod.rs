@@ -24,7 +24,7 @@ where
     Input: Stream<Token = char> + 'a,
 {
     let context_parser = context_parser::<Input>(types, ctxts);
-    let inner_parser = terminner_parser(ctxts, opers);
+    let inner_parser = terminner_parser(ctxts, opers, None);
 
     context_parser
         .skip(spaces())
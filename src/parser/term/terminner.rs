@@ -2,9 +2,9 @@ use combine::{attempt, Parser, Stream};
 
 use crate::{
     context_table::CtxtTable,
-    id::OperId,
+    id::{GenId, OperId},
     parser::term::terminner::{
-        integer::integer_parser, oper::terminner_oper_parser,
+        boolean::boolean_parser, generator::terminner_gen_parser, number::number_parser, oper::terminner_oper_parser,
         oper_unary::terminner_oper_unary_parser, r#const::terminner_const_parser,
         string::string_parser, var::terminner_var_parser,
     },
@@ -13,26 +13,32 @@ use crate::{
 };
 
 mod r#const;
+pub mod generator;
 pub mod oper;
 pub mod oper_unary;
 // mod oper_post;
-mod integer;
-mod string;
+pub mod boolean;
+pub mod number;
+pub mod string;
 mod var;
 
 pub fn terminner_parser_<'a, Input>(
     ctxts: &'a CtxtTable,
     opers: &'a SymbolTable<OperId>,
+    gens: Option<&'a SymbolTable<GenId>>,
 ) -> impl Parser<Input, Output = TermInner> + 'a
 where
     Input: Stream<Token = char> + 'a,
 {
+    // 裸の名前は変数を優先し、なければ生成元とみなす
     attempt(string_parser())
-        .or(attempt(integer_parser()))
-        .or(attempt(terminner_oper_parser(ctxts, opers)))
-        .or(attempt(terminner_oper_unary_parser(ctxts, opers)))
+        .or(attempt(number_parser()))
+        .or(attempt(boolean_parser()))
+        .or(attempt(terminner_oper_parser(ctxts, opers, gens)))
+        .or(attempt(terminner_oper_unary_parser(ctxts, opers, gens)))
         .or(attempt(terminner_const_parser(opers)))
-        .or(terminner_var_parser(ctxts))
+        .or(attempt(terminner_var_parser(ctxts)))
+        .or(terminner_gen_parser(gens))
 }
 
 #[cfg(test)]
@@ -56,7 +62,7 @@ mod tests {
         ctxts.assign_to_current("a".to_string());
 
         let input = "f![a]";
-        let result = terminner_oper_parser(&ctxts, &opers).easy_parse(input);
+        let result = terminner_oper_parser(&ctxts, &opers, None).easy_parse(input);
         dbg!(&result);
         assert!(result.is_ok());
     }
@@ -71,7 +77,7 @@ mod tests {
         ctxts.assign_to_current("a".to_string());
 
         let input = "f![f![a]]";
-        let result = terminner_oper_parser(&ctxts, &opers).easy_parse(input);
+        let result = terminner_oper_parser(&ctxts, &opers, None).easy_parse(input);
         dbg!(&result);
         assert!(result.is_ok());
     }
@@ -87,7 +93,7 @@ mod tests {
         ctxts.assign_to_current("e".to_string());
         let opers = SymbolTable::<OperId>::new();
         opers.assign("f".to_string());
-        let result = terminner_oper_unary_parser(&ctxts, &opers).easy_parse(input);
+        let result = terminner_oper_unary_parser(&ctxts, &opers, None).easy_parse(input);
         dbg!(&result);
         assert!(result.is_ok());
     }
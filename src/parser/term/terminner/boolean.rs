@@ -0,0 +1,29 @@
+use combine::parser::char::{alpha_num, string};
+use combine::{attempt, not_followed_by, one_of, Parser, Stream};
+
+use crate::literal::Literal;
+use crate::term::TermInner;
+
+/// `true`/`false`
+/// `true;`や`trueish`は演算子・名前として読むので、ここでは受け付けない。
+pub fn boolean_parser<Input>() -> impl Parser<Input, Output = TermInner>
+where
+    Input: Stream<Token = char>,
+{
+    attempt(string("true").map(|_| true))
+        .or(string("false").map(|_| false))
+        .skip(not_followed_by(alpha_num().or(one_of(";!".chars()))))
+        .map(|b| TermInner::Lit(Literal::Bool(b)))
+}
+
+#[test]
+fn test_parse_boolean() {
+    use combine::EasyParser;
+
+    let result = boolean_parser().easy_parse("true");
+    assert_eq!(result, Ok((TermInner::Lit(Literal::Bool(true)), "")));
+    let result = boolean_parser().easy_parse("false]");
+    assert_eq!(result, Ok((TermInner::Lit(Literal::Bool(false)), "]")));
+    assert!(boolean_parser().easy_parse("true;").is_err());
+    assert!(boolean_parser().easy_parse("trueish").is_err());
+}
@@ -4,7 +4,7 @@ use combine::{
     Parser, Stream,
 };
 
-use crate::{id::OperId, symbol_table::SymbolTable, term::TermInner};
+use crate::{id::OperId, parser::parse_error, symbol_table::SymbolTable, term::TermInner};
 
 pub fn terminner_const_parser<'a, Input>(
     opers: &'a SymbolTable<OperId>,
@@ -12,12 +12,12 @@ pub fn terminner_const_parser<'a, Input>(
 where
     Input: Stream<Token = char> + 'a,
 {
-    many1(alpha_num()).skip(string(";")).map(|c: Vec<_>| {
+    many1(alpha_num()).skip(string(";")).and_then(|c: Vec<_>| {
         let name: String = c.into_iter().collect();
-        let oper_id = opers
+        opers
             .get(name.as_ref())
-            .expect(format!("Oper '{}' not found in symbol table", name).as_ref());
-        TermInner::Fun(oper_id, vec![])
+            .map(|oper_id| TermInner::Fun(oper_id, vec![]))
+            .ok_or_else(|| parse_error::<Input>(format!("Oper '{}' not found in symbol table", name)))
     })
 }
 
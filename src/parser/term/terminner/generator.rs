@@ -0,0 +1,79 @@
+use combine::parser::char::{alpha_num, char};
+use combine::{many1, optional};
+use combine::stream::Stream;
+use combine::Parser;
+
+use crate::id::GenId;
+use crate::parser::parse_error;
+use crate::symbol_table::SymbolTable;
+use crate::term::TermInner;
+
+/// instanceの生成元(`e1`や`d3`)と、値の分からないことを表すラベル付きnull(`?x`)
+/// 生成元は、引数のない演算子として書いていたころの`e1;`の形でも書ける。
+/// nullは宣言せずに書け、はじめて現れたときに新しい生成元になる。
+/// 宣言されていない生成元もここでは読み、`Instance::validate`が未宣言として報告する。
+/// theory/schemaの項では`gens`がNoneなので、どちらも書けない。
+pub fn terminner_gen_parser<'a, Input>(
+    gens: Option<&'a SymbolTable<GenId>>,
+) -> impl Parser<Input, Output = TermInner> + 'a
+where
+    Input: Stream<Token = char> + 'a,
+{
+    optional(char('?'))
+        .and(many1(alpha_num()))
+        .skip(optional(char(';')))
+        .and_then(move |(null, c): (Option<char>, Vec<_>)| {
+            let name: String = c.into_iter().collect();
+            match (gens, null) {
+                (Some(gens), Some(_)) => Ok(TermInner::Gen(gens.assign(format!("?{}", name)))),
+                (None, Some(_)) => Err(parse_error::<Input>(format!(
+                    "Labelled null '?{}' can only appear in an instance",
+                    name
+                ))),
+                (Some(gens), None) => Ok(TermInner::Gen(gens.assign(name))),
+                (None, None) => Err(parse_error::<Input>(format!(
+                    "Generator '{}' can only appear in an instance",
+                    name
+                ))),
+            }
+        })
+}
+
+#[test]
+fn test_terminner_gen_parser() {
+    use crate::combine::EasyParser;
+
+    let gens = SymbolTable::<GenId>::new();
+    gens.insert("e1".to_string(), GenId(3));
+    let r = terminner_gen_parser(Some(&gens)).easy_parse("e1");
+    assert_eq!(r, Ok((TermInner::Gen(GenId(3)), "")));
+    // 宣言されていない生成元も読める
+    let (e2, _) = terminner_gen_parser(Some(&gens)).easy_parse("e2").unwrap();
+    assert_ne!(e2, TermInner::Gen(GenId(3)));
+    assert_eq!(Some(e2), gens.get("e2").map(TermInner::Gen));
+    assert!(terminner_gen_parser(None).easy_parse("e1").is_err());
+}
+
+#[test]
+fn test_terminner_gen_parser_semicolon() {
+    use crate::combine::EasyParser;
+
+    let gens = SymbolTable::<GenId>::new();
+    gens.insert("e1".to_string(), GenId(3));
+    assert_eq!(terminner_gen_parser(Some(&gens)).easy_parse("e1;"), Ok((TermInner::Gen(GenId(3)), "")));
+}
+
+#[test]
+fn test_terminner_gen_parser_null() {
+    use crate::combine::EasyParser;
+
+    let gens = SymbolTable::<GenId>::init_with(GenId(10));
+    let (x, _) = terminner_gen_parser(Some(&gens)).easy_parse("?x").unwrap();
+    let (y, _) = terminner_gen_parser(Some(&gens)).easy_parse("?y").unwrap();
+    // 同じ名前のnullは同じ生成元、異なる名前なら別の生成元
+    assert_eq!(terminner_gen_parser(Some(&gens)).easy_parse("?x"), Ok((x.clone(), "")));
+    assert_ne!(x, y);
+    assert_eq!(gens.get("?x"), Some(GenId(10)));
+    assert!(gens.get("x").is_none());
+    assert!(terminner_gen_parser(None).easy_parse("?x").is_err());
+}
@@ -0,0 +1,42 @@
+use combine::parser::char::{char, digit};
+use combine::{many1, optional, Parser, Stream};
+
+use crate::literal::{Decimal, Literal};
+use crate::parser::parse_error;
+use crate::term::TermInner;
+
+/// `-12`は`Int`、`12.50`は`Dec`
+pub fn number_parser<Input>() -> impl Parser<Input, Output = TermInner>
+where
+    Input: Stream<Token = char>,
+{
+    optional(char('-'))
+        .and(many1(digit()))
+        .and(optional(char('.').with(many1(digit()))))
+        .and_then(|((sign, int), frac): ((Option<char>, String), Option<String>)| {
+            let sign = if sign.is_some() { "-" } else { "" };
+            let lit = match frac {
+                None => format!("{}{}", sign, int)
+                    .parse::<i64>()
+                    .map(Literal::Int)
+                    .map_err(|e| format!("invalid integer {}{}: {}", sign, int, e)),
+                Some(frac) => format!("{}{}.{}", sign, int, frac)
+                    .parse::<Decimal>()
+                    .map(Literal::Dec),
+            };
+            lit.map(TermInner::Lit).map_err(parse_error::<Input>)
+        })
+}
+
+#[test]
+fn test_parse_number() {
+    use combine::EasyParser;
+
+    let result = number_parser().easy_parse("12345");
+    assert_eq!(result, Ok((TermInner::Lit(Literal::Int(12345)), "")));
+    let result = number_parser().easy_parse("-42");
+    assert_eq!(result, Ok((TermInner::Lit(Literal::Int(-42)), "")));
+    let result = number_parser().easy_parse("12.50");
+    assert_eq!(result, Ok((TermInner::Lit(Literal::Dec(Decimal::new(125, 1))), "")));
+    assert!(number_parser().easy_parse("99999999999999999999").is_err());
+}
@@ -5,7 +5,8 @@ use combine::{between, parser};
 use combine::{many1, sep_by};
 
 use crate::context_table::CtxtTable;
-use crate::id::OperId;
+use crate::id::{GenId, OperId};
+use crate::parser::parse_error;
 use crate::parser::term::terminner::terminner_parser_;
 use crate::symbol_table::SymbolTable;
 use crate::term::TermInner;
@@ -13,6 +14,7 @@ use crate::term::TermInner;
 pub fn terminner_oper_parser<'a, Input>(
     ctxts: &'a CtxtTable,
     opers: &'a SymbolTable<OperId>,
+    gens: Option<&'a SymbolTable<GenId>>,
 ) -> impl Parser<Input, Output = TermInner> + 'a
 where
     Input: Stream<Token = char> + 'a,
@@ -22,15 +24,15 @@ where
         .and(between(
             string("["),
             string("]"),
-            sep_by(terminner_parser(ctxts, opers), spaces()),
+            sep_by(terminner_parser(ctxts, opers, gens), spaces()),
         ))
-        .map(|(c, v): (Vec<_>, Vec<_>)| {
+        .and_then(|(c, v): (Vec<_>, Vec<_>)| {
             let name: String = c.into_iter().collect();
-            let oper_id = opers
-                .get(name.as_ref())
-                .expect(format!("Oper '{}' not found in symbol table", name).as_ref());
             let args = v.into_iter().map(|t| t.into()).collect();
-            TermInner::Fun(oper_id, args)
+            opers
+                .get(name.as_ref())
+                .map(|oper_id| TermInner::Fun(oper_id, args))
+                .ok_or_else(|| parse_error::<Input>(format!("Oper '{}' not found in symbol table", name)))
         })
 }
 
@@ -38,11 +40,12 @@ parser! {
     pub fn terminner_parser['a, Input](
         // vars: std::rc::Rc<SymbolTable<VarId>>,
         ctxts: &'a CtxtTable,
-        opers: &'a SymbolTable<OperId>
+        opers: &'a SymbolTable<OperId>,
+        gens: Option<&'a SymbolTable<GenId>>
     )(Input) -> TermInner
     where [Input: Stream<Token = char>]
     {
-        terminner_parser_(ctxts, opers)
+        terminner_parser_(ctxts, opers, *gens)
     }
 }
 
@@ -56,7 +59,7 @@ fn test_terminner_oper_parser1() {
     ctxts.assign_to_current("a".to_string());
 
     let input = "f![a]";
-    let result = terminner_oper_parser(&ctxts, &opers).easy_parse(input);
+    let result = terminner_oper_parser(&ctxts, &opers, None).easy_parse(input);
     dbg!(&result);
     assert!(result.is_ok());
 }
@@ -71,7 +74,7 @@ fn test_terminner_oper_parser2() {
     // let vars = std::rc::Rc::new(SymbolTable::<VarId>::new());
     // vars.insert("a".to_string(), VarId(0));
 
-    let r = terminner_oper_parser(&ctxts, &opers).easy_parse("f![f![]]");
+    let r = terminner_oper_parser(&ctxts, &opers, None).easy_parse("f![f![]]");
     dbg!(&opers);
     assert_eq!(
         r,
@@ -5,26 +5,27 @@ use combine::{
 };
 
 use crate::{
-    context_table::CtxtTable, id::OperId, parser::term::terminner::oper::terminner_parser,
+    context_table::CtxtTable, id::{GenId, OperId}, parser::{parse_error, term::terminner::oper::terminner_parser},
     symbol_table::SymbolTable, term::TermInner,
 };
 
 pub fn terminner_oper_unary_parser<'a, Input>(
     ctxts: &'a CtxtTable,
     opers: &'a SymbolTable<OperId>,
+    gens: Option<&'a SymbolTable<GenId>>,
 ) -> impl Parser<Input, Output = TermInner> + 'a
 where
     Input: Stream<Token = char> + 'a,
 {
     many1(alpha_num())
         .skip(string("!"))
-        .and(terminner_parser(ctxts, opers))
-        .map(|(c, v): (Vec<_>, _)| {
+        .and(terminner_parser(ctxts, opers, gens))
+        .and_then(|(c, v): (Vec<_>, _)| {
             let name: String = c.into_iter().collect();
-            let oper_id = opers
+            opers
                 .get(name.as_ref())
-                .expect(format!("Oper '{}' not found in symbol table", name).as_ref());
-            TermInner::Fun(oper_id, vec![v.into()])
+                .map(|oper_id| TermInner::Fun(oper_id, vec![v.into()]))
+                .ok_or_else(|| parse_error::<Input>(format!("Oper '{}' not found in symbol table", name)))
         })
 }
 
@@ -38,7 +39,7 @@ fn test_terminner_oper_unary_parser() {
     ctxts.assign_to_current("a".to_string());
 
     let input = "f!a";
-    let result = terminner_oper_unary_parser(&ctxts, &opers).easy_parse(input);
+    let result = terminner_oper_unary_parser(&ctxts, &opers, None).easy_parse(input);
     dbg!(&result);
     assert!(result.is_ok());
 }
@@ -1,5 +1,6 @@
 use combine::{between, many, none_of, token, Parser, Stream};
 
+use crate::literal::Literal;
 use crate::term::TermInner;
 
 pub fn string_parser<Input>() -> impl Parser<Input, Output = TermInner>
@@ -8,7 +9,7 @@ where
 {
     between(token('"'), token('"'), many(none_of("\"".chars()))).map(|s: Vec<_>| {
         let s: String = s.iter().collect();
-        TermInner::Str(s.into())
+        TermInner::Lit(Literal::Str(s))
     })
 }
 
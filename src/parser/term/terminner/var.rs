@@ -4,6 +4,7 @@ use combine::stream::Stream;
 use combine::Parser;
 
 use crate::context_table::CtxtTable;
+use crate::parser::parse_error;
 use crate::term::TermInner;
 
 pub fn terminner_var_parser<'a, Input>(
@@ -12,11 +13,15 @@ pub fn terminner_var_parser<'a, Input>(
 where
     Input: Stream<Token = char> + 'a,
 {
-    many1(alpha_num()).map(move |c: Vec<_>| {
+    many1(alpha_num()).and_then(move |c: Vec<_>| {
         let name: String = c.into_iter().collect();
         // dbg!(ctxts);
-        let varid = ctxts.var_id_from_current(name.as_ref());
-        TermInner::Var(varid)
+        ctxts
+            .find_var_from_current(name.as_ref())
+            .map(TermInner::Var)
+            .ok_or_else(|| {
+                parse_error::<Input>(format!("Variable '{}' not found in current context", name))
+            })
     })
 }
 
@@ -5,6 +5,7 @@ use combine::stream::Stream;
 use combine::Parser;
 use combine::{attempt, sep_end_by};
 
+use crate::builtin::Builtin;
 use crate::id::{OperId, TypeId};
 
 use crate::equation::Equation;
@@ -29,7 +30,7 @@ where
     #[derive(Clone)]
     enum Decl {
         Type(Type),
-        Oper(Oper),
+        Oper((Oper, Option<Builtin>)),
         Equation(Equation),
     }
 
@@ -50,7 +51,12 @@ where
         for decl in decls {
             match decl {
                 Decl::Type(ty) => theory.types.push(ty),
-                Decl::Oper(op) => theory.opers.push(op),
+                Decl::Oper((op, builtin)) => {
+                    if let Some(builtin) = builtin {
+                        theory.builtins.ops.insert(op.id.clone(), builtin);
+                    }
+                    theory.opers.push(op)
+                }
                 Decl::Equation(eq) => theory.eqs.push(eq),
             }
         }
@@ -98,7 +104,7 @@ mod tests {
         let opers = SymbolTable::<OperId>::new();
         let ctxts = CtxtTable::new();
 
-        let f = "theory/test.theory";
+        let f = "example/theory/test.theory";
         let theory_example = std::fs::read_to_string(f).expect("Failed to read");
         let mut parser = theory_parser::<Stream<&str>>(&types, &opers, &ctxts);
         let result = parser.easy_parse(theory_example.as_ref());
@@ -0,0 +1,113 @@
+use std::rc::Rc;
+
+use combine::{
+    attempt, many1, sep_end_by,
+    parser::char::{alpha_num, char, spaces, string},
+    Parser, Stream,
+};
+
+use crate::{
+    id::GenId,
+    instance::{collect_gens, replace_gens, Instance},
+    parser::{parse_error, term::terminner::{generator::terminner_gen_parser, oper::terminner_parser}, DIRECTIVE_SIGN},
+    term::TermInner,
+    transform::Transform,
+    workspace::Workspace,
+};
+
+/// transformファイル全体
+/// ```text
+/// #transform update: i -> i2
+/// #map e1 -> e1
+/// #map ?x -> 180
+/// ```
+pub fn transform_parser<'a, Input>(
+    ws: &'a Workspace,
+) -> impl Parser<Input, Output = Transform> + 'a
+where
+    Input: Stream<Token = char> + 'a,
+{
+    let directive = |name: &'static str| attempt(string(DIRECTIVE_SIGN).and(string(name))).skip(spaces());
+    let name = || many1::<String, _, _>(alpha_num().or(char('_')));
+    let arrow = || spaces().skip(string("->")).skip(spaces());
+
+    let header_parser = directive("transform")
+        .with(name())
+        .skip(spaces())
+        .skip(char(':'))
+        .skip(spaces())
+        .and(name().skip(arrow()).and(name()))
+        .skip(spaces())
+        // `#map`の生成元を読めるように、先にinstanceを読み込む
+        .and_then(move |(name, (source_name, target_name)): (String, (String, String))| {
+            let source = ws.instance(&source_name).map_err(parse_error::<Input>)?;
+            let target = ws.instance(&target_name).map_err(parse_error::<Input>)?;
+            Ok::<_, combine::stream::StreamErrorFor<Input>>((name, (source_name, source), (target_name, target)))
+        });
+    let map_parser = directive("map")
+        .with(terminner_gen_parser(Some(&ws.gens)).skip(arrow()))
+        .and(terminner_parser(&ws.ctxts, &ws.opers, Some(&ws.gens)));
+
+    header_parser
+        .and(sep_end_by(map_parser, spaces()))
+        .and_then(move |((name, (source_name, source), (target_name, target)), maps): (_, Vec<_>)| {
+            // 生成元はinstanceごとに採番されるので、名前で元と行き先のinstanceの生成元に読み替える
+            let table = ws.gens.current_table();
+            let local = |(instance_name, instance): (&str, &Instance), gid: &GenId| {
+                let gen_name = table.gen_name(gid).unwrap_or("?");
+                instance.names.gen_id(gen_name).ok_or_else(|| {
+                    parse_error::<Input>(format!("Generator '{}' is not declared in instance {}", gen_name, instance_name))
+                })
+            };
+            let mut gens = std::collections::BTreeMap::new();
+            for (gen, image) in maps {
+                let TermInner::Gen(gid) = gen else {
+                    unreachable!()
+                };
+                let mut image_gens = vec![];
+                collect_gens(&image, &mut image_gens);
+                let target_gens = image_gens
+                    .iter()
+                    .map(|g| Ok((g.clone(), Rc::new(TermInner::Gen(local((&target_name, &target), g)?)))))
+                    .collect::<Result<_, _>>()?;
+                if gens.insert(local((&source_name, &source), &gid)?, replace_gens(&Rc::new(image), &target_gens)).is_some() {
+                    return Err(parse_error::<Input>(format!("#transform {}: a generator is mapped more than once", name)));
+                }
+            }
+            let mut names = source.names.as_ref().clone();
+            names.extend(target.names.as_ref().clone());
+            Ok(Transform {
+                name,
+                source_name,
+                target_name,
+                source,
+                target,
+                gens,
+                names: Rc::new(names),
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use combine::{eof, EasyParser, Parser};
+
+    use crate::{parser::transform::transform_parser, workspace::Workspace};
+
+    #[test]
+    fn test_transform_parser() {
+        let ws = Workspace::new("example");
+        let input = "#transform h: nulls -> i2\n#map e1 -> mgr!e2\n#map ?x -> 180\n";
+        let (transform, _) = transform_parser(&ws).skip(eof()).easy_parse(input).unwrap();
+        assert_eq!((transform.source_name.as_str(), transform.target_name.as_str()), ("nulls", "i2"));
+        assert_eq!(transform.gens.len(), 2);
+        assert!(transform.to_string().contains("#map e1 -> mgr!e2\n"));
+
+        assert!(transform_parser(&ws).skip(eof()).easy_parse("#transform h: i -> nothing\n").is_err());
+        assert!(transform_parser(&ws).skip(eof()).easy_parse("#transform h: i -> i2\n#map e1 -> e1\n#map e1 -> e2\n").is_err());
+        // hrのx18は、iにもi2にもない
+        ws.instance("hr").unwrap();
+        assert!(transform_parser(&ws).skip(eof()).easy_parse("#transform h: i -> i2\n#map x18 -> e1\n").is_err());
+        assert!(transform_parser(&ws).skip(eof()).easy_parse("#transform h: i -> i2\n#map e1 -> x18\n").is_err());
+    }
+}
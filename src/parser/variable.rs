@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 use combine::parser::char::{alpha_num, spaces, string};
 use combine::stream::Stream;
@@ -14,7 +14,7 @@ use crate::symbol_table::SymbolTable;
 pub fn parse_variable<'a, Input>(
     types: &'a SymbolTable<TypeId>,
     ctxts: &'a CtxtTable,
-) -> impl Parser<Input, Output = HashMap<VarId, Type>> + 'a
+) -> impl Parser<Input, Output = BTreeMap<VarId, Type>> + 'a
 where
     Input: Stream<Token = char> + 'a,
 {
@@ -24,7 +24,7 @@ where
         .skip(spaces())
         .and(type_unary_parser(types))
         .map(move |(v, t): (Vec<Vec<_>>, _)| {
-            let mut vartypes = HashMap::new();
+            let mut vartypes = BTreeMap::new();
             v.into_iter().for_each(|vname| {
                 let vname: String = vname.into_iter().collect();
                 let vname = vname.trim().to_string();
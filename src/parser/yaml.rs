@@ -0,0 +1,84 @@
+use combine::{
+    attempt, eof, many, many1, none_of, optional, position, satisfy, skip_many, skip_many1,
+    parser::char::{alpha_num, char, newline, string},
+    stream::position::SourcePosition,
+    Parser, Stream,
+};
+
+use crate::{parser::parse_error, table::{Row, Tables}};
+
+/// entityごとに、`id`とfkey/attrの列を持つ行を並べたYAML
+/// ```text
+/// Emp:
+///   - id: e1
+///     wrk: d3
+///     last: Gauss
+/// Dept: []
+/// ```
+/// 値は行末まで(`#`以降はコメント)。`"..."`と書けば、クォートごと値になる。
+pub fn yaml_parser<Input>() -> impl Parser<Input, Output = Tables>
+where
+    Input: Stream<Token = char, Position = SourcePosition>,
+{
+    let inline = || skip_many(satisfy(|c| c == ' ' || c == '\t'));
+    let comment = || char('#').with(skip_many(none_of("\n".chars())));
+    // 行末と、続く空行・コメント行
+    let eol = || {
+        attempt(skip_many1(attempt(inline().with(optional(comment())).with(newline()).map(|_| ()))))
+            .or(inline().with(optional(comment())).with(eof()))
+    };
+    let key = || many1::<String, _, _>(alpha_num().or(char('_')));
+    let value = || {
+        let quoted = attempt(char('"').with(many::<String, _, _>(none_of("\"\n".chars()))).skip(char('"')))
+            .map(|s| format!("\"{}\"", s));
+        let plain = many::<String, _, _>(none_of("#\n".chars())).map(|s| s.trim().to_string());
+        quoted.or(plain)
+    };
+    let cell = || key().skip(char(':')).skip(inline()).and(value()).skip(eol());
+
+    let first = attempt(inline().with(char('-')).with(inline())).with(cell());
+    let rest = attempt(skip_many1(char(' ')).with(cell()));
+    let row = position().and(first).and(many(rest)).and_then(|((pos, first), rest): ((SourcePosition, _), Vec<_>)| {
+        let cells = std::iter::once(first).chain(rest).filter(|(_, value): &(String, String)| !value.is_empty());
+        let (ids, cells): (Vec<_>, Vec<_>) = cells.partition(|(column, _)| column == "id");
+        match ids.as_slice() {
+            [(_, id)] => Ok(Row { id: id.clone(), cells, line: pos.line as usize }),
+            [] => Err(parse_error::<Input>("A row has no id".to_string())),
+            _ => Err(parse_error::<Input>("A row has more than one id".to_string())),
+        }
+    });
+    let empty = attempt(string("[]")).skip(eol()).map(|_| vec![]);
+    let table = key()
+        .skip(char(':'))
+        .skip(inline())
+        .and(empty.or(eol().with(many(row))));
+
+    optional(eol()).with(many(table)).skip(eof())
+}
+
+#[cfg(test)]
+mod tests {
+    use combine::{stream::position::Stream, EasyParser};
+
+    use crate::parser::yaml::yaml_parser;
+
+    #[test]
+    fn test_yaml_parser() {
+        let input = "# comment\nEmp:\n  - id: e1 \n    last: \"Gauss\"\n\n    sal: 250 # yen\n  - id: e2\n    last:\nDept: []\n";
+        let (tables, _) = yaml_parser().easy_parse(Stream::new(input)).unwrap();
+        assert_eq!(tables.len(), 2);
+        let (entity, rows) = &tables[0];
+        assert_eq!(entity, "Emp");
+        assert_eq!((rows[0].id.as_str(), rows[0].line), ("e1", 3));
+        assert_eq!(rows[1].line, 7);
+        assert_eq!(
+            rows[0].cells,
+            vec![("last".to_string(), "\"Gauss\"".to_string()), ("sal".to_string(), "250".to_string())]
+        );
+        // 空の値は書かれていないのと同じ
+        assert!(rows[1].cells.is_empty());
+        assert_eq!(tables[1], ("Dept".to_string(), vec![]));
+
+        assert!(yaml_parser().easy_parse(Stream::new("Emp:\n  - last: Gauss\n")).is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use crate::{
+    completion::{rule::Rule, subst::{Subst, Var}},
+    equation::Equation,
+    id::GenId,
+    instance::{replace_gens, Instance},
+    literal::Literal,
+    term::TermInner,
+};
+
+/// `#where`の条件
+#[derive(Clone, Debug)]
+pub enum Predicate {
+    /// `l = r`
+    Eq(Equation),
+    /// `l != r`
+    Neq(Equation),
+    /// `l < r`など。両辺をEquationの左右に持つ
+    Cmp(Comparison, Equation),
+    /// `not (...)`
+    Not(Box<Predicate>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// 条件の真偽。値の分からないnullや値のないfkey/attrが残るとUnknownになる
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Truth {
+    True,
+    False,
+    Unknown,
+}
+
+impl std::ops::Not for Truth {
+    type Output = Truth;
+
+    fn not(self) -> Truth {
+        match self {
+            Truth::True => Truth::False,
+            Truth::False => Truth::True,
+            Truth::Unknown => Truth::Unknown,
+        }
+    }
+}
+
+impl Comparison {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        }
+    }
+
+    fn holds(&self, ord: std::cmp::Ordering) -> bool {
+        match self {
+            Comparison::Lt => ord.is_lt(),
+            Comparison::Le => ord.is_le(),
+            Comparison::Gt => ord.is_gt(),
+            Comparison::Ge => ord.is_ge(),
+        }
+    }
+}
+
+impl std::fmt::Display for Predicate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Predicate::Eq(eq) => write!(f, "{} = {}", eq.left_term(), eq.right_term()),
+            Predicate::Neq(eq) => write!(f, "{} != {}", eq.left_term(), eq.right_term()),
+            Predicate::Cmp(cmp, eq) => write!(f, "{} {} {}", eq.left_term(), cmp.symbol(), eq.right_term()),
+            Predicate::Not(p) => write!(f, "not ({})", p),
+        }
+    }
+}
+
+impl Predicate {
+    /// 両辺の項
+    pub fn equation(&self) -> &Equation {
+        match self {
+            Predicate::Eq(eq) | Predicate::Neq(eq) | Predicate::Cmp(_, eq) => eq,
+            Predicate::Not(p) => p.equation(),
+        }
+    }
+
+    pub fn vars(&self) -> Vec<Var> {
+        let eq = self.equation();
+        [eq.left.vars(), eq.right.vars()].concat()
+    }
+
+    pub fn substitute(&self, subst: &Subst) -> Predicate {
+        let sub = |eq: &Equation| Equation {
+            context: eq.context.clone(),
+            names: eq.names.clone(),
+            left: eq.left.substitute(subst),
+            right: eq.right.substitute(subst),
+        };
+        match self {
+            Predicate::Eq(eq) => Predicate::Eq(sub(eq)),
+            Predicate::Neq(eq) => Predicate::Neq(sub(eq)),
+            Predicate::Cmp(cmp, eq) => Predicate::Cmp(*cmp, sub(eq)),
+            Predicate::Not(p) => Predicate::Not(Box::new(p.substitute(subst))),
+        }
+    }
+
+    /// 両辺の生成元を`gens`に従って置き換える
+    pub fn replace_gens(&self, gens: &BTreeMap<GenId, Rc<TermInner>>) -> Predicate {
+        let replace = |eq: &Equation| Equation {
+            context: eq.context.clone(),
+            names: eq.names.clone(),
+            left: replace_gens(&eq.left, gens),
+            right: replace_gens(&eq.right, gens),
+        };
+        match self {
+            Predicate::Eq(eq) => Predicate::Eq(replace(eq)),
+            Predicate::Neq(eq) => Predicate::Neq(replace(eq)),
+            Predicate::Cmp(cmp, eq) => Predicate::Cmp(*cmp, replace(eq)),
+            Predicate::Not(p) => Predicate::Not(Box::new(p.replace_gens(gens))),
+        }
+    }
+}
+
+impl Instance {
+    /// 生成元だけを含む条件を判定する
+    /// `rules`は`deduction_rules`で作ったものとし、両辺の正規形が異なるリテラルか生成元のときだけ等しくないとする。
+    /// 正規形にnullか値のないfkey/attrが残る場合や、評価しきれない演算子が残る場合は、値次第で変わるのでUnknownとする。
+    /// 大小比較は同じ種類のリテラル(IntとDecは混ぜてよい)どうしでだけ成り立つ。
+    pub fn decide(&self, pred: &Predicate, rules: &Vec<Rule>) -> Truth {
+        let normalize = |t: &Rc<TermInner>| {
+            let term = crate::term::Term {
+                context: pred.equation().context.clone(),
+                names: pred.equation().names.clone(),
+                inner: t.clone(),
+            };
+            term.normalize_with(rules, &self.schema.theory.builtins).inner.clone()
+        };
+        match pred {
+            Predicate::Eq(eq) => {
+                let (l, r) = (normalize(&eq.left), normalize(&eq.right));
+                if l == r {
+                    Truth::True
+                } else if is_value(&l) && is_value(&r) && !self.is_undetermined(&l) && !self.is_undetermined(&r) {
+                    Truth::False
+                } else {
+                    Truth::Unknown
+                }
+            }
+            Predicate::Neq(eq) => !self.decide(&Predicate::Eq(eq.clone()), rules),
+            Predicate::Cmp(cmp, eq) => {
+                let (l, r) = (normalize(&eq.left), normalize(&eq.right));
+                let ord = match (l.as_ref(), r.as_ref()) {
+                    (TermInner::Lit(Literal::Str(x)), TermInner::Lit(Literal::Str(y))) => Some(x.cmp(y)),
+                    (TermInner::Lit(x), TermInner::Lit(y)) => x.as_decimal().zip(y.as_decimal()).map(|(x, y)| x.cmp(&y)),
+                    _ => None,
+                };
+                match ord {
+                    Some(ord) if cmp.holds(ord) => Truth::True,
+                    Some(_) => Truth::False,
+                    None if is_value(&l) && is_value(&r) && !self.is_undetermined(&l) && !self.is_undetermined(&r) => {
+                        Truth::False
+                    }
+                    None => Truth::Unknown,
+                }
+            }
+            Predicate::Not(p) => !self.decide(p, rules),
+        }
+    }
+}
+
+/// これ以上書き換わらない値(リテラルか生成元)
+fn is_value(t: &TermInner) -> bool {
+    matches!(t, TermInner::Lit(_) | TermInner::Gen(_))
+}
@@ -0,0 +1,327 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use crate::{
+    completion::{try_complete, subst::{Subst, Var}, COMPLETION_LIMIT},
+    context::Context,
+    equation::Equation,
+    eval::{Query, QueryEntity},
+    id::{GenId, Symbol, TypeId, VarId},
+    predicate::Predicate,
+    r#type::Type,
+    schema::Schema,
+    term::{Term, TermInner},
+};
+
+/// queryで保たれると確かめられなかった出力のschemaの等式
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PreservationError {
+    /// 写すと成り立たない。`path`は両辺で食い違う箇所(左辺のパスと、fkeyならその行き先の変数)、
+    /// `left`/`right`は元のschemaで写した両辺の正規形
+    Unpreserved { equation: String, path: String, left: String, right: String },
+    /// 元のschemaへ写せない、または完備化が終わらないので調べられない
+    CannotCheck { equation: String, reason: String },
+}
+
+impl std::fmt::Display for PreservationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PreservationError::Unpreserved { equation, path, left, right } => {
+                writeln!(f, "not preserved: {}", equation)?;
+                writeln!(f, "  path:  {}", path)?;
+                writeln!(f, "  left:  {}", left)?;
+                write!(f, "  right: {}", right)
+            }
+            PreservationError::CannotCheck { equation, reason } => {
+                write!(f, "cannot check: {}: {}", equation, reason)
+            }
+        }
+    }
+}
+
+/// 出力のschemaの項を元のschemaへ写したもの
+/// entityの項は行き先のブロックの`#for`の変数への代入に、それ以外は項になる。
+#[derive(Clone, Debug)]
+pub(crate) enum Translated {
+    Row(TypeId, BTreeMap<VarId, Rc<TermInner>>),
+    Term(Rc<TermInner>),
+}
+
+impl Query {
+    /// 出力のschemaの`#rule`がqueryで保たれるか(uber-flowerの等式保存条件)を調べる
+    /// `#rule`の変数ごとにブロックの`#for`の変数を定数に置き換え、`#where`と元のschemaの等式を完備化して両辺を比べる。
+    /// 写せない項があるか完備化が終わらなければ、保たれたとはせず`CannotCheck`にする。
+    pub fn check_preservation(&self, source: &Schema) -> Vec<PreservationError> {
+        let Some(target) = &self.target else {
+            return vec![];
+        };
+        target
+            .constraints
+            .iter()
+            .filter_map(|eq| self.check_equation(source, eq))
+            .collect()
+    }
+
+    fn check_equation(&self, source: &Schema, eq: &Equation) -> Option<PreservationError> {
+        let equation = format!("{} = {}", eq.left_term(), eq.right_term());
+        let cannot_check = |reason: String| Some(PreservationError::CannotCheck { equation: equation.clone(), reason });
+        let mut names = self.names.as_ref().clone();
+        let mut next_gen = names.next_gen_id();
+        let single = eq.context.0.len() == 1;
+
+        // `#rule`の変数ごとに、ブロックの変数を新しい定数に置き換えた代入と`#where`
+        let mut env = BTreeMap::new();
+        let mut eqs = vec![];
+        for (vid, tp) in &eq.context.0 {
+            let Type::Unary(entity) = tp else {
+                return cannot_check(format!("{} is not an entity", eq.names.fmt_type(tp)));
+            };
+            let Some(block) = self.block(entity) else {
+                return cannot_check(format!("no #entity block for {}", eq.names.sort_name(entity).unwrap_or("?")));
+            };
+            let mut row = BTreeMap::new();
+            for (src_vid, _) in block.fr.iter().flat_map(|c| c.0.iter()) {
+                let gid = GenId(next_gen);
+                next_gen += 1;
+                let src_name = self.names.var_name(src_vid).unwrap_or("_");
+                let name = if single {
+                    src_name.to_string()
+                } else {
+                    format!("{}.{}", eq.names.var_name(vid).unwrap_or("_"), src_name)
+                };
+                names.insert(name, Symbol::Gen(gid.clone()));
+                row.insert(src_vid.clone(), Rc::new(TermInner::Gen(gid)));
+            }
+            let subst = to_subst(&row);
+            // 等式の条件だけを前提にする(否定や大小比較は等式を増やさない)
+            eqs.extend(block.wh.iter().filter_map(|wh| match wh {
+                Predicate::Eq(wh) => Some(wh),
+                _ => None,
+            }).map(|wh| Equation {
+                context: Rc::new(Context::default()),
+                names: eq.names.clone(),
+                left: wh.left.substitute(&subst),
+                right: wh.right.substitute(&subst),
+            }));
+            env.insert(vid.clone(), Translated::Row(entity.clone(), row));
+        }
+        eqs.extend(source.constraints.iter().cloned());
+        eqs.extend(source.theory.eqs.iter().cloned());
+        let rules = match try_complete(eqs, COMPLETION_LIMIT) {
+            Ok(rules) => rules,
+            Err(incomplete) => return cannot_check(incomplete.to_string()),
+        };
+
+        let names = Rc::new(names);
+        let normalize = |inner: &Rc<TermInner>| {
+            let term = Term {
+                context: Rc::new(Context::default()),
+                names: names.clone(),
+                inner: inner.clone(),
+            };
+            term.normalize_with(&rules, &source.theory.builtins)
+        };
+
+        let target = self.target.as_ref()?;
+        let (Some(left), Some(right)) = (self.translate(target, &eq.left, &env), self.translate(target, &eq.right, &env)) else {
+            return cannot_check("cannot translate into the source schema (missing #return, #keys or block)".to_string());
+        };
+        let path = eq.left_term().to_string();
+        let unpreserved = |path: String, l: &Rc<TermInner>, r: &Rc<TermInner>| {
+            let (l, r) = (normalize(l), normalize(r));
+            (l != r).then(|| PreservationError::Unpreserved {
+                equation: equation.clone(),
+                path,
+                left: l.to_string(),
+                right: r.to_string(),
+            })
+        };
+        match (left, right) {
+            (Translated::Term(l), Translated::Term(r)) => unpreserved(path, &l, &r),
+            (Translated::Row(_, l), Translated::Row(_, r)) => l.iter().find_map(|(vid, lt)| {
+                let var = self.names.var_name(vid).unwrap_or("_");
+                match r.get(vid) {
+                    Some(rt) => unpreserved(format!("{} ({})", path, var), lt, rt),
+                    None => cannot_check(format!("{} is not assigned on both sides", var)),
+                }
+            }),
+            _ => cannot_check("one side is an entity and the other is not".to_string()),
+        }
+    }
+
+    pub(crate) fn translate(
+        &self,
+        target: &Schema,
+        t: &TermInner,
+        env: &BTreeMap<VarId, Translated>,
+    ) -> Option<Translated> {
+        match t {
+            TermInner::Var(vid) => env.get(vid).cloned(),
+            TermInner::Fun(oid, args) => {
+                if let Some(fkey) = target.fkeys.iter().find(|op| &op.id == oid) {
+                    let [arg] = args.as_slice() else {
+                        return None;
+                    };
+                    let Translated::Row(entity, row) = self.translate(target, arg, env)? else {
+                        return None;
+                    };
+                    let Type::Unary(cod) = fkey.cod.as_ref() else {
+                        return None;
+                    };
+                    // fkeyの変換は行き先の変数を元の変数の項で表すので、元の代入と合成する
+                    let (_, _, transform) = self.block(&entity)?.keys.iter().find(|(id, _, _)| id == oid)?;
+                    return Some(Translated::Row(cod.clone(), compose_row(&row, transform)));
+                }
+                if target.attrs.iter().any(|op| &op.id == oid) {
+                    let [arg] = args.as_slice() else {
+                        return None;
+                    };
+                    let Translated::Row(entity, row) = self.translate(target, arg, env)? else {
+                        return None;
+                    };
+                    let (_, term) = self.block(&entity)?.ret.iter().find(|(id, _)| id == oid)?;
+                    return Some(Translated::Term(term.substitute(&to_subst(&row))));
+                }
+                // typesideの演算子はそのまま
+                let args = args
+                    .iter()
+                    .map(|arg| match self.translate(target, arg, env)? {
+                        Translated::Term(t) => Some(t),
+                        Translated::Row(..) => None,
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                Some(Translated::Term(Rc::new(TermInner::Fun(oid.clone(), args))))
+            }
+            _ => Some(Translated::Term(Rc::new(t.clone()))),
+        }
+    }
+
+    pub(crate) fn block(&self, entity: &TypeId) -> Option<&QueryEntity> {
+        self.entities.iter().find(|qe| qe.entity.as_ref() == Some(entity))
+    }
+}
+
+pub(crate) fn to_subst(row: &BTreeMap<VarId, Rc<TermInner>>) -> Subst {
+    Subst::new(
+        row.iter()
+            .map(|(vid, t)| (Var::Id(vid.clone()), t.clone()))
+            .collect(),
+    )
+}
+
+/// `transform`(行き先の変数 -> 元の変数の項)のあとに`row`を代入した、行き先の変数への代入
+/// `Subst::compose`は値が変数そのものになる組を除くので、`transform`の変数で引き直す。
+pub(crate) fn compose_row(
+    row: &BTreeMap<VarId, Rc<TermInner>>,
+    transform: &[(VarId, TermInner)],
+) -> BTreeMap<VarId, Rc<TermInner>> {
+    let subst = to_subst(row);
+    let composed = subst.compose(&Subst::new(
+        transform
+            .iter()
+            .map(|(vid, term)| (Var::Id(vid.clone()), Rc::new(term.clone())))
+            .collect(),
+    ));
+    transform
+        .iter()
+        .map(|(vid, term)| {
+            let t = composed.0.get(&Var::Id(vid.clone())).cloned();
+            (vid.clone(), t.unwrap_or_else(|| term.substitute(&subst)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, rc::Rc};
+
+    use combine::{eof, EasyParser, Parser};
+
+    use crate::{
+        eval::Query,
+        id::{GenId, VarId},
+        parser::query::query_parser,
+        preservation::{compose_row, PreservationError},
+        term::TermInner,
+        workspace::Workspace,
+    };
+
+    fn parse(ws: &Workspace, input: &str) -> Query {
+        query_parser::<combine::easy::Stream<&str>>(ws)
+            .skip(eof())
+            .easy_parse(input)
+            .unwrap()
+            .0
+    }
+
+    const TARGET: &str = "#target {\n#theory \"test\"\n#sort Worker\n#sort Division\n\
+        #fkey boss: Worker -> Worker\n#fkey dept: Worker -> Division\n#attr ename: Worker -> Str\n\
+        #rule w: Worker | dept![boss![w]] = dept![w]\n#rule w: Worker | ename![boss![w]] = ename![w]\n}\n";
+
+    #[test]
+    fn test_check_preservation() {
+        let ws = Workspace::new("example");
+        let source = ws.schema("s").unwrap();
+        let input = format!(
+            "#instance i\n{}#entity Worker {{\n#for e: Emp\n#return ename := last!e\n\
+             #keys boss := [e -> mgr!e]\n#keys dept := [d -> wrk!e]\n}}\n\
+             #entity Division {{\n#for d: Dept\n}}\n",
+            TARGET
+        );
+        let unpreserved = parse(&ws, &input).check_preservation(&source);
+        // wrk!mgr!e = wrk!eは元のschemaで成り立つが、last!mgr!e = last!eは成り立たない
+        assert_eq!(
+            unpreserved,
+            [PreservationError::Unpreserved {
+                equation: "ename!boss!w = ename!w".to_string(),
+                path: "ename!boss!w".to_string(),
+                left: "last!mgr!e".to_string(),
+                right: "last!e".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_check_preservation_cannot_check() {
+        let ws = Workspace::new("example");
+        let source = ws.schema("s").unwrap();
+        // enameの#returnがないので、ename!boss!w = ename!wは写せない
+        let input = format!(
+            "#instance i\n{}#entity Worker {{\n#for e: Emp\n\
+             #keys boss := [e -> mgr!e]\n#keys dept := [d -> wrk!e]\n}}\n\
+             #entity Division {{\n#for d: Dept\n}}\n",
+            TARGET
+        );
+        let errors = parse(&ws, &input).check_preservation(&source);
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(&errors[0], PreservationError::CannotCheck { equation, .. } if equation == "ename!boss!w = ename!w"));
+    }
+
+    #[test]
+    fn test_compose_row() {
+        let (x, y) = (VarId(0), VarId(1));
+        let var = |vid: &VarId| TermInner::Var(vid.clone());
+        let gen = |id: usize| Rc::new(TermInner::Gen(GenId(id)));
+        let row = BTreeMap::from([(x.clone(), gen(10)), (y.clone(), gen(11))]);
+        // 入れ替えと、変数そのものへの写し(Subst::composeでは除かれる)
+        let swapped = compose_row(&row, &[(x.clone(), var(&y)), (y.clone(), var(&y))]);
+        assert_eq!(swapped, BTreeMap::from([(x.clone(), gen(11)), (y.clone(), gen(11))]));
+        // 元の代入にない変数はそのまま残る
+        let z = VarId(2);
+        assert_eq!(compose_row(&row, &[(x.clone(), var(&z))]), BTreeMap::from([(x, Rc::new(var(&z)))]));
+    }
+
+    #[test]
+    fn test_check_preservation_with_where() {
+        let ws = Workspace::new("example");
+        let source = ws.schema("s").unwrap();
+        // 自分自身が上司である従業員だけなら、名前も保たれる
+        let input = format!(
+            "#instance i\n{}#entity Worker {{\n#for e: Emp\n#where mgr!e = e\n#return ename := last!e\n\
+             #keys boss := [e -> mgr!e]\n#keys dept := [d -> wrk!e]\n}}\n\
+             #entity Division {{\n#for d: Dept\n}}\n",
+            TARGET
+        );
+        assert!(parse(&ws, &input).check_preservation(&source).is_empty());
+    }
+}
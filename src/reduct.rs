@@ -1,10 +1,11 @@
 use std::{
-    collections::HashMap,
+    collections::BTreeMap,
     rc::Rc,
 };
 
 use crate::{
-    completion::complete,
+    builtin::Builtins,
+    completion::congruence::ground_rules,
     completion::critical_pairs::prepare_rules,
     equation::Equation,
     instance::Instance,
@@ -19,26 +20,38 @@ use crate::{
 use crate::util::dispv;
 
 impl Instance {
+    /// `deducible`で使う書き換え規則
+    /// `#data`とschemaのconstraintを生成元ごとに代入した等式を合同閉包で閉じ、fkeyとattrの値を導く。
+    /// 生成元でない項(`mgr!mgr!wrk!e`の`wrk!e`など)にも使えるように、constraintとtypesideの等式を完備化した規則を加える。
+    /// 書いた向きのままだと`mgr!e = mgr!mgr!e`のような等式で書き換えが止まらないので、向きはLPOで決める。
+    /// 完備化が終わらないときは、そこまでに得られた規則で導ける範囲だけを導く。
+    pub fn deduction_rules(&self) -> Vec<Rule> {
+        self.deduction_rules_from(&self.schema.rules().0)
+    }
+
+    /// `Schema::rules`で完備化しておいた規則を使う`deduction_rules`
+    /// データだけを変えて何度も作るときに、schemaの完備化を繰り返さないためのもの。
+    pub fn deduction_rules_from(&self, schema_rules: &[Rule]) -> Vec<Rule> {
+        let mut rules = ground_rules(&self.ground_equations());
+        rules.extend_from_slice(schema_rules);
+        rules
+    }
+
+    /// `#data`と、schemaのconstraintを生成元ごとに代入した等式
+    pub(crate) fn ground_equations(&self) -> Vec<Equation> {
+        let instances = self.schema.constraints.iter().flat_map(|cons| self.instantiate(cons));
+        self.data.iter().cloned().chain(instances).collect()
+    }
+
+    /// 生成元だけを含む等式がinstanceのデータとtypesideの等式・組み込み演算から導けるか
+    /// 両辺はfkeyのパスでも、attrやtypesideの演算子・リテラルを含む項でもよい。
     pub fn deducible(&self, eq: &Equation) -> bool {
-        // TODO: 本来はSchemaのconstraintsも必要
-        let eq_tp = match eq.left.as_ref() {
-            TermInner::Fun(operid, _) => {
-                self.schema.fkeys.iter().find(|op| &op.id == operid).map(|oper| oper.cod.clone())
-            }
-            _ => unimplemented!()
-        };
-        let eqs = self.data.iter().filter(|e| {
-            let e_tp = match e.left.as_ref() {
-                TermInner::Fun(operid, _) => {
-                    self.schema.fkeys.iter().find(|op| &op.id == operid).map(|oper| oper.cod.clone())
-                }
-                _ => unimplemented!()
-            };
-            e_tp == eq_tp
-        }).cloned().collect::<Vec<_>>();
-        // dispv("filtered_eqs:", &eqs);
-        let rules = complete(eqs, 0);
-        eq.is_reducible(&rules)
+        eq.is_reducible(&self.deduction_rules(), &self.schema.theory.builtins)
+    }
+
+    /// `deduction_rules`を前もって作っておき、繰り返し判定するとき用
+    pub fn deducible_with(&self, eq: &Equation, rules: &Vec<Rule>) -> bool {
+        eq.is_reducible(rules, &self.schema.theory.builtins)
     }
 }
 
@@ -53,24 +66,36 @@ impl Equation {
         )
     }
 
-    fn is_reducible(&self, rules: &Vec<Rule>) -> bool {
+    fn is_reducible(&self, rules: &Vec<Rule>, builtins: &Builtins) -> bool {
         // dispv("is_reducible rules:", rules);
 
-        let left = self.left_term().normalize(rules);
-        let right = self.right_term().normalize(rules);
-        println!("is_reducible {} ?? {}", left, right);
+        let left = self.left_term().normalize_with(rules, builtins);
+        let right = self.right_term().normalize_with(rules, builtins);
         left == right
     }
 }
 
 impl Term {
     pub fn normalize<'a>(&self, rules: &Vec<Rule>) -> Rc<Term> {
+        self.normalize_with(rules, &Builtins::default())
+    }
+
+    /// 書き換え規則で書き換えられなくなったら、組み込み演算をリテラルの引数について評価する
+    pub fn normalize_with(&self, rules: &Vec<Rule>, builtins: &Builtins) -> Rc<Term> {
         let mut term = Rc::new(self.clone());
         loop {
-            let result = reduct(term.clone(), rules);
+            let mut result = reduct(term.clone(), rules);
             // println!("result: {} term: {}", result, term);
             if result == term {
-                break;
+                let evaluated = builtins.eval(&term.inner);
+                if evaluated == term.inner {
+                    break;
+                }
+                result = Rc::new(Term {
+                    context: term.context.clone(),
+                    names: term.names.clone(),
+                    inner: evaluated,
+                });
             }
             // println!("REDUCED {} -> {}", term, result);
             term = result
@@ -198,7 +223,7 @@ impl TermInner {
                         None
 
                         // (!Var::Id(vid.clone()).is_used_in(term.clone())).then(|| {
-                        //     let new_subst = HashMap::from([(Var::Id(vid.clone()), term.clone())]);
+                        //     let new_subst = BTreeMap::from([(Var::Id(vid.clone()), term.clone())]);
                         //     // subst.insert(Var::Id(vid.clone()), term.clone());
                         //     new_subst.into()
                         // })
@@ -214,7 +239,7 @@ impl TermInner {
                     _ => {
                         (!Var::Ruled(vid.clone(), *rid, kind.clone()).is_used_in(term.clone()))
                             .then(|| {
-                                let subst = HashMap::from([(
+                                let subst = BTreeMap::from([(
                                     Var::Ruled(vid.clone(), *rid, kind.clone()),
                                     term.clone(),
                                 )]);
@@ -276,7 +301,7 @@ impl Term {
                 match term.inner.as_ref() {
                     TermInner::Var(v) if vid == v => Some(Subst::default()),
                     _ => (!Var::Id(vid.clone()).is_used_in(term.inner.clone())).then(|| {
-                        let subst = HashMap::from([(Var::Id(vid.clone()), term.inner.clone())]);
+                        let subst = BTreeMap::from([(Var::Id(vid.clone()), term.inner.clone())]);
                         subst.into()
                     }),
                 }
@@ -290,7 +315,7 @@ impl Term {
                     _ => (!Var::Ruled(vid.clone(), *rid, kind.clone())
                         .is_used_in(term.inner.clone()))
                     .then(|| {
-                        let subst = HashMap::from([(
+                        let subst = BTreeMap::from([(
                             Var::Ruled(vid.clone(), *rid, kind.clone()),
                             term.inner.clone(),
                         )]);
@@ -557,3 +582,87 @@ mod tests {
     //     ])
     // }
 }
+
+#[cfg(test)]
+mod deduction_tests {
+    use std::rc::Rc;
+
+    use combine::{eof, stream::position::Stream, EasyParser, Parser};
+
+    use crate::{
+        equation::Equation,
+        instance::{replace_gens, Instance},
+        parser::instance::instance_parser,
+        term::TermInner,
+        workspace::Workspace,
+    };
+
+    /// `text`を読んだinstanceの最初の`#data`を、生成元を名前で`instance`のものに読み替えて返す
+    fn first_data(ws: &Workspace, instance: &Instance, text: &str) -> Equation {
+        let parsed = instance_parser(ws).skip(eof()).easy_parse(Stream::new(text)).unwrap().0;
+        let gens = parsed
+            .generators()
+            .map(|g| (g.id.clone(), Rc::new(TermInner::Gen(instance.names.gen_id(&g.name).unwrap()))))
+            .collect();
+        let eq = &parsed.data[0];
+        Equation {
+            context: eq.context.clone(),
+            names: instance.names.clone(),
+            left: replace_gens(&eq.left, &gens),
+            right: replace_gens(&eq.right, &gens),
+        }
+    }
+
+    #[test]
+    fn test_deduction_rules_for_many_rows() {
+        let ws = Workspace::new("example");
+        let n = 60;
+        let elems = format!("#schema s\n#elem d1: Dept\n#elem {}: Emp\n", (0..n).map(|k| format!("e{k}")).collect::<Vec<_>>().join(" "));
+        let mut input = elems.clone() + "#data name!d1 = \"HR\"\n#data sec!d1 = e0\n";
+        for k in 0..n {
+            input += &format!("#data last!e{k} = \"L{k}\"\n#data wrk!e{k} = d1\n#data mgr!e{k} = e0\n#data sal!e{k} = {}\n", 100 + k);
+        }
+        let instance = instance_parser(&ws).skip(eof()).easy_parse(Stream::new(input.as_str())).unwrap().0;
+        assert_eq!(instance.data.len(), 4 * n + 2);
+
+        let rules = instance.deduction_rules();
+        let eq = |left: &str, right: &str| first_data(&ws, &instance, &format!("{}#data {} = {}\n", elems, left, right));
+        assert!(instance.deducible_with(&eq("sal!mgr!e42", "100"), &rules));
+        assert!(instance.deducible_with(&eq("wrk!sec!wrk!e59", "d1"), &rules));
+        assert!(!instance.deducible_with(&eq("sal!e42", "100"), &rules));
+        assert_eq!(instance.saturate().data.len(), instance.data.len());
+    }
+
+    #[test]
+    fn test_deduction_rules_use_constraints() {
+        let ws = Workspace::new("example");
+        let i = ws.instance("i").unwrap();
+        let eq = |left: &str, right: &str| {
+            let text = format!("#schema s\n#elem e1 e2 e3 e4 e5 e6 e7: Emp\n#elem d1 d2 d3: Dept\n#data {} = {}\n", left, right);
+            first_data(&ws, &i, &text)
+        };
+        // mgr!e6 = e7とwrk!e6 = d2に、constraintのmgr!mgr!e = mgr!eとwrk!mgr!e = wrk!eを合わせる
+        assert!(i.deducible(&eq("wrk!e7", "d2")));
+        assert!(i.deducible(&eq("mgr!e7", "e7")));
+        assert!(i.deducible(&eq("name!wrk!mgr!mgr!e6", "\"Admin\"")));
+        assert!(!i.deducible(&eq("wrk!e7", "d1")));
+    }
+
+    #[test]
+    fn test_deduction_rules_orient_reversed_constraints() {
+        let ws = Workspace::new("example");
+        let mut i = ws.instance("i").unwrap();
+        // mgr!mgr!e = mgr!eをmgr!e = mgr!mgr!eと書いても、書き換えが止まる
+        let cons = &mut i.schema.constraints[0];
+        std::mem::swap(&mut cons.left, &mut cons.right);
+        assert_eq!(cons.left_term().to_string(), "mgr!e");
+
+        let eq = |left: &str, right: &str| {
+            let text = format!("#schema s\n#elem e1 e2 e3 e4 e5 e6 e7: Emp\n#elem d1 d2 d3: Dept\n#data {} = {}\n", left, right);
+            first_data(&ws, &i, &text)
+        };
+        assert!(i.deducible(&eq("mgr!mgr!mgr!e7", "e7")));
+        assert!(i.deducible(&eq("wrk!mgr!e7", "d2")));
+        assert!(!i.deducible(&eq("mgr!e3", "e4")));
+    }
+}
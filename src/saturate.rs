@@ -1,7 +1,92 @@
-use crate::instance::Instance;
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    rc::Rc,
+};
+
+use crate::{
+    completion::rule::Rule,
+    context::Context,
+    id::TypeId,
+    instance::{Elem, Instance},
+    r#type::Type,
+    term::{Term, TermInner},
+};
+
+/// entityごとの台集合。元は生成元かfkeyの項の正規形で表す
+pub type Carrier = BTreeMap<TypeId, BTreeSet<Rc<TermInner>>>;
+
+/// 台集合の元がこれより増えたら、fkeyをたどっても閉じないものとして打ち切る
+pub const MAX_CARRIER: usize = 10000;
 
 impl Instance {
+    /// データから等しいと分かる同じentityの生成元を同一視し、自明になった等式を除く
+    /// 同一視した生成元のうち、先に宣言された方を残す。
     pub fn saturate(&self) -> Instance {
-        self.clone()
+        let rules = self.deduction_rules();
+        let builtins = &self.schema.theory.builtins;
+        let mut representatives: Vec<(Rc<TermInner>, Elem)> = vec![];
+        let mut instance = self.clone();
+        for elem in &self.elems {
+            let Elem::Gen(g) = elem else {
+                continue;
+            };
+            let term = Term {
+                context: Rc::new(Context::default()),
+                names: self.names.clone(),
+                inner: Rc::new(TermInner::Gen(g.id.clone())),
+            };
+            let normal = term.normalize_with(&rules, builtins).inner.clone();
+            let keep = representatives.iter().find_map(|(t, e)| match e {
+                Elem::Gen(kept) if t == &normal && kept.entity == g.entity => Some(kept.id.clone()),
+                _ => None,
+            });
+            match keep {
+                Some(keep) => instance = instance.merge(&keep, &g.id),
+                None => representatives.push((normal, elem.clone())),
+            }
+        }
+        instance.data.retain(|eq| eq.left != eq.right);
+        instance
+    }
+
+    /// 生成元と、そこからfkeyをたどった項(`wrk!p`など)の正規形をentityごとに集める
+    /// `rules`は`deduction_rules`とし、saturateしたinstanceに使う。新しい正規形が出なくなるまでfkeyをたどる。
+    /// 元が`MAX_CARRIER`を超えたら、閉じなかったentityを返す。
+    pub fn carrier(&self, rules: &Vec<Rule>) -> Result<Carrier, TypeId> {
+        let builtins = &self.schema.theory.builtins;
+        let normalize = |inner: Rc<TermInner>| {
+            let term = Term { context: Rc::new(Context::default()), names: self.names.clone(), inner };
+            term.normalize_with(rules, builtins).inner.clone()
+        };
+        let mut carrier = Carrier::new();
+        for entity in &self.schema.entities {
+            if let Type::Unary(tid) = entity {
+                carrier.insert(tid.clone(), BTreeSet::new());
+            }
+        }
+        let mut queue = self
+            .generators()
+            .map(|g| (g.entity.clone(), normalize(Rc::new(TermInner::Gen(g.id.clone())))))
+            .collect::<VecDeque<_>>();
+        let mut size = 0;
+        while let Some((entity, t)) = queue.pop_front() {
+            if !carrier.entry(entity.clone()).or_default().insert(t.clone()) {
+                continue;
+            }
+            size += 1;
+            if size > MAX_CARRIER {
+                return Err(entity);
+            }
+            for fkey in &self.schema.fkeys {
+                match (fkey.dom.as_ref(), fkey.cod.as_ref()) {
+                    (Type::Unary(dom), Type::Unary(cod)) if dom == &entity => {
+                        let image = normalize(Rc::new(TermInner::Fun(fkey.id.clone(), vec![t.clone()])));
+                        queue.push_back((cod.clone(), image));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(carrier)
     }
 }
@@ -1,7 +1,7 @@
 use std::rc::Rc;
 
+use crate::completion::{complete_partially, rule::Rule, Incomplete, COMPLETION_LIMIT};
 use crate::equation::Equation;
-use crate::id::Symbol;
 use crate::oper::Oper;
 use crate::symbol_table::Names;
 use crate::r#type::Type;
@@ -26,123 +26,27 @@ impl std::fmt::Display for Schema {
 
         for ent in &self.entities {
             if let Type::Unary(typeid) = ent {
-                let tn = self.names.iter()
-                    .find(|(_, sym)| sym == &&Symbol::Type(typeid.clone()));
-                if let Some((nm, _)) = tn {
+                if let Some(nm) = self.names.sort_name(typeid) {
                     writeln!(f, "#sort {}", nm)?;
                 } else {
                     writeln!(f, "{:?}", ent)?;
-                }                
+                }
             }
         }
 
-        writeln!(f, "")?;
+        writeln!(f)?;
 
         for fk in &self.fkeys {
-            let fkname = self.names.iter()
-                .find(|(_, sym)| sym == &&Symbol::Fun(fk.id.clone()));
-            if let Some((nm, _)) = fkname {
-                let domname = match fk.dom.as_ref() {
-                    Type::Unary(tid) => {
-                        let tn = self.names.iter()
-                            .find(|(_, sym)| sym == &&Symbol::Type(tid.clone()));
-                        if let Some((nm, _)) = tn {
-                            nm.to_string()
-                        } else {
-                            format!("{:?}", fk.dom)
-                        }
-                    }
-                    Type::Binary(_, t1, t2) => {
-                        let mut names = Vec::new();
-                        for t in [t1, t2] {
-                            match t.as_ref() {
-                                Type::Unary(tid) => {
-                                    let tn = self.names.iter()
-                                        .find(|(_, sym)| sym == &&Symbol::Type(tid.clone()));
-                                    if let Some((nm, _)) = tn {
-                                        names.push(nm.to_string());
-                                    } else {                        
-                                        names.push(format!("{:?}", t));
-                                    }                              
-                                }    
-                                _ => unimplemented!(), // 現在はないパターンなので
-                            }                            
-                        }
-                        format!("({})", names.join(" * "))
-                    }
-                };
-                let codname = match fk.cod.as_ref() {
-                    Type::Unary(tid) => {
-                        let tn = self.names.iter()
-                            .find(|(_, sym)| sym == &&Symbol::Type(tid.clone()));
-                        if let Some((nm, _)) = tn {
-                            nm.to_string()
-                        } else {
-                            format!("{:?}", fk.cod)
-                        }
-                    }
-                    _ => unimplemented!(), // 現在はないパターンなので
-                };
-                writeln!(f, "#fkey {}: {} -> {}", nm, domname, codname)?;
-            } else {
-                writeln!(f, "{:?}", fk)?;
-            }
+            self.fmt_oper(f, "#fkey", fk)?;
         }
 
-        writeln!(f, "")?;
+        writeln!(f)?;
 
         for attr in &self.attrs {
-            let attrname = self.names.iter()
-                .find(|(_, sym)| sym == &&Symbol::Fun(attr.id.clone()));
-            if let Some((nm, _)) = attrname {
-                let domname = match attr.dom.as_ref() {
-                    Type::Unary(tid) => {
-                        let tn = self.names.iter()
-                            .find(|(_, sym)| sym == &&Symbol::Type(tid.clone()));
-                        if let Some((nm, _)) = tn {
-                            nm.to_string()
-                        } else {
-                            format!("{:?}", attr.dom)
-                        }
-                    }
-                    Type::Binary(_, t1, t2) => {
-                        let mut names = Vec::new();
-                        for t in [t1, t2] {
-                            match t.as_ref() {
-                                Type::Unary(tid) => {
-                                    let tn = self.names.iter()
-                                        .find(|(_, sym)| sym == &&Symbol::Type(tid.clone()));
-                                    if let Some((nm, _)) = tn {
-                                        names.push(nm.to_string());
-                                    } else {                        
-                                        names.push(format!("{:?}", t));
-                                    }                              
-                                }    
-                                _ => unimplemented!(), // 現在はないパターンなので
-                            }                            
-                        }
-                        format!("({})", names.join(" * "))
-                    }
-                };
-                let codname = match attr.cod.as_ref() {
-                    Type::Unary(tid) => {
-                        let tn = self.names.iter()
-                            .find(|(_, sym)| sym == &&Symbol::Type(tid.clone()));
-                        if let Some((nm, _)) = tn {
-                            nm.to_string()
-                        } else {
-                            format!("{:?}", attr.cod)
-                        }
-                    }
-                    _ => unimplemented!(), // 現在はないパターンなので
-                };
-                writeln!(f, "#attr {}: {} -> {}", nm, domname, codname)?;
-            } else {
-                writeln!(f, "{:?}", attr)?;
-            }   
+            self.fmt_oper(f, "#attr", attr)?;
         }
 
-        writeln!(f, "")?;
+        writeln!(f)?;
 
         for cons in &self.constraints {
             writeln!(f, "#rule {}", cons)?;
@@ -151,3 +55,24 @@ impl std::fmt::Display for Schema {
         Ok(())
     }
 }
+
+impl Schema {
+    /// constraintとtypesideの等式をLPOで向き付けて完備化した規則と、完備化が終わらなかったときはその理由
+    pub fn rules(&self) -> (Vec<Rule>, Option<Incomplete>) {
+        let eqs = [self.constraints.clone(), self.theory.eqs.clone()].concat();
+        complete_partially(eqs, COMPLETION_LIMIT)
+    }
+
+    fn fmt_oper(&self, f: &mut std::fmt::Formatter<'_>, directive: &str, op: &Oper) -> std::fmt::Result {
+        if let Some(nm) = self.names.oper_name(&op.id) {
+            let domname = self.names.fmt_type(&op.dom);
+            let codname = match op.cod.as_ref() {
+                Type::Unary(_) => self.names.fmt_type(&op.cod),
+                _ => unimplemented!(), // 現在はないパターンなので
+            };
+            writeln!(f, "{} {}: {} -> {}", directive, nm, domname, codname)
+        } else {
+            writeln!(f, "{:?}", op)
+        }
+    }
+}
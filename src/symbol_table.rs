@@ -1,17 +1,244 @@
 use std::cell::RefCell;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::rc::Rc;
 
 use autoincrement::AsyncIncrement;
 use autoincrement::AsyncIncremental;
 
+use crate::id::GenId;
 use crate::id::OperId;
 use crate::id::Symbol;
 use crate::id::TypeId;
+use crate::id::VarId;
+use crate::r#type::Type;
 
-pub type Names = HashMap<String, Symbol>;
+/// 名前と記号(型・演算子・変数・生成元)の双方向の対応表
+/// 型・演算子・変数・生成元はそれぞれ別の名前空間を持つ。
+/// 変数は文脈ごとに採番されるので、`scope`で親の表に文脈ローカルな変数を重ねて使う。
+/// 重ねた表では、変数は引くときも一覧にするときもその表の分だけを見る。
+#[derive(Clone, Default, PartialEq)]
+pub struct Names {
+    parent: Option<Rc<Names>>,
+    types: HashMap<String, TypeId>,
+    opers: HashMap<String, OperId>,
+    vars: HashMap<String, VarId>,
+    gens: HashMap<String, GenId>,
+    symbols: HashMap<Symbol, String>,
+}
+
+impl Names {
+    pub fn new() -> Self {
+        Names::default()
+    }
+
+    /// `parent`の型・演算子・生成元の名前を引き継ぎ、変数はこの表のものだけを使う
+    pub fn scope(parent: Rc<Names>) -> Self {
+        Names {
+            parent: Some(parent),
+            ..Names::default()
+        }
+    }
+
+    pub fn insert(&mut self, name: String, symbol: Symbol) {
+        // 同じ名前が別の記号を指していた場合は、古い記号からの逆引きを消す
+        let replaced = match &symbol {
+            Symbol::Type(id) => self.types.insert(name.clone(), id.clone()).map(Symbol::Type),
+            Symbol::Fun(id) => self.opers.insert(name.clone(), id.clone()).map(Symbol::Fun),
+            Symbol::Var(id) => self.vars.insert(name.clone(), id.clone()).map(Symbol::Var),
+            Symbol::Gen(id) => self.gens.insert(name.clone(), id.clone()).map(Symbol::Gen),
+        };
+        if let Some(old) = replaced.filter(|old| old != &symbol) {
+            if self.symbols.get(&old) == Some(&name) {
+                self.symbols.remove(&old);
+            }
+        }
+        self.symbols.insert(symbol, name);
+    }
+
+    pub fn extend(&mut self, other: Names) {
+        other.entries().into_iter().for_each(|(name, symbol)| {
+            self.insert(name, symbol);
+        });
+    }
+
+    pub fn type_id(&self, name: &str) -> Option<TypeId> {
+        self.types
+            .get(name)
+            .cloned()
+            .or_else(|| self.parent.as_ref().and_then(|p| p.type_id(name)))
+    }
+
+    pub fn oper_id(&self, name: &str) -> Option<OperId> {
+        self.opers
+            .get(name)
+            .cloned()
+            .or_else(|| self.parent.as_ref().and_then(|p| p.oper_id(name)))
+    }
+
+    pub fn var_id(&self, name: &str) -> Option<VarId> {
+        self.vars.get(name).cloned()
+    }
+
+    pub fn gen_id(&self, name: &str) -> Option<GenId> {
+        self.gens
+            .get(name)
+            .cloned()
+            .or_else(|| self.parent.as_ref().and_then(|p| p.gen_id(name)))
+    }
+
+    pub fn name_of(&self, symbol: &Symbol) -> Option<&str> {
+        let inherited = || match symbol {
+            Symbol::Var(_) => None,
+            _ => self.parent.as_ref().and_then(|p| p.name_of(symbol)),
+        };
+        self.symbols.get(symbol).map(String::as_str).or_else(inherited)
+    }
+
+    pub fn sort_name(&self, id: &TypeId) -> Option<&str> {
+        self.name_of(&Symbol::Type(id.clone()))
+    }
+
+    pub fn oper_name(&self, id: &OperId) -> Option<&str> {
+        self.name_of(&Symbol::Fun(id.clone()))
+    }
+
+    pub fn var_name(&self, id: &VarId) -> Option<&str> {
+        self.name_of(&Symbol::Var(id.clone()))
+    }
+
+    pub fn gen_name(&self, id: &GenId) -> Option<&str> {
+        self.name_of(&Symbol::Gen(id.clone()))
+    }
+
+    /// 型を表示用の文字列にする(`Emp`や`(Int * Int)`)
+    pub fn fmt_type(&self, ty: &Type) -> String {
+        match ty {
+            Type::Unary(tid) => self
+                .sort_name(tid)
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("{:?}", ty)),
+            Type::Binary(_, t1, t2) => {
+                let names = [t1, t2]
+                    .iter()
+                    .map(|t| match t.as_ref() {
+                        Type::Unary(_) => self.fmt_type(t),
+                        _ => unimplemented!(), // 現在はないパターンなので
+                    })
+                    .collect::<Vec<_>>();
+                format!("({})", names.join(" * "))
+            }
+        }
+    }
+
+    /// 親の表も含めた(名前, 記号)の一覧。記号の順に並べる
+    pub fn entries(&self) -> Vec<(String, Symbol)> {
+        let mut entries = BTreeMap::new();
+        if let Some(parent) = &self.parent {
+            parent.entries().into_iter().for_each(|(name, symbol)| {
+                entries.insert(symbol, name);
+            });
+        }
+        if self.parent.is_some() {
+            // 変数は文脈ローカルなので親の変数は引き継がない
+            entries.retain(|symbol, _| !matches!(symbol, Symbol::Var(_)));
+        }
+        self.symbols.iter().for_each(|(symbol, name)| {
+            entries.insert(symbol.clone(), name.clone());
+        });
+        entries
+            .into_iter()
+            .map(|(symbol, name)| (name, symbol))
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries().is_empty()
+    }
+
+    /// 表に現れるどの生成元とも重ならない番号
+    pub fn next_gen_id(&self) -> usize {
+        self.entries()
+            .into_iter()
+            .filter_map(|(_, symbol)| match symbol {
+                Symbol::Gen(gid) => Some(gid.0 + 1),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// 表に現れるどの変数とも重ならない番号
+    pub fn next_var_id(&self) -> usize {
+        self.entries()
+            .into_iter()
+            .filter_map(|(_, symbol)| match symbol {
+                Symbol::Var(vid) => Some(vid.0 + 1),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+impl FromIterator<(String, Symbol)> for Names {
+    fn from_iter<T: IntoIterator<Item = (String, Symbol)>>(iter: T) -> Self {
+        let mut names = Names::new();
+        iter.into_iter().for_each(|(name, symbol)| names.insert(name, symbol));
+        names
+    }
+}
+
+impl std::fmt::Debug for Names {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.entries()).finish()
+    }
+}
+
+/// 1行に1つ`sort Emp 3`/`func wrk 5`/`var e 0`/`gen e1 2`の形で書き出す
+impl std::fmt::Display for Names {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (name, symbol) in self.entries() {
+            match symbol {
+                Symbol::Type(id) => writeln!(f, "sort {} {}", name, id.0)?,
+                Symbol::Fun(id) => writeln!(f, "func {} {}", name, id.0)?,
+                Symbol::Var(id) => writeln!(f, "var {} {}", name, id.0)?,
+                Symbol::Gen(id) => writeln!(f, "gen {} {}", name, id.0)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Names {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let fields = line.split_whitespace().collect::<Vec<_>>();
+                let [kind, name, id] = fields[..] else {
+                    return Err(format!("invalid names entry: {}", line));
+                };
+                let id = id
+                    .parse::<usize>()
+                    .map_err(|_| format!("invalid id in names entry: {}", line))?;
+                let symbol = match kind {
+                    "sort" => Symbol::Type(TypeId(id)),
+                    "func" => Symbol::Fun(OperId(id)),
+                    "var" => Symbol::Var(VarId(id)),
+                    "gen" => Symbol::Gen(GenId(id)),
+                    _ => return Err(format!("unknown symbol kind in names entry: {}", line)),
+                };
+                Ok((name.to_string(), symbol))
+            })
+            .collect()
+    }
+}
 
 pub struct SymbolTable<Id: AsyncIncremental> {
-    pub table: RefCell<HashMap<String, Id>>,
+    pub table: RefCell<BTreeMap<String, Id>>,
     generator: AsyncIncrement<Id>,
 }
 
@@ -24,14 +251,14 @@ impl<Id: AsyncIncremental + std::fmt::Debug> std::fmt::Debug for SymbolTable<Id>
 impl<Id: AsyncIncremental + Clone> SymbolTable<Id> {
     pub fn new() -> Self {
         SymbolTable::<Id> {
-            table: RefCell::new(HashMap::new()),
+            table: RefCell::new(BTreeMap::new()),
             generator: Id::init(),
         }
     }
 
     pub fn init_with(v: Id) -> Self {
         SymbolTable::<Id> {
-            table: RefCell::new(HashMap::new()),
+            table: RefCell::new(BTreeMap::new()),
             generator: Id::init_with(v),
         }
     }
@@ -51,14 +278,19 @@ impl<Id: AsyncIncremental + Clone> SymbolTable<Id> {
         self.table.borrow_mut().insert(name, id);
     }
 
+    /// 表に名前を登録せずに、新しい番号だけを得る
+    pub fn fresh(&self) -> Id {
+        self.generator.pull()
+    }
+
     pub fn get(&self, name: &str) -> Option<Id> {
         self.table.borrow().get(name).cloned()
     }
 }
 
 impl SymbolTable<OperId> {
-    pub fn current_table(&self) -> HashMap<String, Symbol> {
-        let names = HashMap::new();
+    pub fn current_table(&self) -> Names {
+        let names = Names::new();
         self.table.borrow().iter().fold(names, |mut table, (k, v)| {
             table.insert(k.clone(), Symbol::Fun(v.clone()));
             table
@@ -67,11 +299,91 @@ impl SymbolTable<OperId> {
 }
 
 impl SymbolTable<TypeId> {
-    pub fn current_table(&self) -> HashMap<String, Symbol> {
-        let names = HashMap::new();
+    pub fn current_table(&self) -> Names {
+        let names = Names::new();
         self.table.borrow().iter().fold(names, |mut table, (k, v)| {
             table.insert(k.clone(), Symbol::Type(v.clone()));
             table
         })
     }
-}
\ No newline at end of file
+}
+
+impl SymbolTable<GenId> {
+    pub fn current_table(&self) -> Names {
+        let names = Names::new();
+        self.table.borrow().iter().fold(names, |mut table, (k, v)| {
+            table.insert(k.clone(), Symbol::Gen(v.clone()));
+            table
+        })
+    }
+}
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::id::{GenId, OperId, Symbol, TypeId, VarId};
+    use crate::symbol_table::Names;
+
+    #[test]
+    fn test_names_lookup_both_ways() {
+        let names: Names = vec![
+            ("Emp".to_string(), Symbol::Type(TypeId(1))),
+            ("wrk".to_string(), Symbol::Fun(OperId(2))),
+            ("e".to_string(), Symbol::Var(VarId(0))),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(names.type_id("Emp"), Some(TypeId(1)));
+        assert_eq!(names.oper_id("wrk"), Some(OperId(2)));
+        assert_eq!(names.var_id("e"), Some(VarId(0)));
+        assert_eq!(names.sort_name(&TypeId(1)), Some("Emp"));
+        assert_eq!(names.oper_name(&OperId(2)), Some("wrk"));
+        assert_eq!(names.var_name(&VarId(0)), Some("e"));
+        assert_eq!(names.oper_id("Emp"), None);
+    }
+
+    #[test]
+    fn test_names_gens_are_separate() {
+        let mut names = Names::new();
+        names.insert("wrk".to_string(), Symbol::Fun(OperId(2)));
+        names.insert("wrk".to_string(), Symbol::Gen(GenId(2)));
+        assert_eq!(names.oper_id("wrk"), Some(OperId(2)));
+        assert_eq!(names.gen_id("wrk"), Some(GenId(2)));
+        assert_eq!(names.oper_name(&OperId(2)), Some("wrk"));
+        assert_eq!(names.gen_name(&GenId(2)), Some("wrk"));
+    }
+
+    #[test]
+    fn test_names_scope() {
+        let mut global = Names::new();
+        global.insert("wrk".to_string(), Symbol::Fun(OperId(2)));
+        global.insert("x".to_string(), Symbol::Var(VarId(0)));
+        let global = Rc::new(global);
+
+        let mut local = Names::scope(global.clone());
+        local.insert("e".to_string(), Symbol::Var(VarId(1)));
+        assert_eq!(local.oper_name(&OperId(2)), Some("wrk"));
+        assert_eq!(local.oper_id("wrk"), Some(OperId(2)));
+        assert_eq!(local.var_name(&VarId(1)), Some("e"));
+        assert_eq!(global.var_name(&VarId(0)), Some("x"));
+        // 親の変数は、引いても一覧にしても見えない
+        assert_eq!(local.var_id("x"), None);
+        assert_eq!(local.var_name(&VarId(0)), None);
+        assert_eq!(
+            local.entries(),
+            vec![("wrk".to_string(), Symbol::Fun(OperId(2))), ("e".to_string(), Symbol::Var(VarId(1)))]
+        );
+    }
+
+    #[test]
+    fn test_names_roundtrip() {
+        let mut names = Names::new();
+        names.insert("Emp".to_string(), Symbol::Type(TypeId(1)));
+        names.insert("wrk".to_string(), Symbol::Fun(OperId(2)));
+        names.insert("e".to_string(), Symbol::Var(VarId(0)));
+        let text = names.to_string();
+        assert_eq!(text, "sort Emp 1\nfunc wrk 2\nvar e 0\n");
+        let parsed = text.parse::<Names>().unwrap();
+        assert_eq!(parsed.entries(), names.entries());
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use combine::{attempt, eof, Parser};
+
+use crate::{
+    context::Context,
+    equation::Equation,
+    id::{GenId, TypeId},
+    instance::{Elem, Generator, Instance},
+    literal::Literal,
+    parser::term::terminner::{boolean::boolean_parser, number::number_parser, string::string_parser},
+    r#type::Type,
+    schema::Schema,
+    term::{Term, TermInner},
+    workspace::Workspace,
+};
+
+/// 表の1行。`id`の生成元と、fkey/attrの列の値
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Row {
+    pub id: String,
+    pub cells: Vec<(String, String)>,
+    /// ファイル上の行番号。分からなければ0
+    pub line: usize,
+}
+
+/// entityごとの表。YAML/CSVなどの表形式のファイルとinstanceの間で受け渡す
+pub type Tables = Vec<(String, Vec<Row>)>;
+
+/// 表を読めなかった箇所。entityの表と、分かればその行番号
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TableError {
+    pub entity: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl TableError {
+    fn new(entity: &str, line: usize, message: String) -> Self {
+        TableError { entity: entity.to_string(), line, message }
+    }
+}
+
+impl std::fmt::Display for TableError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.entity.as_str(), self.line) {
+            ("", _) => write!(f, "{}", self.message),
+            (entity, 0) => write!(f, "{}: {}", entity, self.message),
+            (entity, line) => write!(f, "{}:{}: {}", entity, line, self.message),
+        }
+    }
+}
+
+impl Instance {
+    /// 表をschemaのinstanceとして読む
+    /// 行の`id`が`#elem`に、列が`fkey!id = 値`の`#data`になる。
+    /// 値は`?x`ならnull、fkeyの列なら生成元、attrの列ならそのsortのリテラル(`Str`ならクォートは省ける)。
+    /// `bare_nulls`なら、`Str`以外のattrの列でリテラルとして読めない識別子も`?`を省いたnullとみなす。
+    pub fn from_tables(ws: &Workspace, schema: &Schema, tables: &Tables, bare_nulls: bool) -> Result<Instance, TableError> {
+        let mut instance = Instance { schema: schema.clone(), ..Instance::default() };
+        let mut gens: BTreeMap<&str, (GenId, TypeId)> = BTreeMap::new();
+        for (entity, rows) in tables {
+            let tid = ws
+                .types
+                .get(entity)
+                .filter(|tid| schema.entities.contains(&Type::Unary(tid.clone())))
+                .ok_or_else(|| TableError::new(entity, 0, "not an entity of the schema".to_string()))?;
+            for row in rows {
+                let gid = ws.gens.assign(row.id.clone());
+                if gens.insert(&row.id, (gid.clone(), tid.clone())).is_some() {
+                    return Err(TableError::new(entity, row.line, format!("generator '{}' appears more than once", row.id)));
+                }
+                instance.elems.push(Elem::Gen(Generator::new(gid, row.id.clone(), tid.clone())));
+            }
+        }
+
+        let sort_name = |tid: &TypeId| schema.names.sort_name(tid).unwrap_or("?").to_string();
+        let mut data = vec![];
+        for (entity, rows) in tables {
+            for row in rows {
+                let (gid, tid) = &gens[row.id.as_str()];
+                let error = |message: String| TableError::new(entity, row.line, message);
+                for (column, raw) in &row.cells {
+                    let op = ws
+                        .opers
+                        .get(column)
+                        .and_then(|oid| schema.fkeys.iter().chain(&schema.attrs).find(|op| op.id == oid))
+                        .filter(|op| op.dom.as_ref() == &Type::Unary(tid.clone()))
+                        .ok_or_else(|| error(format!("'{}' is not a fkey/attr of {}", column, entity)))?;
+                    let Type::Unary(cod) = op.cod.as_ref() else {
+                        return Err(error(format!("'{}' is not a fkey/attr of {}", column, entity)));
+                    };
+                    let value = if raw.starts_with('?') {
+                        TermInner::Gen(ws.gens.assign(raw.clone()))
+                    } else if schema.entities.contains(&Type::Unary(cod.clone())) {
+                        match gens.get(raw.as_str()) {
+                            Some((gid, entity)) if entity == cod => TermInner::Gen(gid.clone()),
+                            Some((_, entity)) => {
+                                return Err(error(format!(
+                                    "{}!{}: '{}' belongs to {}, not {}",
+                                    column,
+                                    row.id,
+                                    raw,
+                                    sort_name(entity),
+                                    sort_name(cod)
+                                )))
+                            }
+                            None => return Err(error(format!("{}!{}: generator '{}' has no row", column, row.id, raw))),
+                        }
+                    } else if let Some(lit) = read_literal(raw, &sort_name(cod)) {
+                        lit
+                    } else if bare_nulls && is_null_name(raw) {
+                        // `sal: x`のように`?`を省いたnull
+                        TermInner::Gen(ws.gens.assign(format!("?{}", raw)))
+                    } else {
+                        return Err(error(format!("{}!{}: cannot read '{}' as {}", column, row.id, raw, sort_name(cod))));
+                    };
+                    let left = TermInner::Fun(op.id.clone(), vec![Rc::new(TermInner::Gen(gid.clone()))]);
+                    data.push((Rc::new(left), Rc::new(value)));
+                }
+            }
+        }
+
+        let mut names = ws.types.current_table();
+        names.extend(ws.opers.current_table());
+        names.extend(ws.gens.current_table());
+        instance.names = Rc::new(names);
+        instance.data = data
+            .into_iter()
+            .map(|(left, right)| Equation { context: Rc::new(Context::default()), names: instance.names.clone(), left, right })
+            .collect();
+        instance.freshen_gens(&ws.gens);
+        instance.collect_nulls().map_err(|message| TableError::new("", 0, message))?;
+        Ok(instance)
+    }
+
+    /// 生成元ごとにfkey/attrの値を並べた表
+    /// 値は正規形が生成元・null・リテラルになるものに限るので、saturateしたinstanceに使う。値のないセルは書かない。
+    /// `#target`のないブロックのないqueryの結果は、行がどのentityにも属さないので書けない。
+    pub fn to_tables(&self) -> Result<Tables, String> {
+        if self.elems.iter().any(|e| matches!(e, Elem::Subst(_))) {
+            return Err("the rows of a query without #entity blocks need a #target to be exported".to_string());
+        }
+        let rules = self.deduction_rules();
+        let builtins = &self.schema.theory.builtins;
+        let mut tables = vec![];
+        for entity in &self.schema.entities {
+            let Type::Unary(tid) = entity else {
+                continue;
+            };
+            let ops = self
+                .schema
+                .fkeys
+                .iter()
+                .chain(&self.schema.attrs)
+                .filter(|op| op.dom.as_ref() == entity)
+                .collect::<Vec<_>>();
+            let mut rows = vec![];
+            for g in self.generators_of(tid) {
+                let mut cells = vec![];
+                for op in &ops {
+                    let name = self.names.oper_name(&op.id).unwrap_or("?").to_string();
+                    let inner = Rc::new(TermInner::Fun(op.id.clone(), vec![Rc::new(TermInner::Gen(g.id.clone()))]));
+                    let term = Term { context: Rc::new(Context::default()), names: self.names.clone(), inner };
+                    let normal = term.normalize_with(&rules, builtins);
+                    match normal.inner.as_ref() {
+                        TermInner::Gen(gid) => cells.push((name, self.names.gen_name(gid).unwrap_or("?").to_string())),
+                        TermInner::Lit(lit) => cells.push((name, write_literal(lit))),
+                        _ if normal.inner == term.inner => {}
+                        _ => return Err(format!("{}!{} = {} is not a generator, null or literal", name, g.name, normal)),
+                    }
+                }
+                rows.push(Row { id: g.name.clone(), cells, line: 0 });
+            }
+            tables.push((self.names.sort_name(tid).unwrap_or("?").to_string(), rows));
+        }
+
+        Ok(tables)
+    }
+}
+
+/// sortのリテラルとして読む。`Str`はクォートがなければそのまま文字列にする
+fn read_literal(raw: &str, sort: &str) -> Option<TermInner> {
+    let parsed = attempt(string_parser())
+        .or(attempt(boolean_parser()))
+        .or(number_parser())
+        .skip(eof())
+        .parse(raw)
+        .ok()
+        .map(|(t, _)| t);
+    match parsed {
+        Some(TermInner::Lit(lit)) if lit.sort().name() == sort => Some(TermInner::Lit(lit)),
+        _ if sort == "Str" && !raw.starts_with('"') => Some(TermInner::Lit(Literal::Str(raw.to_string()))),
+        _ => None,
+    }
+}
+
+/// `Str`以外のattrの列で、リテラルとして読めない識別子はnullの名前とみなす
+fn is_null_name(raw: &str) -> bool {
+    raw.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && raw.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && raw != "true"
+        && raw != "false"
+}
+
+/// 読み戻すとnullやクォート付きの値、コメントと紛れる文字列だけクォートする
+fn write_literal(lit: &Literal) -> String {
+    match lit {
+        Literal::Str(s)
+            if !s.is_empty()
+                && s.trim() == s
+                && !s.starts_with(['"', '?', '-', '[', '{', '\''])
+                && !s.contains(['#', ':', '\n']) =>
+        {
+            s.clone()
+        }
+        _ => lit.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        instance::Instance,
+        table::{Row, TableError, Tables},
+        workspace::Workspace,
+    };
+
+    fn table(entity: &str, rows: &[(&str, &[(&str, &str)])]) -> Tables {
+        let rows = rows
+            .iter()
+            .enumerate()
+            .map(|(line, (id, cells))| Row {
+                id: id.to_string(),
+                cells: cells.iter().map(|(c, v)| (c.to_string(), v.to_string())).collect(),
+                line: line + 2,
+            })
+            .collect();
+        vec![(entity.to_string(), rows)]
+    }
+
+    #[test]
+    fn test_from_tables_errors() {
+        let ws = Workspace::new("example");
+        let schema = ws.schema("s").unwrap();
+        let ok = table("Emp", &[("e1", &[("last", "Gauss"), ("sal", "?y"), ("mgr", "e1")])]);
+        let instance = Instance::from_tables(&ws, &schema, &ok, true).unwrap();
+        assert_eq!(instance.data.len(), 3);
+        assert_eq!(instance.nulls().count(), 1);
+
+        let bare = table("Emp", &[("e1", &[("sal", "x")]), ("e2", &[("sal", "?x")])]);
+        let instance = Instance::from_tables(&ws, &schema, &bare, true).unwrap();
+        assert_eq!(instance.nulls().map(|g| g.name.clone()).collect::<Vec<_>>(), vec!["?x"]);
+        let error = Instance::from_tables(&ws, &schema, &bare, false).err().unwrap();
+        assert_eq!(error, TableError::new("Emp", 2, "sal!e1: cannot read 'x' as Int".to_string()));
+
+        for bad in [
+            table("Nothing", &[("e1", &[])]),
+            table("Emp", &[("e1", &[]), ("e1", &[])]),
+            table("Emp", &[("e1", &[("name", "HR")])]),
+            table("Emp", &[("e1", &[("sal", "2.5")])]),
+            table("Emp", &[("e1", &[("mgr", "e2")])]),
+        ] {
+            assert!(Instance::from_tables(&ws, &schema, &bad, true).is_err());
+        }
+
+        let bad = table("Emp", &[("e1", &[]), ("e2", &[("sal", "2.5")])]);
+        let error = Instance::from_tables(&ws, &schema, &bad, true).err().unwrap();
+        assert_eq!(error, TableError::new("Emp", 3, "sal!e2: cannot read '2.5' as Int".to_string()));
+        assert_eq!(error.to_string(), "Emp:3: sal!e2: cannot read '2.5' as Int");
+    }
+}
@@ -1,7 +1,7 @@
 use std::rc::Rc;
 
 use crate::{
-    completion::{rule::{RuleId, RuleKind}, subst::Var}, context::Context, id::{OperId, Symbol, VarId}, symbol_table::Names,
+    completion::{rule::{RuleId, RuleKind}, subst::Var}, context::Context, id::{GenId, OperId, VarId}, literal::Literal, symbol_table::Names,
 };
 type Link<T> = std::rc::Rc<T>;
 
@@ -38,29 +38,25 @@ impl Term {
     ) -> std::fmt::Result {
         use TermInner::*;
         match inner.as_ref() {
-            Int(i) => {
-                write!(f, "{}", i)
+            Lit(lit) => {
+                write!(f, "{}", lit)
             }
-            Str(s) => {
-                write!(f, "{:?}", s)
+            Gen(gid) => {
+                if let Some(nm) = self.names.gen_name(gid) {
+                    write!(f, "{}", nm)
+                } else {
+                    write!(f, "g{:?}", gid.0)
+                }
             }
             Var(vid) => {
-                let v = self
-                    .names
-                    .iter()
-                    .find(|(_, sym)| sym == &&Symbol::Var(vid.clone()));
-                if let Some((nm, _)) = v {
+                if let Some(nm) = self.names.var_name(vid) {
                     write!(f, "{}", nm)
                 } else {
                     write!(f, "v{:?}", vid.0)
                 }
             }
             RuledVar(vid, rid, kind) => {
-                let v = self
-                    .names
-                    .iter()
-                    .find(|(_, sym)| sym == &&Symbol::Var(vid.clone()));
-                if let Some((nm, _)) = v {
+                if let Some(nm) = self.names.var_name(vid) {
                     let _ = write!(f, "{}", nm);
                 } else {
                     let _ = write!(f, "v{:?}", vid.0);
@@ -79,11 +75,7 @@ impl Term {
                 }
             }
             Fun(operid, args) => {
-                let v = self
-                    .names
-                    .iter()
-                    .find(|(_, sym)| sym == &&Symbol::Fun(operid.clone()));
-                if let Some((nm, _)) = v {
+                if let Some(nm) = self.names.oper_name(operid) {
                     let _ = write!(f, "{}", nm);
                     match args.len() {
                         0 => {
@@ -137,11 +129,7 @@ impl Term {
                     }
                     match var {
                         crate::completion::subst::Var::Id(vid) => {
-                            let v = self
-                                .names
-                                .iter()
-                                .find(|(_, sym)| sym == &&Symbol::Var(vid.clone()));
-                            if let Some((nm, _)) = v {
+                            if let Some(nm) = self.names.var_name(vid) {
                                 let _ = write!(f, "{} -> ", nm);
                             } else {
                                 let _ = write!(f, "v{:?} -> ", vid.0);
@@ -168,8 +156,10 @@ impl Term {
 pub enum TermInner {
     Var(VarId),
     Fun(OperId, Vec<Link<TermInner>>),
-    Str(String),
-    Int(usize),
+    // sortはリテラルの種類で決まる
+    Lit(Literal),
+    // instanceの生成元
+    Gen(GenId),
 
     RuledVar(VarId, RuleId, RuleKind),
 
@@ -181,8 +171,8 @@ impl std::fmt::Debug for TermInner {
         match self {
             TermInner::Var(id) => write!(f, "Var{:?}", id.0),
             TermInner::Fun(op_id, args) => write!(f, "Fun{:?}{:?}", op_id.0, args),
-            TermInner::Str(s) => write!(f, "Str{:?}", s),
-            TermInner::Int(i) => write!(f, "Int{:?}", i),
+            TermInner::Lit(lit) => write!(f, "Lit{:?}", lit),
+            TermInner::Gen(id) => write!(f, "Gen{:?}", id.0),
 
             TermInner::RuledVar(vid, rid, kind) => {
                 if kind == &RuleKind::NotSet {
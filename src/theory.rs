@@ -1,7 +1,8 @@
 use std::rc::Rc;
 
+use crate::builtin::Builtins;
 use crate::equation::Equation;
-use crate::id::Symbol;
+use crate::external::ExternalFn;
 use crate::oper::Oper;
 use crate::symbol_table::Names;
 use crate::r#type::Type;
@@ -12,6 +13,20 @@ pub struct Theory {
     pub types: Vec<Type>,
     pub opers: Vec<Oper>,
     pub eqs: Vec<Equation>,
+    pub builtins: Builtins,
+}
+
+impl Theory {
+    /// `#func`で宣言した演算子に外部関数を登録する
+    pub fn register_external(&mut self, name: &str, f: Rc<dyn ExternalFn>) -> Result<(), String> {
+        let oid = self
+            .names
+            .oper_id(name)
+            .filter(|oid| self.opers.iter().any(|op| &op.id == oid))
+            .ok_or_else(|| format!("#func {} is not declared in the theory", name))?;
+        self.builtins.register(oid, name, f);
+        Ok(())
+    }
 }
 
 impl std::fmt::Display for Theory {
@@ -21,74 +36,38 @@ impl std::fmt::Display for Theory {
 
         for ty in &self.types {
             if let Type::Unary(typeid) = ty {
-                let tn = self.names.iter()
-                    .find(|(_, sym)| sym == &&Symbol::Type(typeid.clone()));
-                if let Some((nm, _)) = tn {
+                if let Some(nm) = self.names.sort_name(typeid) {
                     writeln!(f, "#sort {}", nm)?;
                 } else {
                     writeln!(f, "{:?}", ty)?;
-                }                
+                }
             }
         }
 
-        writeln!(f, "")?;
+        writeln!(f)?;
 
         for op in &self.opers {
-            let on = self.names.iter()
-                .find(|(_, sym)| sym == &&Symbol::Fun(op.id.clone()));
-            if let Some((nm, _)) = on {
-                let domname = match op.dom.as_ref() {
-                    Type::Unary(tid) => {
-                        let tn = self.names.iter()
-                            .find(|(_, sym)| sym == &&Symbol::Type(tid.clone()));
-                        if let Some((nm, _)) = tn {
-                            nm.to_string()
-                        } else {
-                            format!("{:?}", op.dom)
-                        }
-                    }
-                    Type::Binary(_, t1, t2) => {
-                        let mut names = Vec::new();
-                        for t in [t1, t2] {
-                            match t.as_ref() {
-                                Type::Unary(tid) => {
-                                    let tn = self.names.iter()
-                                        .find(|(_, sym)| sym == &&Symbol::Type(tid.clone()));
-                                    if let Some((nm, _)) = tn {
-                                        names.push(nm.to_string());
-                                    } else {                        
-                                        names.push(format!("{:?}", t));
-                                    }                              
-                                }    
-                                _ => unimplemented!(), // 現在はないパターンなので
-                            }                            
-                        }
-                        format!("({})", names.join(" * "))
-                    }
-                };
+            if let Some(nm) = self.names.oper_name(&op.id) {
+                let domname = self.names.fmt_type(&op.dom);
                 let codname = match op.cod.as_ref() {
-                    Type::Unary(tid) => {
-                        let tn = self.names.iter()
-                            .find(|(_, sym)| sym == &&Symbol::Type(tid.clone()));
-                        if let Some((nm, _)) = tn {
-                            nm.to_string()
-                        } else {
-                            format!("{:?}", op.cod)
-                        }
-                    }
+                    Type::Unary(_) => self.names.fmt_type(&op.cod),
                     _ => unimplemented!(), // 現在はないパターンなので
                 };
-                writeln!(f, "#func {}: {} -> {}", nm, domname, codname)?;
+                write!(f, "#func {}: {} -> {}", nm, domname, codname)?;
+                if let Some(builtin) = self.builtins.ops.get(&op.id) {
+                    write!(f, " #builtin {}", builtin)?;
+                }
+                writeln!(f)?;
             } else {
                 writeln!(f, "{:?}", op)?;
             }
         }
 
-        writeln!(f, "")?;
+        writeln!(f)?;
 
         for eq in &self.eqs {
             writeln!(f, "#rule {}", eq)?;
         }
         Ok(())
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,257 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use crate::{
+    context::Context,
+    id::{GenId, TypeId},
+    instance::{Elem, Generator, Instance},
+    symbol_table::Names,
+    term::{Term, TermInner},
+};
+
+/// 同じschemaのinstanceの間の準同型 `#transform h: i -> j`
+/// 元のinstanceの生成元(nullを含む)を、行き先のinstanceの項へ写す。
+#[derive(Clone, Default)]
+pub struct Transform {
+    pub name: String,
+    /// 元と行き先のinstanceの名前
+    pub source_name: String,
+    pub target_name: String,
+    pub source: Instance,
+    pub target: Instance,
+    pub gens: BTreeMap<GenId, Rc<TermInner>>,
+    /// 元と行き先の生成元、fkey・attrの名前
+    pub names: Rc<Names>,
+}
+
+/// 準同型として成り立たない箇所
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TransformError {
+    /// 元のinstanceの生成元の行き先がない
+    Unmapped { gen: String },
+    /// 写す元が元のinstanceの生成元でない
+    Unknown { gen: String },
+    /// 行き先の項のsortが生成元のentityと合わない
+    IllTyped { gen: String, expected: String, found: String },
+    /// 元の`#data`が行き先で成り立たない
+    Unpreserved { equation: String, left: String, right: String },
+}
+
+impl std::fmt::Display for TransformError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransformError::Unmapped { gen } => write!(f, "#map {}: not mapped", gen),
+            TransformError::Unknown { gen } => write!(f, "#map {}: not a generator of the source instance", gen),
+            TransformError::IllTyped { gen, expected, found } => {
+                write!(f, "#map {}: expected {}, found {}", gen, expected, found)
+            }
+            TransformError::Unpreserved { equation, left, right } => {
+                writeln!(f, "not preserved: {}", equation)?;
+                writeln!(f, "  left:  {}", left)?;
+                write!(f, "  right: {}", right)
+            }
+        }
+    }
+}
+
+impl Transform {
+    /// instanceの恒等写像
+    pub fn identity(name: &str, instance_name: &str, instance: &Instance) -> Transform {
+        let gens = elements(instance)
+            .map(|g| (g.id.clone(), Rc::new(TermInner::Gen(g.id.clone()))))
+            .collect();
+        Transform {
+            name: name.to_string(),
+            source_name: instance_name.to_string(),
+            target_name: instance_name.to_string(),
+            source: instance.clone(),
+            target: instance.clone(),
+            gens,
+            names: instance.names.clone(),
+        }
+    }
+
+    /// 元のinstanceの項の生成元を、行き先の項に置き換える
+    pub fn apply(&self, t: &TermInner) -> Rc<TermInner> {
+        match t {
+            TermInner::Gen(gid) => self.gens.get(gid).cloned().unwrap_or_else(|| Rc::new(t.clone())),
+            TermInner::Fun(oid, args) => Rc::new(TermInner::Fun(oid.clone(), args.iter().map(|arg| self.apply(arg)).collect())),
+            _ => Rc::new(t.clone()),
+        }
+    }
+
+    /// `self`の後に`next`を続けた準同型。名前は`h_k`になる
+    pub fn compose(&self, next: &Transform) -> Result<Transform, String> {
+        if self.target_name != next.source_name {
+            return Err(format!(
+                "Cannot compose {}: {} -> {} with {}: {} -> {}",
+                self.name, self.source_name, self.target_name, next.name, next.source_name, next.target_name
+            ));
+        }
+        let gens = self.gens.iter().map(|(gid, t)| (gid.clone(), next.apply(t))).collect();
+        let mut names = self.names.as_ref().clone();
+        names.extend(next.names.as_ref().clone());
+        Ok(Transform {
+            name: format!("{}_{}", self.name, next.name),
+            source_name: self.source_name.clone(),
+            target_name: next.target_name.clone(),
+            source: self.source.clone(),
+            target: next.target.clone(),
+            gens,
+            names: Rc::new(names),
+        })
+    }
+
+    /// すべての生成元が写され、sortが合い、元の`#data`が行き先で成り立つかを調べる
+    /// `#data`の両辺を写し、行き先のデータとschemaの等式で正規化して比べる。
+    pub fn check(&self) -> Vec<TransformError> {
+        let names = &self.names;
+        let gen_name = |gid: &GenId| names.gen_name(gid).unwrap_or("?").to_string();
+        let sort_name = |tid: &TypeId| names.sort_name(tid).unwrap_or("?").to_string();
+        let mut errors = vec![];
+
+        for gid in self.gens.keys() {
+            if !elements(&self.source).any(|g| &g.id == gid) {
+                errors.push(TransformError::Unknown { gen: gen_name(gid) });
+            }
+        }
+        for g in elements(&self.source) {
+            let Some(image) = self.gens.get(&g.id) else {
+                errors.push(TransformError::Unmapped { gen: g.name.clone() });
+                continue;
+            };
+            match self.target.term_sort(image) {
+                Some(found) if found != g.entity => errors.push(TransformError::IllTyped {
+                    gen: g.name.clone(),
+                    expected: sort_name(&g.entity),
+                    found: sort_name(&found),
+                }),
+                _ => {}
+            }
+        }
+        // 写していない・型の合わない生成元があると、`#data`の両辺を行き先で正規化できない
+        if !errors.is_empty() {
+            return errors;
+        }
+
+        let rules = self.target.deduction_rules();
+        let normalize = |t: &Rc<TermInner>| {
+            Term {
+                context: Rc::new(Context::default()),
+                names: names.clone(),
+                inner: self.apply(t),
+            }
+            .normalize_with(&rules, &self.target.schema.theory.builtins)
+        };
+        for eq in &self.source.data {
+            let (left, right) = (normalize(&eq.left), normalize(&eq.right));
+            if left.inner != right.inner {
+                errors.push(TransformError::Unpreserved {
+                    equation: format!("{} = {}", eq.left_term(), eq.right_term()),
+                    left: left.to_string(),
+                    right: right.to_string(),
+                });
+            }
+        }
+        errors
+    }
+}
+
+/// 生成元とnull。写す対象になる
+pub(crate) fn elements(instance: &Instance) -> impl Iterator<Item = &Generator> + '_ {
+    instance.elems.iter().filter_map(|e| match e {
+        Elem::Gen(g) | Elem::Null(g) => Some(g),
+        Elem::Subst(_) => None,
+    })
+}
+
+impl std::fmt::Display for Transform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "#transform {}: {} -> {}", self.name, self.source_name, self.target_name)?;
+        // 元のinstanceの宣言順に並べる
+        for g in elements(&self.source) {
+            let Some(image) = self.gens.get(&g.id) else {
+                continue;
+            };
+            let image = Term {
+                context: Rc::new(Context::default()),
+                names: self.names.clone(),
+                inner: image.clone(),
+            };
+            writeln!(f, "#map {} -> {}", g.name, image)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use combine::{eof, EasyParser, Parser};
+
+    use crate::{
+        parser::transform::transform_parser,
+        term::TermInner,
+        transform::{Transform, TransformError},
+        workspace::Workspace,
+    };
+
+    #[test]
+    fn test_check_transform() {
+        let ws = Workspace::new("example");
+        let update = ws.transform("update").unwrap();
+        assert!(update.check().is_empty());
+        let i = ws.instance("i").unwrap();
+        assert!(Transform::identity("id", "i", &i).check().is_empty());
+    }
+
+    #[test]
+    fn test_check_transform_errors() {
+        let ws = Workspace::new("example");
+        let mut update = ws.transform("update").unwrap();
+        let e2 = update.source.names.gen_id("e2").unwrap();
+        let e1 = update.target.names.gen_id("e1").unwrap();
+        // e2をe1に写すと、e2の名前や所属が保たれない
+        update.gens.insert(e2, Rc::new(TermInner::Gen(e1)));
+        let errors = update.check();
+        assert!(errors.contains(&TransformError::Unpreserved {
+            equation: "last!e2 = \"Noether\"".to_string(),
+            left: "\"Gauss\"".to_string(),
+            right: "\"Noether\"".to_string(),
+        }));
+
+        let input = "#transform bad: nulls -> i2\n#map e1 -> d1\n#map ?x -> 180\n";
+        let (bad, _) = transform_parser(&ws).skip(eof()).easy_parse(input).unwrap();
+        let errors = bad.check();
+        assert!(errors.contains(&TransformError::IllTyped {
+            gen: "e1".to_string(),
+            expected: "Emp".to_string(),
+            found: "Dept".to_string(),
+        }));
+        assert!(errors.contains(&TransformError::Unmapped { gen: "e2".to_string() }));
+    }
+
+    #[test]
+    fn test_compose_transform() {
+        let ws = Workspace::new("example");
+        let update = ws.transform("update").unwrap();
+        let i2 = ws.instance("i2").unwrap();
+        let composed = update.compose(&Transform::identity("id", "i2", &i2)).unwrap();
+        assert_eq!(composed.name, "update_id");
+        assert_eq!(composed.gens, update.gens);
+        assert!(composed.check().is_empty());
+        assert!(composed.compose(&update).is_err());
+    }
+
+    #[test]
+    fn test_print_transform() {
+        let ws = Workspace::new("example");
+        let update = ws.transform("update").unwrap();
+        let printed = update.to_string();
+        assert!(printed.starts_with("#transform update: nulls -> i2\n#map e1 -> e1\n"));
+        assert!(printed.contains("#map ?x -> 180\n"));
+        let (parsed, _) = transform_parser(&ws).skip(eof()).easy_parse(printed.as_str()).unwrap();
+        assert_eq!(parsed.gens, update.gens);
+    }
+}
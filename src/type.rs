@@ -15,3 +15,13 @@ impl std::fmt::Debug for Type {
         }
     }
 }
+
+impl Type {
+    /// 積`A * B * C`を左から並べたsort(演算子の引数ごとのsort)
+    pub fn components(&self) -> Vec<TypeId> {
+        match self {
+            Type::Unary(id) => vec![id.clone()],
+            Type::Binary(_, t1, t2) => [t1.components(), t2.components()].concat(),
+        }
+    }
+}
@@ -87,3 +87,31 @@ pub fn dispv<T: std::fmt::Display>(title: &str, vec: &Vec<T>) {
     });
     println!("]");
 }
+
+/// instanceを名前で調べるテスト用の関数
+#[cfg(test)]
+pub mod instance {
+    use std::collections::BTreeSet;
+
+    use crate::instance::Instance;
+
+    /// entityの生成元の名前
+    pub fn gen_names(instance: &Instance, entity: &str) -> Vec<String> {
+        let tid = instance.names.type_id(entity).unwrap();
+        instance.generators_of(&tid).map(|g| g.name.clone()).collect()
+    }
+
+    /// 左辺が`left`と表示される`#data`の右辺
+    pub fn value(instance: &Instance, left: &str) -> Option<String> {
+        instance
+            .data
+            .iter()
+            .find(|eq| eq.left_term().to_string() == left)
+            .map(|eq| eq.right_term().to_string())
+    }
+
+    /// `#data`を`左辺 = 右辺`の文字列の集合にしたもの。別のworkspaceや形式から読んだinstanceと比べるのに使う
+    pub fn data(instance: &Instance) -> BTreeSet<String> {
+        instance.data.iter().map(|eq| format!("{} = {}", eq.left_term(), eq.right_term())).collect()
+    }
+}
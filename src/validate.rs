@@ -0,0 +1,308 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::rc::Rc;
+
+use crate::{
+    equation::Equation,
+    id::GenId,
+    instance::{collect_gens, Elem, Generator, Instance},
+    oper::Oper,
+    r#type::Type,
+    term::TermInner,
+};
+
+/// `Instance::validate`が見つけた問題
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Issue {
+    /// 生成元にfkey/attrの値がない
+    Missing { elem: String, oper: String },
+    /// 左辺が`fkey!elem`/`attr!elem`の形でない
+    Malformed { data: String, reason: String },
+    /// 右辺のsortがfkey/attrの値域と合わない
+    IllTyped { data: String, expected: String, found: String },
+    /// 同じ`#data`が複数回書かれている
+    Duplicate { data: String },
+    /// 同じ左辺に異なる値が与えられている
+    Conflict { left: String, first: String, second: String },
+    /// `#elem`で宣言されていない生成元を参照している。lineは`#data`の行で、分からなければ0
+    Undeclared { name: String, data: String, line: usize },
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Issue::Missing { elem, oper } => write!(f, "missing: {}!{} has no value", oper, elem),
+            Issue::Malformed { data, reason } => write!(f, "malformed: #data {}: {}", data, reason),
+            Issue::IllTyped { data, expected, found } => {
+                write!(f, "ill-typed: #data {}: expected {}, found {}", data, expected, found)
+            }
+            Issue::Duplicate { data } => write!(f, "duplicate: #data {}", data),
+            Issue::Conflict { left, first, second } => {
+                write!(f, "conflict: {} = {} and {} = {}", left, first, left, second)
+            }
+            Issue::Undeclared { name, data, line: 0 } => {
+                write!(f, "undeclared: generator {} in #data {}", name, data)
+            }
+            Issue::Undeclared { name, data, line } => {
+                write!(f, "undeclared: generator {} in #data {} (line {})", name, data, line)
+            }
+        }
+    }
+}
+
+impl Instance {
+    /// 宣言と`#data`の整合性を調べ、見つかった問題を宣言順に返す
+    pub fn validate(&self) -> Vec<Issue> {
+        let mut issues = vec![];
+        let generators = self
+            .elems
+            .iter()
+            .filter_map(|e| match e {
+                // nullのentityはtypesideのsortなので、値がないとは報告されない
+                Elem::Gen(g) | Elem::Null(g) => Some((g.id.clone(), g)),
+                _ => None,
+            })
+            .collect::<BTreeMap<GenId, &Generator>>();
+
+        // 左辺ごとに最初に現れた右辺
+        let mut values: BTreeMap<Rc<TermInner>, &Equation> = BTreeMap::new();
+        for eq in &self.data {
+            let data = format!("{} = {}", eq.left_term(), eq.right_term());
+
+            let mut gens = vec![];
+            collect_gens(&eq.left, &mut gens);
+            collect_gens(&eq.right, &mut gens);
+            // 書かれた順のまま、同じ生成元は1度だけ報告する
+            let mut seen = BTreeSet::new();
+            gens.retain(|gid| seen.insert(gid.clone()));
+            let line = self.lines.get(&(eq.left.clone(), eq.right.clone())).copied().unwrap_or(0);
+            let undeclared = gens
+                .into_iter()
+                .filter(|gid| !generators.contains_key(gid))
+                .map(|gid| Issue::Undeclared {
+                    name: self.gen_name(&gid),
+                    data: data.clone(),
+                    line,
+                })
+                .collect::<Vec<_>>();
+            if !undeclared.is_empty() {
+                issues.extend(undeclared);
+                continue;
+            }
+
+            let op = match self.check_left(&eq.left, &generators) {
+                Ok(op) => op,
+                Err(reason) => {
+                    issues.push(Issue::Malformed { data, reason });
+                    continue;
+                }
+            };
+            let expected = self.names.fmt_type(&op.cod);
+            if let Some(found) = self.sort_name_of(&eq.right, &generators) {
+                if found != expected {
+                    issues.push(Issue::IllTyped { data, expected, found });
+                    continue;
+                }
+            }
+
+            match values.get(&eq.left) {
+                Some(prev) if prev.right == eq.right => issues.push(Issue::Duplicate { data }),
+                Some(prev) => issues.push(Issue::Conflict {
+                    left: eq.left_term().to_string(),
+                    first: prev.right_term().to_string(),
+                    second: eq.right_term().to_string(),
+                }),
+                None => {
+                    values.insert(eq.left.clone(), eq);
+                }
+            }
+        }
+
+        for g in generators.values() {
+            let entity = Type::Unary(g.entity.clone());
+            for op in self.schema.fkeys.iter().chain(&self.schema.attrs) {
+                if op.dom.as_ref() != &entity {
+                    continue;
+                }
+                let left = TermInner::Fun(op.id.clone(), vec![Rc::new(TermInner::Gen(g.id.clone()))]);
+                if !values.contains_key(&left) {
+                    issues.push(Issue::Missing {
+                        elem: g.name.clone(),
+                        oper: self.oper_name(op),
+                    });
+                }
+            }
+        }
+
+        issues
+    }
+
+    /// 左辺が`fkey!elem`/`attr!elem`で、elemがfkey/attrの定義域に属するか
+    fn check_left(&self, left: &TermInner, generators: &BTreeMap<GenId, &Generator>) -> Result<&Oper, String> {
+        let TermInner::Fun(operid, args) = left else {
+            return Err("left side must be fkey!elem or attr!elem".to_string());
+        };
+        let op = self
+            .schema
+            .fkeys
+            .iter()
+            .chain(&self.schema.attrs)
+            .find(|op| &op.id == operid)
+            .ok_or_else(|| "left side must be fkey!elem or attr!elem".to_string())?;
+        let [arg] = args.as_slice() else {
+            return Err(format!("{} takes exactly one generator", self.oper_name(op)));
+        };
+        let TermInner::Gen(gid) = arg.as_ref() else {
+            return Err(format!("argument of {} must be a generator", self.oper_name(op)));
+        };
+        let g = generators[gid];
+        if op.dom.as_ref() != &Type::Unary(g.entity.clone()) {
+            return Err(format!(
+                "{} is a {}, but {} expects {}",
+                g.name,
+                self.names.fmt_type(&Type::Unary(g.entity.clone())),
+                self.oper_name(op),
+                self.names.fmt_type(&op.dom)
+            ));
+        }
+        Ok(op)
+    }
+
+    /// 項のsortの名前。リテラルは種類で決まり、演算子は値域で決まる
+    fn sort_name_of(&self, t: &TermInner, generators: &BTreeMap<GenId, &Generator>) -> Option<String> {
+        match t {
+            TermInner::Lit(lit) => Some(lit.sort().name().to_string()),
+            TermInner::Gen(gid) => Some(self.names.fmt_type(&Type::Unary(generators.get(gid)?.entity.clone()))),
+            TermInner::Fun(oid, _) => self
+                .schema
+                .fkeys
+                .iter()
+                .chain(&self.schema.attrs)
+                .chain(&self.schema.theory.opers)
+                .find(|op| &op.id == oid)
+                .map(|op| self.names.fmt_type(&op.cod)),
+            _ => None,
+        }
+    }
+
+    fn oper_name(&self, op: &Oper) -> String {
+        self.names
+            .oper_name(&op.id)
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("{:?}", op.id))
+    }
+
+    fn gen_name(&self, gid: &GenId) -> String {
+        self.names
+            .gen_name(gid)
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("{:?}", gid))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use combine::{eof, stream::position::Stream, EasyParser, Parser};
+
+    use crate::{instance::Instance, parser::instance::instance_parser, validate::Issue, workspace::Workspace};
+
+    fn parse(ws: &Workspace, input: &str) -> Instance {
+        instance_parser(ws)
+            .skip(eof())
+            .easy_parse(Stream::new(input))
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn test_validate_example() {
+        let ws = Workspace::new("example");
+        let issues = ws.instance("i").unwrap().validate();
+        let missing = ["mgr", "wrk", "last", "sal"]
+            .iter()
+            .map(|oper| Issue::Missing { elem: "e7".to_string(), oper: oper.to_string() })
+            .collect::<Vec<_>>();
+        assert_eq!(issues, missing);
+    }
+
+    #[test]
+    fn test_validate_conflict_and_duplicate() {
+        let ws = Workspace::new("example");
+        let input = "#schema s\n#elem e1: Emp\n#elem d2 d3: Dept\n\
+            #data wrk!e1 = d3\n#data wrk!e1 = d3\n#data wrk!e1 = d2\n";
+        let issues = parse(&ws, input).validate();
+        assert!(issues.contains(&Issue::Duplicate { data: "wrk!e1 = d3".to_string() }));
+        assert!(issues.contains(&Issue::Conflict {
+            left: "wrk!e1".to_string(),
+            first: "d3".to_string(),
+            second: "d2".to_string(),
+        }));
+    }
+
+    #[test]
+    fn test_validate_malformed() {
+        let ws = Workspace::new("example");
+        let input = "#schema s\n#elem e1: Emp\n#elem d1: Dept\n\
+            #data wrk!wrk!e1 = d1\n#data wrk!d1 = d1\n#data d1 = wrk!e1\n";
+        let issues = parse(&ws, input).validate();
+        let malformed = issues
+            .iter()
+            .filter(|issue| matches!(issue, Issue::Malformed { .. }))
+            .count();
+        assert_eq!(malformed, 3);
+    }
+
+    #[test]
+    fn test_validate_ill_typed() {
+        let ws = Workspace::new("example");
+        let input = "#schema s\n#elem e1: Emp\n#elem d1: Dept\n\
+            #data sal!e1 = \"Gauss\"\n#data last!e1 = \"Gauss\"\n#data mgr!e1 = d1\n#data wrk!e1 = d1\n";
+        let issues = parse(&ws, input).validate();
+        let ill_typed = issues
+            .iter()
+            .filter(|issue| matches!(issue, Issue::IllTyped { .. }))
+            .collect::<Vec<_>>();
+        assert_eq!(ill_typed.len(), 2);
+        assert_eq!(
+            ill_typed[0],
+            &Issue::IllTyped {
+                data: "sal!e1 = \"Gauss\"".to_string(),
+                expected: "Int".to_string(),
+                found: "Str".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_validate_undeclared() {
+        let ws = Workspace::new("example");
+        // d1はiで宣言されているが、このinstanceでは宣言されていない
+        ws.instance("i").unwrap();
+        let input = "#schema s\n#elem x: Emp\n#data wrk!x = d1\n";
+        let issues = parse(&ws, input).validate();
+        assert!(issues.contains(&Issue::Undeclared {
+            name: "d1".to_string(),
+            data: "wrk!x = d1".to_string(),
+            line: 3,
+        }));
+    }
+
+    #[test]
+    fn test_validate_undeclared_anywhere() {
+        let ws = Workspace::new("example");
+        // どのinstanceでも宣言されていない生成元も、読めたうえで報告する
+        let input = "#schema s\n#elem x: Emp\n\n#data mgr!x = y\n#data wrk!y = mgr!y\n";
+        let issues = parse(&ws, input).validate();
+        let undeclared = issues
+            .iter()
+            .filter(|issue| matches!(issue, Issue::Undeclared { .. }))
+            .map(|issue| issue.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            undeclared,
+            vec![
+                "undeclared: generator y in #data mgr!x = y (line 4)",
+                "undeclared: generator y in #data wrk!y = mgr!y (line 5)",
+            ]
+        );
+    }
+}
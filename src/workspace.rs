@@ -0,0 +1,209 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use combine::{eof, stream::position, EasyParser, Parser};
+
+use crate::context_table::CtxtTable;
+use crate::eval::Query;
+use crate::external::ExternalFn;
+use crate::id::{GenId, OperId, TypeId};
+use crate::instance::Instance;
+use crate::mapping::Mapping;
+use crate::transform::Transform;
+use crate::parser::instance::instance_parser;
+use crate::parser::mapping::mapping_parser;
+use crate::parser::query::query_parser;
+use crate::parser::schema::schema_parser;
+use crate::parser::transform::transform_parser;
+use crate::parser::theory::theory_parser;
+use crate::schema::Schema;
+use crate::symbol_table::SymbolTable;
+use crate::theory::Theory;
+
+/// 読み込んだtheory/schema/instance/queryで共有する記号表
+/// 同じ名前は、どのファイルから参照しても同じIDになる。
+/// ただしinstanceの生成元は、読み込んだ後でinstanceごとの新しいIDに付け替える(`Instance::freshen_gens`)。
+/// 一度読み込んだファイルは名前ごとに保持し、再度パースしない。
+pub struct Workspace {
+    root: PathBuf,
+    pub types: SymbolTable<TypeId>,
+    pub opers: SymbolTable<OperId>,
+    pub gens: SymbolTable<GenId>,
+    pub ctxts: CtxtTable,
+    theories: RefCell<BTreeMap<String, Theory>>,
+    schemas: RefCell<BTreeMap<String, Schema>>,
+    instances: RefCell<BTreeMap<String, Instance>>,
+    externals: RefCell<BTreeMap<String, Rc<dyn ExternalFn>>>,
+}
+
+impl Workspace {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Workspace {
+            root: root.into(),
+            types: SymbolTable::<TypeId>::new(),
+            opers: SymbolTable::<OperId>::new(),
+            gens: SymbolTable::<GenId>::new(),
+            ctxts: CtxtTable::new(),
+            theories: RefCell::new(BTreeMap::new()),
+            schemas: RefCell::new(BTreeMap::new()),
+            instances: RefCell::new(BTreeMap::new()),
+            externals: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    /// これから読み込むtheoryのうち、`#func name`を宣言しているものに外部関数を登録する
+    /// 既に読み込んだschema/instanceには反映されないので、読み込む前に登録すること。
+    pub fn register_external(&self, name: &str, f: impl ExternalFn + 'static) {
+        self.externals.borrow_mut().insert(name.to_string(), Rc::new(f));
+    }
+
+    /// `{root}/{kind}/{name}.{kind}`
+    pub fn path(&self, kind: &str, name: &str) -> PathBuf {
+        self.root.join(kind).join(format!("{}.{}", name, kind))
+    }
+
+    fn read(&self, kind: &str, name: &str) -> Result<String, String> {
+        let path = self.path(kind, name);
+        std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {} file: {}: {}", kind, path.display(), e))
+    }
+
+    fn parse_error(&self, kind: &str, name: &str, err: impl std::fmt::Display) -> String {
+        format!(
+            "Failed to parse {} from file: {}\n{}",
+            kind,
+            self.path(kind, name).display(),
+            err
+        )
+    }
+
+    pub fn theory(&self, name: &str) -> Result<Theory, String> {
+        if let Some(theory) = self.theories.borrow().get(name) {
+            return Ok(theory.clone());
+        }
+        let src = self.read("theory", name)?;
+        let mut theory = theory_parser::<combine::easy::Stream<&str>>(&self.types, &self.opers, &self.ctxts)
+            .skip(eof())
+            .easy_parse(src.as_ref())
+            .map_err(|e| self.parse_error("theory", name, e))?
+            .0;
+        for (func, f) in self.externals.borrow().iter() {
+            // 宣言していないtheoryには登録しない
+            let _ = theory.register_external(func, f.clone());
+        }
+        self.theories.borrow_mut().insert(name.to_string(), theory.clone());
+        Ok(theory)
+    }
+
+    pub fn schema(&self, name: &str) -> Result<Schema, String> {
+        if let Some(schema) = self.schemas.borrow().get(name) {
+            return Ok(schema.clone());
+        }
+        let src = self.read("schema", name)?;
+        let schema = schema_parser::<combine::easy::Stream<&str>>(self)
+            .skip(eof())
+            .easy_parse(src.as_ref())
+            .map_err(|e| self.parse_error("schema", name, e))?
+            .0;
+        self.schemas.borrow_mut().insert(name.to_string(), schema.clone());
+        Ok(schema)
+    }
+
+    pub fn instance(&self, name: &str) -> Result<Instance, String> {
+        if let Some(instance) = self.instances.borrow().get(name) {
+            return Ok(instance.clone());
+        }
+        let src = self.read("instance", name)?;
+        let instance = instance_parser(self)
+            .skip(eof())
+            .easy_parse(position::Stream::new(src.as_str()))
+            .map_err(|e| self.parse_error("instance", name, e))?
+            .0;
+        self.instances.borrow_mut().insert(name.to_string(), instance.clone());
+        Ok(instance)
+    }
+
+    /// queryを読み込み、`#instance`で指定されたinstanceと組にして返す
+    pub fn query(&self, name: &str) -> Result<(Instance, Query), String> {
+        let src = self.read("query", name)?;
+        let query = query_parser::<combine::easy::Stream<&str>>(self)
+            .skip(eof())
+            .easy_parse(src.as_ref())
+            .map_err(|e| self.parse_error("query", name, e))?
+            .0;
+        let instance_name = query.instance.clone().ok_or_else(|| {
+            format!(
+                "Query {} does not declare its #instance",
+                self.path("query", name).display()
+            )
+        })?;
+        let instance = self.instance(&instance_name)?;
+        Ok((instance, query))
+    }
+
+    /// `{root}/mapping/{name}.mapping`を読み込む
+    pub fn mapping(&self, name: &str) -> Result<Mapping, String> {
+        let src = self.read("mapping", name)?;
+        let mapping = mapping_parser::<combine::easy::Stream<&str>>(self)
+            .skip(eof())
+            .easy_parse(src.as_ref())
+            .map_err(|e| self.parse_error("mapping", name, e))?
+            .0;
+        Ok(mapping)
+    }
+
+    /// `{root}/transform/{name}.transform`を読み込む
+    pub fn transform(&self, name: &str) -> Result<Transform, String> {
+        let src = self.read("transform", name)?;
+        let transform = transform_parser::<combine::easy::Stream<&str>>(self)
+            .skip(eof())
+            .easy_parse(src.as_ref())
+            .map_err(|e| self.parse_error("transform", name, e))?
+            .0;
+        Ok(transform)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::workspace::Workspace;
+
+    #[test]
+    fn test_query_shares_ids_with_instance() {
+        let ws = Workspace::new("example");
+        let (instance, query) = ws.query("_").unwrap();
+        let name = instance.names.oper_id("name").unwrap();
+        assert_eq!(query.entities[0].ret[0].0, name);
+        assert_eq!(ws.opers.get("name"), Some(name));
+    }
+
+    #[test]
+    fn test_instance_is_loaded_once() {
+        let ws = Workspace::new("example");
+        let first = ws.instance("i").unwrap();
+        let second = ws.instance("i").unwrap();
+        assert!(std::rc::Rc::ptr_eq(&first.names, &second.names));
+    }
+
+    #[test]
+    fn test_query_unknown_name() {
+        use combine::{eof, EasyParser, Parser};
+
+        use crate::parser::query::query_parser;
+
+        let ws = Workspace::new("example");
+        let input = "#instance i\n#for e: Emp\n#where wrk!e = d9\n";
+        let result = query_parser::<combine::easy::Stream<&str>>(&ws)
+            .skip(eof())
+            .easy_parse(input);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let ws = Workspace::new("example");
+        assert!(ws.schema("nothing").is_err());
+    }
+}
@@ -0,0 +1,87 @@
+use combine::EasyParser;
+
+use crate::{instance::Instance, parser::yaml::yaml_parser, schema::Schema, workspace::Workspace};
+
+impl Instance {
+    /// entityごとに行を並べたYAML(`example/instance/i.yaml`)を、schemaのinstanceとして読む
+    /// `sal: x`のように`?`を省いたnullも読む。
+    pub fn from_yaml(ws: &Workspace, schema: &Schema, src: &str) -> Result<Instance, String> {
+        let (tables, _) = yaml_parser()
+            .easy_parse(combine::stream::position::Stream::new(src))
+            .map_err(|e| format!("Failed to parse YAML\n{}", e))?;
+        Instance::from_tables(ws, schema, &tables, true).map_err(|e| e.to_string())
+    }
+
+    /// `from_yaml`で読み戻せるYAML。saturateしたinstanceに使う
+    pub fn to_yaml(&self) -> Result<String, String> {
+        let mut yaml = String::new();
+        for (entity, rows) in self.to_tables()? {
+            if rows.is_empty() {
+                yaml += &format!("{}: []\n", entity);
+                continue;
+            }
+            yaml += &format!("{}:\n", entity);
+            for row in rows {
+                yaml += &format!("  - id: {}\n", row.id);
+                for (column, value) in row.cells {
+                    yaml += &format!("    {}: {}\n", column, value);
+                }
+            }
+        }
+        Ok(yaml)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{instance::Instance, util::instance::data, workspace::Workspace};
+
+    #[test]
+    fn test_from_yaml() {
+        let ws = Workspace::new("example");
+        let i = ws.instance("i").unwrap();
+        let src = std::fs::read_to_string("example/instance/i.yaml").unwrap();
+        let imported = Instance::from_yaml(&ws, &i.schema, &src).unwrap();
+        assert_eq!(imported.generators().count(), i.generators().count());
+        assert_eq!(imported.nulls().map(|g| g.name.clone()).collect::<Vec<_>>(), vec!["?x"]);
+        let data = data(&imported);
+        assert!(data.contains("sal!e7 = ?x"));
+        assert!(data.contains("last!e7 = \"Hypatia\""));
+        assert!(data.contains("last!e3 = \"EinStein\""));
+
+        // e2の上司e3は部署も違い、給料も低い。書き換えずにconstraintの違反として報告する
+        let violations = imported
+            .check_constraints()
+            .iter()
+            .map(|v| format!("{} = {}", v.equation.left_term(), v.equation.right_term()))
+            .collect::<Vec<_>>();
+        assert_eq!(violations, vec!["wrk!mgr!e2 = wrk!e2", "st![sal!e2 sal!mgr!e2] = true;"]);
+    }
+
+    #[test]
+    fn test_to_yaml() {
+        let ws = Workspace::new("example");
+        let i = ws.instance("i").unwrap().saturate();
+        let yaml = i.to_yaml().unwrap();
+        assert!(yaml.starts_with("Emp:\n  - id: e1\n    mgr: e1\n    wrk: d3\n    last: Gauss\n    sal: 250\n"));
+        assert!(yaml.contains("  - id: e7\n    mgr: e7\n    wrk: d2\nDept:\n"));
+
+        // constraintから導いたe7の値も書くので、読み戻すと元のデータより増える
+        let exported = Instance::from_yaml(&ws, &i.schema, &yaml).unwrap();
+        let added = data(&exported).difference(&data(&i)).cloned().collect::<Vec<_>>();
+        assert_eq!(added, ["mgr!e7 = e7", "wrk!e7 = d2"]);
+
+        // ブロックのないqueryの結果の行は、`#target`のentityの生成元として書く
+        let (instance, query) = ws.query("_").unwrap();
+        let yaml = crate::eval::eval(instance, query).unwrap().saturate().to_yaml().unwrap();
+        assert!(yaml.starts_with("Row:\n  - id: Row[e -> e2]\n    boss: Row[e -> e4]\n    name: Noether\n"));
+        assert!(yaml.ends_with("  - id: Row[e -> e7]\n    boss: Row[e -> e7]\n"));
+        let result = crate::eval::eval(ws.instance("i").unwrap(), ws.query("_").unwrap().1).unwrap().saturate();
+        let exported = Instance::from_yaml(&ws, &result.schema, &yaml).unwrap();
+        assert_eq!(data(&exported), data(&result));
+
+        let hr = ws.instance("hr").unwrap().saturate();
+        let exported = Instance::from_yaml(&ws, &hr.schema, &hr.to_yaml().unwrap()).unwrap();
+        assert_eq!(data(&exported), data(&hr));
+    }
+}